@@ -0,0 +1,217 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use lean_sys::*;
+use pb_rust::ffitest::lean_helpers::*;
+use pb_rust::networktest::rb_native;
+use pb_rust::networktest::rb_protocol::{lean, Message, Packet};
+use pb_rust::networktest::simulator::node_address;
+
+// micro benchmarks for the lean FFI: the marshalling primitives in `lean_helpers`,
+// converting messages to and from lean, and a single `handle_packet` call
+// (against the native port, for scale).
+//
+// cargo bench --bench ffi
+
+const STRING_LENGTHS: [usize; 3] = [8, 256, 16 * 1024];
+const ARRAY_LENGTHS: [usize; 3] = [4, 31, 1024];
+
+fn string_of(len: usize) -> String {
+    "x".repeat(len)
+}
+
+fn strings(c: &mut Criterion) {
+    lean::initialize();
+    let mut group = c.benchmark_group("strings");
+    for len in STRING_LENGTHS {
+        group.bench_with_input(
+            BenchmarkId::new("rust_string_to_lean", len),
+            &len,
+            |b, &len| {
+                b.iter_batched(
+                    || string_of(len),
+                    |s| unsafe { lean_dec(rust_string_to_lean(s)) },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("lean_string_to_rust", len),
+            &len,
+            |b, &len| {
+                let s = unsafe { rust_string_to_lean(string_of(len)) };
+                b.iter(|| unsafe { black_box(lean_string_to_rust(s, Mode::Borrow)) });
+                unsafe { lean_dec(s) };
+            },
+        );
+    }
+    group.finish();
+}
+
+fn arrays(c: &mut Criterion) {
+    lean::initialize();
+    let mut group = c.benchmark_group("arrays");
+    for len in ARRAY_LENGTHS {
+        group.bench_with_input(
+            BenchmarkId::new("rust_usize_vec_to_lean_array", len),
+            &len,
+            |b, &len| {
+                b.iter_batched(
+                    || (0..len).collect::<Vec<usize>>(),
+                    |vec| unsafe { lean_dec(rust_usize_vec_to_lean_array(vec)) },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("index_lean_array", len),
+            &len,
+            |b, &len| {
+                let arr = unsafe { rust_usize_vec_to_lean_array((0..len).collect()) };
+                b.iter(|| unsafe { black_box(index_lean_array(arr, black_box(len / 2))) });
+                unsafe { lean_dec(arr) };
+            },
+        );
+        // e.g. the node list handed to `create_protocol`
+        group.bench_with_input(
+            BenchmarkId::new("rust_string_vec_to_lean_array", len),
+            &len,
+            |b, &len| {
+                b.iter_batched(
+                    || (0..len).map(node_address).collect::<Vec<String>>(),
+                    |vec| unsafe { lean_dec(rust_string_vec_to_lean_array(vec)) },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("lean_string_array_to_rust", len),
+            &len,
+            |b, &len| {
+                let arr =
+                    unsafe { rust_string_vec_to_lean_array((0..len).map(node_address).collect()) };
+                b.iter(|| unsafe { black_box(lean_string_array_to_rust(arr)) });
+                unsafe { lean_dec(arr) };
+            },
+        );
+    }
+    group.finish();
+}
+
+fn messages(c: &mut Criterion) {
+    lean::initialize();
+    let mut group = c.benchmark_group("messages");
+    for len in STRING_LENGTHS {
+        let message = Message::VoteMsg {
+            originator: node_address(0),
+            r: 7,
+            v: string_of(len),
+        };
+        group.bench_with_input(BenchmarkId::new("to_lean", len), &message, |b, message| {
+            b.iter_batched(
+                || message.clone(),
+                |message| unsafe { lean_dec(message.to_lean()) },
+                BatchSize::SmallInput,
+            )
+        });
+        // `from_lean` borrows the lean message, so the same one can be converted over and over.
+        group.bench_with_input(
+            BenchmarkId::new("from_lean", len),
+            &message,
+            |b, message| {
+                let message_lean = unsafe { message.clone().to_lean() };
+                b.iter(|| unsafe { black_box(Message::from_lean(message_lean)) });
+                unsafe { lean_dec(message_lean) };
+            },
+        );
+    }
+    group.finish();
+}
+
+// a lean node that gets released once criterion is done with it
+struct Node(lean::Protocol);
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        unsafe {
+            lean_dec(self.0.protocol);
+            lean_dec(self.0.node_state);
+        }
+    }
+}
+
+// node 1, which has already seen the leader's proposal, and an echo for it from node 2.
+// (the first echo it gets doesn't reach any threshold, so this is the common case.)
+fn echo_setup(nodes: usize) -> (Vec<String>, Packet, Packet) {
+    let addresses: Vec<String> = (0..nodes).map(node_address).collect();
+    let msg = |originator: &str| Message::EchoMsg {
+        originator: originator.to_string(),
+        r: 0,
+        v: String::from("value"),
+    };
+    let proposal = Packet {
+        src: addresses[0].clone(),
+        dst: addresses[1].clone(),
+        msg: Message::InitialMsg {
+            r: 0,
+            v: String::from("value"),
+        },
+        consumed: false,
+    };
+    let echo = Packet {
+        src: addresses[2].clone(),
+        dst: addresses[1].clone(),
+        msg: msg(&addresses[0]),
+        consumed: false,
+    };
+    (addresses, proposal, echo)
+}
+
+fn handle_packet(c: &mut Criterion) {
+    lean::initialize();
+    let mut group = c.benchmark_group("handle_packet");
+    for nodes in [4, 7, 16, 31] {
+        let (addresses, proposal, echo) = echo_setup(nodes);
+
+        // a fresh node every iteration, so that lean can update its state in place,
+        // like it does in the driver.
+        group.bench_with_input(BenchmarkId::new("lean", nodes), &nodes, |b, _| {
+            b.iter_batched(
+                || unsafe {
+                    let mut node = lean::Protocol::create(
+                        addresses.clone(),
+                        addresses[1].clone(),
+                        addresses[0].clone(),
+                    );
+                    node.handle_packet(proposal.clone());
+                    (Node(node), echo.clone())
+                },
+                |(mut node, echo)| {
+                    let packets = unsafe { node.0.handle_packet(echo) };
+                    (node, packets)
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("native", nodes), &nodes, |b, _| {
+            b.iter_batched(
+                || {
+                    let mut node = rb_native::Protocol::create(
+                        addresses.clone(),
+                        addresses[1].clone(),
+                        addresses[0].clone(),
+                    );
+                    node.handle_packet(proposal.clone());
+                    (node, echo.clone())
+                },
+                |(mut node, echo)| {
+                    let packets = node.handle_packet(echo);
+                    (node, packets)
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, strings, arrays, messages, handle_packet);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pb_rust::networktest::simulator::{simulate, SimConfig};
+
+// end-to-end RB rounds in the simulator: the leader broadcasts one value,
+// and the run ends once every node has delivered it and the network is quiet.
+// that's O(N^2) packets through `handle_packet`, so this is mostly a measure of the protocol
+// (and of marshalling, with lean), not of the network.
+//
+// the nodes run the lean protocol, or the native port when built with `--no-default-features`.
+//
+// cargo bench --bench rounds
+
+fn rounds(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulated_round");
+    // the larger clusters take a while per round
+    group.sample_size(10);
+    for nodes in [4, 7, 16, 31] {
+        let config = SimConfig {
+            nodes,
+            rounds: 1,
+            duplicate_probability: 0.0,
+            ..SimConfig::default()
+        };
+        // every node broadcasts an echo and a vote to every node, after the leader's proposal.
+        group.throughput(Throughput::Elements((nodes + 2 * nodes * nodes) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(nodes), &config, |b, config| {
+            b.iter(|| {
+                let report = simulate(config.clone());
+                assert!(report.is_ok(), "{report}");
+                report
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, rounds);
+criterion_main!(benches);
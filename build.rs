@@ -7,6 +7,11 @@ const LEAN_LIB_DIR: &str = "lib";
 const LEAN_BUILD_DIR: &str = "lib/.lake/build/lib";
 
 fn main() {
+    // nothing to build or link without the lean protocol.
+    if env::var_os("CARGO_FEATURE_LEAN").is_none() {
+        return;
+    }
+
     let cwd = env::current_dir().expect("Failed to get current directory");
 
     env::set_current_dir(LEAN_LIB_DIR)
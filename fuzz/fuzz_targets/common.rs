@@ -0,0 +1,51 @@
+use arbitrary::Arbitrary;
+use pb_rust::networktest::rb_protocol::{Message, Packet};
+
+// `Arbitrary` mirrors of the protocol types, so that the main crate doesn't have to derive it.
+
+#[derive(Arbitrary, Debug)]
+pub enum FuzzMessage {
+    InitialMsg {
+        r: usize,
+        v: String,
+    },
+    EchoMsg {
+        originator: String,
+        r: usize,
+        v: String,
+    },
+    VoteMsg {
+        originator: String,
+        r: usize,
+        v: String,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct FuzzPacket {
+    pub src: String,
+    pub dst: String,
+    pub msg: FuzzMessage,
+    pub consumed: bool,
+}
+
+impl From<FuzzMessage> for Message {
+    fn from(msg: FuzzMessage) -> Self {
+        match msg {
+            FuzzMessage::InitialMsg { r, v } => Message::InitialMsg { r, v },
+            FuzzMessage::EchoMsg { originator, r, v } => Message::EchoMsg { originator, r, v },
+            FuzzMessage::VoteMsg { originator, r, v } => Message::VoteMsg { originator, r, v },
+        }
+    }
+}
+
+impl From<FuzzPacket> for Packet {
+    fn from(packet: FuzzPacket) -> Self {
+        Packet {
+            src: packet.src,
+            dst: packet.dst,
+            msg: packet.msg.into(),
+            consumed: packet.consumed,
+        }
+    }
+}
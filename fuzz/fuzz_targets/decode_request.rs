@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pb_rust::networktest::rb_protocol::RBRequest;
+
+// every request a node receives is decoded from untrusted CBOR, by libp2p's `request_response::cbor`
+// codec (which is just `cbor4ii`). decoding must never panic, and whatever decodes must
+// survive being encoded and decoded again unchanged.
+fuzz_target!(|data: &[u8]| {
+    let Ok(request) = cbor4ii::serde::from_slice::<RBRequest>(data) else {
+        return;
+    };
+
+    let bytes = cbor4ii::serde::to_vec(Vec::new(), &request).expect("requests should encode");
+    let decoded: RBRequest =
+        cbor4ii::serde::from_slice(&bytes).expect("re-encoded requests should decode");
+    assert_eq!(decoded, request);
+});
@@ -0,0 +1,88 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use pb_rust::networktest::rb_native;
+use pb_rust::networktest::rb_protocol::{lean, Message, Packet};
+use pb_rust::networktest::simulator::node_address;
+
+// feeds an arbitrary sequence of packets to a single node, through the exported `handle_message`.
+// the packets are drawn from a small set of addresses, rounds and values, so that they actually
+// add up to echo and vote thresholds instead of all being ignored.
+//
+// the node must not crash, and must behave exactly like the native port (see `differential.rs`)
+// after every packet.
+
+const ROUNDS: usize = 4;
+
+#[derive(Arbitrary, Debug)]
+enum Kind {
+    Initial,
+    Echo,
+    Vote,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Step {
+    src: u8,
+    kind: Kind,
+    originator: u8,
+    round: u8,
+    value: u8,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    nodes: u8,
+    // the node under test, which is also the leader if `leader_is_us`
+    us: u8,
+    leader_is_us: bool,
+    steps: Vec<Step>,
+}
+
+fuzz_target!(|input: Input| {
+    lean::initialize();
+
+    let nodes = (input.nodes % 7) as usize + 1;
+    let addresses: Vec<String> = (0..nodes).map(node_address).collect();
+    let address = |i: u8| addresses[i as usize % nodes].clone();
+    let us = address(input.us);
+    let leader = if input.leader_is_us {
+        us.clone()
+    } else {
+        address(input.us.wrapping_add(1))
+    };
+
+    let mut node = unsafe { lean::Protocol::create(addresses.clone(), us.clone(), leader.clone()) };
+    let mut native = rb_native::Protocol::create(addresses.clone(), us.clone(), leader);
+
+    for step in input.steps {
+        let r = step.round as usize % ROUNDS;
+        let v = format!("v{}", step.value % 3);
+        let originator = address(step.originator);
+        let msg = match step.kind {
+            Kind::Initial => Message::InitialMsg { r, v },
+            Kind::Echo => Message::EchoMsg { originator, r, v },
+            Kind::Vote => Message::VoteMsg { originator, r, v },
+        };
+        let packet = Packet {
+            src: address(step.src),
+            dst: us.clone(),
+            msg,
+            consumed: false,
+        };
+
+        let sent = unsafe { node.handle_packet(packet.clone()) };
+        assert_eq!(sent, native.handle_packet(packet));
+        for round in 0..ROUNDS {
+            let output = unsafe { node.check_output(round) };
+            assert_eq!(
+                output,
+                native.check_output(round),
+                "output for round {round}"
+            );
+        }
+    }
+
+    unsafe { node.release() };
+});
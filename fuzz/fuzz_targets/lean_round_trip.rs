@@ -0,0 +1,17 @@
+#![no_main]
+
+mod common;
+
+use common::FuzzPacket;
+use libfuzzer_sys::fuzz_target;
+use pb_rust::networktest::rb_protocol::{lean, Packet};
+
+// any packet (i.e. anything a peer can send us) must come back unchanged
+// from a trip through lean: `to_lean` (`create_packet`/`create_message`), then `from_lean`.
+fuzz_target!(|packet: FuzzPacket| {
+    lean::initialize();
+
+    let packet: Packet = packet.into();
+    let round_tripped = unsafe { Packet::from_lean(packet.clone().to_lean()) };
+    assert_eq!(round_tripped, packet);
+});
@@ -0,0 +1,62 @@
+import LeanSts.State
+import LeanSts.BFT.Network
+import ConsistentBroadcast
+
+-- lean-rust interfacing for consistent broadcast
+-- ===
+-- same idea as `Protocol.lean`: a concrete instantiation of `CB.CBProtocol`, and exports with the
+-- same shape as RB's (`create_protocol`, `send_message`, `handle_message`, `check_output`).
+-- every export is prefixed with `cb_`, since RB, PB and CB all end up in the same fat library
+-- (`Protocol.lean` imports this module).
+
+@[reducible] def CBAddress := String
+@[reducible] def CBRound := USize
+@[reducible] def CBValue := String
+
+abbrev ConcreteCBMessage := (@CB.Message CBAddress CBRound CBValue)
+abbrev ConcreteCBState := (@CB.NodeState CBAddress CBRound CBValue)
+abbrev ConcreteCBPacket := (Packet CBAddress ConcreteCBMessage)
+abbrev ConcreteCBInternalTransition := @CB.InternalTransition CBRound
+abbrev ConcreteCBProtocol := @NetworkProtocol CBAddress ConcreteCBMessage ConcreteCBState ConcreteCBInternalTransition
+
+-- calls rust for the value the leader broadcasts (see `src/networktest/cb_protocol.rs`).
+-- the address is borrowed.
+@[extern "cb_get_node_value"]
+opaque cb_get_node_value : @& CBAddress → CBValue
+
+@[export cb_create_protocol]
+def cb_create_protocol (node_arr: Array CBAddress) : ConcreteCBProtocol :=
+  let node_list := Array.toList node_arr
+  @CB.CBProtocol CBAddress CBRound CBValue String.decEq USize.decEq String.decEq (node_list) (cb_get_node_value)
+
+-- one export per constructor, like PB's.
+@[export cb_create_initial]
+def cb_create_initial (r: CBRound) (v: CBValue) : ConcreteCBMessage :=
+  CB.Message.InitialMsg r v
+
+@[export cb_create_echo]
+def cb_create_echo (originator: CBAddress) (r: CBRound) (v: CBValue) : ConcreteCBMessage :=
+  CB.Message.EchoMsg originator r v
+
+@[export cb_create_packet]
+def cb_create_packet (src: CBAddress) (dst: CBAddress) (msg: ConcreteCBMessage) (consumed: Bool) : ConcreteCBPacket :=
+  {src := src, dst := dst, msg := msg, consumed := consumed}
+
+@[export cb_init_node_state]
+def cb_init_node_state (p: ConcreteCBProtocol) (node_address: CBAddress) : ConcreteCBState :=
+  p.localInit node_address
+
+@[export cb_send_message]
+def cb_send_message (p: ConcreteCBProtocol) (node_state: ConcreteCBState) (round: CBRound) : ConcreteCBState × Array ConcreteCBPacket :=
+  let (new_state, packet_list) := p.procInternal node_state round
+  (new_state, List.toArray packet_list)
+
+@[export cb_handle_message]
+def cb_handle_message (p: ConcreteCBProtocol) (node_state: ConcreteCBState) (src: CBAddress) (msg: ConcreteCBMessage) : ConcreteCBState × Array ConcreteCBPacket :=
+  let (new_state, packet_list) := p.procMessage node_state src msg
+  (new_state, List.toArray packet_list)
+
+-- every value output for `leader`'s broadcast in `round`. empty until there's an output.
+@[export cb_check_output]
+def cb_check_output (node_state: ConcreteCBState) (leader: CBAddress) (round: CBRound) : Array CBValue :=
+  List.toArray (node_state.output (leader, round))
@@ -0,0 +1,142 @@
+import LeanSts.State
+import LeanSts.BFT.Network
+
+-- consistent broadcast (CB): the echo phase of Bracha's RB, without the vote phase.
+-- https://decentralizedthoughts.github.io/2020-09-19-living-with-asynchrony-brachas-reliable-broadcast/
+--
+-- the leader sends its value to everyone, everyone echoes the first value they get from it,
+-- and a node outputs a value once ⌈(N + f + 1) / 2⌉ nodes have echoed it.
+-- any two such quorums share an honest node, so no two honest nodes output different values,
+-- but unlike RB, there's no totality: if a byzantine leader only gets some honest nodes to
+-- output, the others never will. in exchange, it takes one round of all-to-all messages instead of two.
+--
+-- this follows `ReliableBroadcast.lean` step for step, and everything is in the `CB` namespace,
+-- since `Protocol.lean` imports both (see `CBProtocol.lean`).
+
+namespace CB
+
+section ConsistentBroadcast
+variable {Address Round Value : Type}
+variable [dec_addr : DecidableEq Address] [dec_round : DecidableEq Round] [dec_value : DecidableEq Value]
+
+def InternalTransition := Round
+
+inductive Message
+  | InitialMsg (r : Round) (v : Value)
+  /-- The `originator` is the leader, i.e. the party that initiates the broadcast.
+    It is NOT the sender of the message. -/
+  | EchoMsg (originator : Address) (r : Round) (v : Value)
+deriving DecidableEq
+
+structure NodeState :=
+  /-- This node's address -/
+  id : Address
+  /-- The set of all nodes -/
+  allNodes : List Address
+
+  sent : Round → Bool
+  echoed : (Address × Round) → Option Value
+  msgReceivedFrom : (@Message Address Round Value) → List Address
+  output : (Address × Round) → List Value
+
+def CBNetworkState := @AsynchronousNetwork.World Address (Packet Address (@Message Address Round Value)) (@NodeState Address Round Value)
+instance CBAdversary
+  (f : ℕ)
+  (nodes : {ns : List Address // List.Nodup ns ∧ 0 < List.length ns ∧ f < List.length ns})
+  (isByz : {isC : Address → Bool // List.length (List.filter isC nodes.val) ≤ f})
+  :
+  @NonadaptiveByzantineAdversary Address (Packet Address (@Message Address Round Value)) (@NetworkState Address (Packet Address (@Message Address Round Value)) (@NodeState Address Round Value)) where
+  setting := {
+    N := List.length nodes.val,
+    f := f,
+    nodes := ⟨(Multiset.ofList nodes.val), by aesop⟩
+
+    N_gt_0 := by aesop
+    f_lt_N := by aesop
+    N_nodes := by aesop
+  }
+  /- Unforgeable channels assumption, as in RB. -/
+  constraint := ⟨(λ pkt _ => isByz.val pkt.src)⟩
+  isByzantine := isByz
+  byz_lte_f := by { dsimp [Finset.filter] ; aesop }
+
+
+def initLocalState (id : Address) (nodes : List Address) : @NodeState Address Round Value := {
+  id := id
+  allNodes := nodes
+  sent := λ _ => false
+  echoed := λ _ => none
+  msgReceivedFrom := λ _ => []
+  output := λ _ => []
+}
+
+def procInt (inputValue : Address → Value) (st : @NodeState Address Round Value) (r : @InternalTransition Round) :
+  (@NodeState Address Round Value) × List (Packet Address (@Message Address Round Value)) :=
+  if st.sent r then
+    (st, [])
+  else
+    let st' := { st with sent := st.sent[r ↦ true] };
+    let msg := Message.InitialMsg r (inputValue st.id);
+    let pkts := Packet.broadcast st.id st.allNodes msg
+    (st', pkts)
+
+/-- Internal message handler for Consistent Broadcast. Returns `none` if nothing to do. -/
+def handleMessage (st : @NodeState Address Round Value) (src : Address) (msg : @Message Address Round Value) :
+  Option ((@NodeState Address Round Value) × List (Packet Address (@Message Address Round Value))) :=
+  match msg with
+  | Message.InitialMsg r v =>
+    if let .none := st.echoed (src, r) then
+      let st' := {st with echoed := st.echoed[(src, r) ↦ some v]};
+      let msg := Message.EchoMsg src r v;
+      let pkts := Packet.broadcast st.id st.allNodes msg
+      (st', pkts)
+    else none
+  | Message.EchoMsg _ _ _ =>
+    let alreadyReceived := st.msgReceivedFrom msg;
+    if src ∈ alreadyReceived then
+      none
+    else
+      let msgReceivedFrom' := st.msgReceivedFrom[msg ↦ src :: alreadyReceived]
+      let st' := {st with msgReceivedFrom := msgReceivedFrom'}
+      .some (st', [])
+
+local notation "CBMessage" => (@Message Address Round Value)
+local notation "CBState" => (@NodeState Address Round Value)
+local notation "CBPacket" => (Packet Address CBMessage)
+
+def numNodes (st : CBState) : ℕ := st.allNodes.length
+
+def byzThres (st : CBState) : ℕ := (numNodes st - 1) / 3
+
+/-- ⌈(N + f + 1) / 2⌉ -/
+def thresEcho4Output (st : CBState) := (numNodes st + byzThres st + 2) / 2
+
+def tryUpdateOutputByMessage (st : CBState) (msg : CBMessage) : CBState :=
+  if let Message.EchoMsg q r v := msg then
+    if thresEcho4Output st ≤ List.length (st.msgReceivedFrom msg) then
+      let l := st.output (q, r)
+      {st with output := st.output[(q, r) ↦ l.insert v]}
+    else
+      st
+  else
+    st
+
+def procMsg (st : @NodeState Address Round Value) (src : Address) (msg : @Message Address Round Value) :
+  (@NodeState Address Round Value) × List (Packet Address (@Message Address Round Value)) :=
+  match handleMessage st src msg with
+  | some (st', pkts) =>
+    match msg with
+    | Message.InitialMsg _ _ =>
+      (st', pkts)
+    | _ =>
+      (tryUpdateOutputByMessage st' msg, pkts)
+  | none =>
+      (st, [])
+
+instance CBProtocol (nodes : List Address) (inputValue : Address → Value) :
+  @NetworkProtocol Address (@Message Address Round Value) (@NodeState Address Round Value) (@InternalTransition Round) :=
+  ⟨λ id => initLocalState id nodes, procInt inputValue, procMsg⟩
+
+end ConsistentBroadcast
+
+end CB
@@ -0,0 +1,91 @@
+import Lean.Data.Json
+import LeanSts.BFT.Network
+
+-- a JSON boundary for any lean-sts `NetworkProtocol`.
+-- ===
+-- every protocol bound so far (`Protocol.lean`, `PBProtocol.lean`) needs its own exports, and rust
+-- code that knows how lean lays out its constructors. instead, this puts a protocol behind a `Node`
+-- that only speaks JSON: addresses, messages, internal transitions and outputs go in and out as
+-- JSON, using their `ToJson`/`FromJson` instances. the node's state never crosses over,
+-- so it can be anything (e.g. made of functions, like RB's).
+--
+-- protocols are registered by name in `JsonProtocols.lean`, which has the exports rust calls.
+-- on the rust side, `src/networktest/json_bridge.rs` turns them back into typed protocols with serde.
+
+open Lean
+
+-- rust's serde sends `usize`s as plain numbers.
+instance : ToJson USize := ⟨λ n => toJson n.toNat⟩
+instance : FromJson USize := ⟨λ j => do
+  let n : Nat ← fromJson? j
+  pure n.toUSize⟩
+
+namespace JsonBridge
+
+/-- A node behind the bridge. Its state is hidden behind functions that speak JSON. -/
+structure Node where
+  {State : Type}
+  state : State
+  /-- `procInternal st transition`: the new state, and the packets to send as a JSON array. -/
+  procInternal : State → Json → Except String (State × Json)
+  /-- `procMessage st src msg` -/
+  procMessage : State → Json → Json → Except String (State × Json)
+  /-- everything the node has output so far, as a JSON array, oldest first. -/
+  outputs : State → Json
+
+/-- A node that doesn't do anything, for when a node couldn't be created. -/
+def Node.inert : Node := {
+  state := ()
+  procInternal := λ _ _ => throw "this node was never created"
+  procMessage := λ _ _ _ => throw "this node was never created"
+  outputs := λ _ => Json.arr #[]
+}
+
+section
+variable {Address Message State InternalTransition Output : Type}
+variable [ToJson Address] [FromJson Address] [ToJson Message] [FromJson Message]
+variable [FromJson InternalTransition] [ToJson Output]
+
+-- the same shape as serde's encoding of `network_protocol::Packet` on the rust side.
+def packetToJson (p : Packet Address Message) : Json :=
+  Json.mkObj [
+    ("src", toJson p.src),
+    ("dst", toJson p.dst),
+    ("msg", toJson p.msg),
+    ("consumed", toJson p.consumed)
+  ]
+
+def packetsToJson (ps : List (Packet Address Message)) : Json :=
+  Json.arr (ps.map packetToJson).toArray
+
+/-- Puts node `me` of protocol `p` behind the bridge.
+  `outputs` lists what a node has output so far, since `NetworkProtocol` has no notion of output. -/
+def Node.ofProtocol (p : NetworkProtocol Address Message State InternalTransition)
+  (outputs : State → List Output) (me : Address) : Node := {
+  state := p.localInit me
+  procInternal := λ st transition => do
+    let transition : InternalTransition ← fromJson? transition
+    let (st', packets) := p.procInternal st transition
+    pure (st', packetsToJson packets)
+  procMessage := λ st src msg => do
+    let src : Address ← fromJson? src
+    let msg : Message ← fromJson? msg
+    let (st', packets) := p.procMessage st src msg
+    pure (st', packetsToJson packets)
+  outputs := λ st => toJson (outputs st)
+}
+end
+
+/-- What every export returns besides the node: `{"ok": ...}`, or `{"error": "..."}`. -/
+def result (r : Except String Json) : String :=
+  match r with
+  | .ok j => (Json.mkObj [("ok", j)]).compress
+  | .error e => (Json.mkObj [("error", toJson e)]).compress
+
+/-- Runs one step on the node. On an error, the node is left as it was. -/
+def Node.step (node : Node) (f : node.State → Except String (node.State × Json)) : Node × String :=
+  match f node.state with
+  | .ok (st, j) => ({ node with state := st }, result (.ok j))
+  | .error e => (node, result (.error e))
+
+end JsonBridge
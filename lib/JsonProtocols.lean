@@ -0,0 +1,104 @@
+import Lean.Data.Json
+import LeanSts.BFT.Network
+import ReliableBroadcast
+import JsonBridge
+
+-- the protocols behind the JSON bridge (see `JsonBridge.lean`), and the exports rust calls.
+-- ===
+-- to plug in a new protocol, give its messages `ToJson`/`FromJson`, add it to `protocols` below,
+-- and implement `JsonProtocol` for it in rust. these exports stay the same, so there's no new
+-- unsafe rust to write.
+--
+-- every export is prefixed with `json_`, since this ends up in the same fat library as RB and PB.
+
+open Lean JsonBridge
+
+deriving instance ToJson, FromJson for Message
+
+-- RB
+-- ===
+-- the same RB as `Protocol.lean`'s, with two differences that fit it to the bridge:
+-- the leader's value comes with the internal transition (instead of through `get_node_value`),
+-- and the state keeps a log of deliveries, since `NodeState.output` can't be listed.
+
+@[reducible] def JsonRBAddress := String
+@[reducible] def JsonRBRound := USize
+@[reducible] def JsonRBValue := String
+
+abbrev JsonRBMessage := (@Message JsonRBAddress JsonRBRound JsonRBValue)
+abbrev JsonRBPacket := (Packet JsonRBAddress JsonRBMessage)
+
+structure JsonRBDelivery where
+  leader : JsonRBAddress
+  round : JsonRBRound
+  value : JsonRBValue
+deriving ToJson
+
+abbrev JsonRBState := (@NodeState JsonRBAddress JsonRBRound JsonRBValue) × List JsonRBDelivery
+
+-- whose broadcast, in which round, a message is about.
+def rbBroadcastOf (src : JsonRBAddress) : JsonRBMessage → JsonRBAddress × JsonRBRound
+  | Message.InitialMsg r _ => (src, r)
+  | Message.EchoMsg originator r _ => (originator, r)
+  | Message.VoteMsg originator r _ => (originator, r)
+
+def rbProcMsg (s : JsonRBState) (src : JsonRBAddress) (msg : JsonRBMessage) : JsonRBState × List JsonRBPacket :=
+  let (st, delivered) := s
+  let (st', packets) := procMsg st src msg
+  let (leader, r) := rbBroadcastOf src msg
+  let delivered' :=
+    if delivered.any (λ d => d.leader == leader && d.round == r) then delivered
+    else match st'.output (leader, r) with
+      | [] => delivered
+      | v :: _ => delivered ++ [{ leader := leader, round := r, value := v }]
+  ((st', delivered'), packets)
+
+instance JsonRBProtocol (nodes : List JsonRBAddress) :
+  @NetworkProtocol JsonRBAddress JsonRBMessage JsonRBState (JsonRBRound × JsonRBValue) :=
+  ⟨λ id => (initLocalState id nodes, []),
+   λ (st, delivered) (r, v) =>
+     let (st', packets) := procInt (λ _ => v) st r
+     ((st', delivered), packets),
+   rbProcMsg⟩
+
+def rbNode (members me : Json) : Except String Node := do
+  let nodes : List JsonRBAddress ← fromJson? members
+  let me : JsonRBAddress ← fromJson? me
+  pure (Node.ofProtocol (JsonRBProtocol nodes) (λ (_, delivered) => delivered) me)
+
+-- the registry
+-- ===
+
+/-- Every protocol behind the bridge, by name. Each one makes a node from the members and its
+  own address (both JSON). -/
+def protocols : List (String × (Json → Json → Except String Node)) := [
+  ("rb", rbNode)
+]
+
+-- exports
+-- ===
+-- they all take strings (JSON, apart from the protocol's name) and return strings (see `result`).
+
+@[export json_create]
+def json_create (name : String) (members : String) (me : String) : Node × String :=
+  let created : Except String Node := do
+    let members ← Json.parse members
+    let me ← Json.parse me
+    match protocols.lookup name with
+    | some create => create members me
+    | none => throw s!"unknown protocol: {name}"
+  match created with
+  | .ok node => (node, result (.ok Json.null))
+  | .error e => (Node.inert, result (.error e))
+
+@[export json_proc_internal]
+def json_proc_internal (node : Node) (transition : String) : Node × String :=
+  node.step (λ st => do node.procInternal st (← Json.parse transition))
+
+@[export json_proc_message]
+def json_proc_message (node : Node) (src : String) (msg : String) : Node × String :=
+  node.step (λ st => do node.procMessage st (← Json.parse src) (← Json.parse msg))
+
+@[export json_outputs]
+def json_outputs (node : Node) : String :=
+  (node.outputs node.state).compress
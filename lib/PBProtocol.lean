@@ -0,0 +1,95 @@
+import LeanSts.State
+import LeanSts.BFT.Network
+import ProvableBroadcast
+
+-- lean-rust interfacing for provable broadcast
+-- ===
+-- same idea as `Protocol.lean`: a concrete instantiation of `PB.PBProtocol`, and exports with the
+-- same shape as RB's (`create_protocol`, `send_message`, `handle_message`, `check_output`).
+-- every export is prefixed with `pb_`, since both protocols end up in the same fat library
+-- (`Protocol.lean` imports this module).
+
+@[reducible] def PBAddress := String
+@[reducible] def PBRound := USize
+@[reducible] def PBValue := String
+@[reducible] def PBProof := String
+-- signatures are opaque to lean, so rust passes them around as (hex) strings.
+@[reducible] def PBSignature := String
+-- lean doesn't combine the partial signatures: the output is the (signer, signature) pairs,
+-- and rust combines them with whatever signature scheme it's using.
+@[reducible] def PBCombined := List (PBAddress × PBSignature)
+
+abbrev ConcretePBMessage := (@PB.Message PBRound PBValue PBProof PBSignature)
+abbrev ConcretePBState := (@PB.NodeState PBAddress PBRound PBValue PBProof PBSignature PBCombined)
+abbrev ConcretePBPacket := (Packet PBAddress ConcretePBMessage)
+abbrev ConcretePBInternalTransition := @PB.InternalTransition PBRound
+abbrev ConcretePBProtocol := @NetworkProtocol PBAddress ConcretePBMessage ConcretePBState ConcretePBInternalTransition
+abbrev ConcretePBParams := PB.Params PBAddress PBRound PBValue PBProof PBSignature PBCombined
+
+-- the params call back into rust (see `src/networktest/pb_protocol.rs`).
+-- the first argument is always the node doing the work, so that rust can find its keys.
+-- all of them borrow their arguments.
+-- the value comes with its proof, so both are picked in one call.
+@[extern "pb_get_node_value_proof"]
+opaque pb_get_node_value_proof : @& PBAddress → PBRound → PBValue × PBProof
+
+-- (me, sender, round, value, proof)
+@[extern "pb_externally_validate"]
+opaque pb_externally_validate : @& PBAddress → @& PBAddress → PBRound → @& PBValue → @& PBProof → Bool
+
+-- (me, sender, round, value)
+@[extern "pb_partially_sign"]
+opaque pb_partially_sign : @& PBAddress → @& PBAddress → PBRound → @& PBValue → PBSignature
+
+-- (me, signer, sender, round, value, signature)
+@[extern "pb_partially_validate"]
+opaque pb_partially_validate : @& PBAddress → @& PBAddress → @& PBAddress → PBRound → @& PBValue → @& PBSignature → Bool
+
+def pb_params (me : PBAddress) : ConcretePBParams := {
+  valueBFT := pb_get_node_value_proof
+  externallyValidate := pb_externally_validate me
+  partiallySign := pb_partially_sign me
+  partiallyValidate := pb_partially_validate me
+  combine := id
+}
+
+-- unlike RB, the protocol is specific to one node, since its params sign with that node's key.
+@[export pb_create_protocol]
+def pb_create_protocol (node_arr: Array PBAddress) (me: PBAddress) : ConcretePBProtocol :=
+  let node_list := Array.toList node_arr
+  @PB.PBProtocol PBAddress PBRound PBValue PBProof PBSignature PBCombined String.decEq USize.decEq (node_list) (pb_params me)
+
+-- one export per constructor, so that (unlike `create_message`) there are no invalid tags.
+@[export pb_create_init]
+def pb_create_init (r: PBRound) (v: PBValue) (p: PBProof) : ConcretePBMessage :=
+  PB.Message.InitMsg r v p
+
+@[export pb_create_echo]
+def pb_create_echo (r: PBRound) (ps: PBSignature) : ConcretePBMessage :=
+  PB.Message.EchoMsg r ps
+
+@[export pb_create_packet]
+def pb_create_packet (src: PBAddress) (dst: PBAddress) (msg: ConcretePBMessage) (consumed: Bool) : ConcretePBPacket :=
+  {src := src, dst := dst, msg := msg, consumed := consumed}
+
+@[export pb_init_node_state]
+def pb_init_node_state (p: ConcretePBProtocol) (node_address: PBAddress) : ConcretePBState :=
+  p.localInit node_address
+
+@[export pb_send_message]
+def pb_send_message (p: ConcretePBProtocol) (node_state: ConcretePBState) (round: PBRound) : ConcretePBState × Array ConcretePBPacket :=
+  let (new_state, packet_list) := p.procInternal node_state round
+  (new_state, List.toArray packet_list)
+
+@[export pb_handle_message]
+def pb_handle_message (p: ConcretePBProtocol) (node_state: ConcretePBState) (src: PBAddress) (msg: ConcretePBMessage) : ConcretePBState × Array ConcretePBPacket :=
+  let (new_state, packet_list) := p.procMessage node_state src msg
+  (new_state, List.toArray packet_list)
+
+-- the signers and their signatures, in the order they arrived.
+-- empty when there's no output yet (an output always has at least one signature).
+@[export pb_check_output]
+def pb_check_output (node_state: ConcretePBState) (round: PBRound) : Array PBAddress × Array PBSignature :=
+  match node_state.output round with
+  | none => (#[], #[])
+  | some sigs => (List.toArray (sigs.map Prod.fst), List.toArray (sigs.map Prod.snd))
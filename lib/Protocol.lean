@@ -1,6 +1,11 @@
 import LeanSts.State
 import LeanSts.BFT.Network
 import ReliableBroadcast
+-- not used here, but the fat library is built from this module's imports,
+-- so this is what links provable and consistent broadcast's exports (and the JSON bridge's) into it too.
+import PBProtocol
+import CBProtocol
+import JsonProtocols
 
 -- lean-rust interfacing
 -- ===
@@ -79,3 +84,30 @@ def check_output (node_state: ConcreteRBState) (leader: ConcreteAddress) (round:
   match out with
   | [] => ConcreteValueOption.None
   | o :: _ => ConcreteValueOption.Some o
+
+-- read-only views of a node's state, so that rust can fingerprint it (e.g. in the model checker).
+-- `NodeState` is mostly made of functions, so it can't be marshalled as a whole.
+-- instead, rust asks about every key it cares about.
+@[export node_sent]
+def node_sent (node_state: ConcreteRBState) (round: ConcreteRound) : Bool :=
+  node_state.sent round
+
+@[export node_echoed]
+def node_echoed (node_state: ConcreteRBState) (originator: ConcreteAddress) (round: ConcreteRound) : ConcreteValueOption :=
+  match node_state.echoed (originator, round) with
+  | none => ConcreteValueOption.None
+  | some v => ConcreteValueOption.Some v
+
+@[export node_voted]
+def node_voted (node_state: ConcreteRBState) (originator: ConcreteAddress) (round: ConcreteRound) : ConcreteValueOption :=
+  match node_state.voted (originator, round) with
+  | none => ConcreteValueOption.None
+  | some v => ConcreteValueOption.Some v
+
+@[export node_output]
+def node_output (node_state: ConcreteRBState) (originator: ConcreteAddress) (round: ConcreteRound) : Array ConcreteValue :=
+  List.toArray (node_state.output (originator, round))
+
+@[export node_received_from]
+def node_received_from (node_state: ConcreteRBState) (msg: ConcreteRBMessage) : Array ConcreteAddress :=
+  List.toArray (node_state.msgReceivedFrom msg)
@@ -0,0 +1,130 @@
+import LeanSts.State
+import LeanSts.BFT.Network
+
+-- provable broadcast (PB), as in the bythos paper.
+-- https://github.com/verse-lab/bythos
+--
+-- this mirrors the native implementation in `src/protocol.rs`:
+-- the sender broadcasts a value and a proof, every node that externally validates them echoes
+-- back a partial signature, and the sender combines the first N - f valid partial signatures
+-- (one per node) into its output.
+--
+-- everything here is in the `PB` namespace, since `Protocol.lean` imports both this and
+-- `ReliableBroadcast.lean`, which has its own `Message`, `NodeState`, etc.
+
+namespace PB
+
+section ProvableBroadcast
+variable {Address Round Value Proof PartialSig CombinedSig : Type}
+variable [dec_addr : DecidableEq Address] [dec_round : DecidableEq Round]
+
+def InternalTransition := Round
+
+inductive Message
+  | InitMsg (r : Round) (v : Value) (p : Proof)
+  | EchoMsg (r : Round) (ps : PartialSig)
+
+/-- The application's side of PB (`PbParams` in `protocol.rs`).
+  Unlike the rust trait, these don't get a `self`: they close over whatever they need instead
+  (e.g. the node's own address, for signing). -/
+structure Params (Address Round Value Proof PartialSig CombinedSig : Type) where
+  valueBFT : Address → Round → Value × Proof
+  /-- `externallyValidate sender r v p` -/
+  externallyValidate : Address → Round → Value → Proof → Bool
+  /-- `partiallySign sender r v`: our partial signature on `sender`'s value. -/
+  partiallySign : Address → Round → Value → PartialSig
+  /-- `partiallyValidate signer sender r v ps` -/
+  partiallyValidate : Address → Address → Round → Value → PartialSig → Bool
+  combine : List (Address × PartialSig) → CombinedSig
+
+structure NodeState :=
+  /-- This node's address -/
+  id : Address
+  /-- The set of all nodes -/
+  allNodes : List Address
+
+  /-- sender state: what we broadcast in each round, if anything -/
+  sent : Round → Option (Value × Proof)
+  /-- the partial signatures we've collected, at most one per node -/
+  counter : Round → List (Address × PartialSig)
+  output : Round → Option CombinedSig
+  /-- receiver state: what we echoed for each (sender, round) -/
+  echoed : (Address × Round) → Option (Value × Proof)
+
+local notation "PBMessage" => (@Message Round Value Proof PartialSig)
+local notation "PBState" => (@NodeState Address Round Value Proof PartialSig CombinedSig)
+local notation "PBPacket" => (Packet Address PBMessage)
+local notation "PBParams" => (Params Address Round Value Proof PartialSig CombinedSig)
+
+def initLocalState (id : Address) (nodes : List Address) : PBState := {
+  id := id
+  allNodes := nodes
+  sent := λ _ => none
+  counter := λ _ => []
+  output := λ _ => none
+  echoed := λ _ => none
+}
+
+def numNodes (st : PBState) : ℕ := st.allNodes.length
+
+def byzThres (st : PBState) : ℕ := (numNodes st - 1) / 3
+
+def signatureThreshold (st : PBState) : ℕ := numNodes st - byzThres st
+
+def procInt (params : PBParams) (st : PBState) (r : @InternalTransition Round) : PBState × List PBPacket :=
+  match st.sent r with
+  | some _ => (st, [])
+  | none =>
+    let (v, p) := params.valueBFT st.id r
+    let st' := { st with sent := st.sent[r ↦ some (v, p)] }
+    let msg : PBMessage := Message.InitMsg r v p
+    (st', Packet.broadcast st.id st.allNodes msg)
+
+def handleInit (params : PBParams) (st : PBState) (src : Address) (r : Round) (v : Value) (p : Proof) :
+  PBState × List PBPacket :=
+  -- the sender echoes its own Init too, like every other node.
+  if let .none := st.echoed (src, r) then
+    if params.externallyValidate src r v p then
+      let ps := params.partiallySign src r v
+      let st' := { st with echoed := st.echoed[(src, r) ↦ some (v, p)] }
+      let msg : PBMessage := Message.EchoMsg r ps
+      (st', [{ src := st.id, dst := src, msg := msg, consumed := false }])
+    else
+      (st, [])
+  else
+    (st, [])
+
+def handleEcho (params : PBParams) (st : PBState) (src : Address) (r : Round) (ps : PartialSig) : PBState :=
+  match st.sent r, st.output r with
+  -- we're the sender, and haven't combined the signatures yet
+  | some (v, _), none =>
+    let sigs := st.counter r
+    if !params.partiallyValidate src st.id r v ps then
+      st
+    else if sigs.any (λ (signer, _) => decide (signer = src)) then
+      st
+    else
+      let sigs' := sigs ++ [(src, ps)]
+      let st' := { st with counter := st.counter[r ↦ sigs'] }
+      if signatureThreshold st ≤ sigs'.length then
+        { st' with output := st'.output[r ↦ some (params.combine sigs')] }
+      else
+        st'
+  | _, _ => st
+
+def procMsg (params : PBParams) (st : PBState) (src : Address) (msg : PBMessage) : PBState × List PBPacket :=
+  -- only members take part in the protocol.
+  if src ∈ st.allNodes then
+    match msg with
+    | Message.InitMsg r v p => handleInit params st src r v p
+    | Message.EchoMsg r ps => (handleEcho params st src r ps, [])
+  else
+    (st, [])
+
+instance PBProtocol (nodes : List Address) (params : PBParams) :
+  @NetworkProtocol Address PBMessage PBState (@InternalTransition Round) :=
+  ⟨λ id => initLocalState id nodes, procInt params, procMsg params⟩
+
+end ProvableBroadcast
+
+end PB
@@ -40,6 +40,24 @@ lean_lib Structs where
 lean_lib ReliableBroadcast where
   defaultFacets := #[LeanLib.sharedFacet]
 
+@[default_target]
+lean_lib ProvableBroadcast where
+  defaultFacets := #[LeanLib.sharedFacet]
+
+@[default_target]
+lean_lib ConsistentBroadcast where
+  defaultFacets := #[LeanLib.sharedFacet]
+
+-- PB's exports. built as part of `Protocol`'s fat library, which imports it.
+lean_lib PBProtocol
+
+-- CB's exports. likewise.
+lean_lib CBProtocol
+
+-- the JSON bridge, and the protocols behind it. also built as part of `Protocol`'s fat library.
+lean_lib JsonBridge
+lean_lib JsonProtocols
+
 -- build this module as a static fat library.
 -- i.e. package all dependencies and make those symbols available within this lib.
 -- references this PR: https://github.com/leanprover/lean4/pull/4271/files
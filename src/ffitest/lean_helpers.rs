@@ -14,13 +14,10 @@ pub enum Mode {
 /// Copies a Rust string into Lean.
 /// The Rust string will be deallocated, and re-allocated on the Lean side.
 pub unsafe fn rust_string_to_lean(s: String) -> *mut lean_object {
-    let c_str_s = std::ffi::CString::new(s).unwrap();
-    // need to cast to *const u8, since that's the type accepted by `lean_mk_string`.
-    let c_str_ptr = c_str_s.as_ptr();
+    // we pass the length explicitly instead of going through a C string,
+    // since rust strings may contain NUL bytes (which lean strings can hold just fine).
     // reallocation in lean occurs here
-    let c_str = lean_mk_string(c_str_ptr as *const u8);
-
-    c_str
+    lean_mk_string_from_bytes(s.as_ptr(), s.len())
 
     // rust string `s` is freed after this block ends
 }
@@ -28,9 +25,12 @@ pub unsafe fn rust_string_to_lean(s: String) -> *mut lean_object {
 /// Copies a Lean string into Rust.
 /// The Lean string will be deallocated, and re-allocated on the Rust side.
 pub unsafe fn lean_string_to_rust(s: *mut lean_object, mode: Mode) -> String {
-    let result_c_str_ptr = lean_string_cstr(s);
-    let result_c_str = std::ffi::CStr::from_ptr(result_c_str_ptr as *const i8);
-    let result_str = result_c_str.to_str().unwrap().to_string();
+    // `lean_string_size` counts the terminating NUL, which isn't part of the string.
+    // (reading up to the first NUL instead would cut off strings that contain one.)
+    let result_bytes =
+        std::slice::from_raw_parts(lean_string_cstr(s) as *const u8, lean_string_size(s) - 1);
+    // lean strings are always valid UTF-8.
+    let result_str = String::from_utf8_lossy(result_bytes).into_owned();
 
     // free c-str on lean side
     match mode {
@@ -81,6 +81,15 @@ pub unsafe fn rust_string_vec_to_lean_array(vec: Vec<String>) -> *mut lean_objec
     arr
 }
 
+/// Copies a Lean `Array String` into Rust. The array is borrowed.
+pub unsafe fn lean_string_array_to_rust(arr: *mut lean_object) -> Vec<String> {
+    assert!(lean_is_array(arr));
+    (0..lean_array_size(arr))
+        // `lean_array_get_core` borrows (unlike `lean_array_uget`, which bumps the refcount).
+        .map(|i| lean_string_to_rust(lean_array_get_core(arr, i), Mode::Borrow))
+        .collect()
+}
+
 // io helpers
 pub unsafe fn cleanup_lean_io(o: *mut lean_object) {
     if lean_io_result_is_ok(o) {
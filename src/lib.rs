@@ -1,3 +1,46 @@
-#![allow(unused_variables)]
-#![allow(dead_code)]
-mod protocol;
+pub mod protocol;
+pub mod signature;
+
+pub mod networktest {
+    pub mod aba_protocol;
+    pub mod avid_protocol;
+    pub mod byzantine;
+    #[cfg(feature = "lean")]
+    pub mod cb_protocol;
+    #[cfg(feature = "lean")]
+    pub mod differential;
+    #[cfg(feature = "lean")]
+    pub mod json_bridge;
+    pub mod libp2p_mdns;
+    pub mod libp2p_mdns_ping;
+    pub mod libp2p_mdns_request_response;
+    pub mod libp2p_pb;
+    pub mod libp2p_rb;
+    #[cfg(feature = "lean")]
+    pub mod model_checker;
+    pub mod network_protocol;
+    pub mod pb_protocol;
+    pub mod rb_actor;
+    mod rb_batch;
+    pub mod rb_chaos;
+    pub mod rb_control;
+    pub mod rb_native;
+    mod rb_peers;
+    pub mod rb_protocol;
+    #[cfg(feature = "lean")]
+    #[allow(dead_code, unused_variables)]
+    pub mod sandbox;
+    pub mod simulator;
+    #[allow(dead_code)]
+    pub mod tcp;
+    pub mod trace;
+}
+
+#[cfg(feature = "lean")]
+pub mod ffitest {
+    pub mod arrays;
+    pub mod globals;
+    pub mod lean_helpers;
+    pub mod simple;
+    pub mod structs;
+}
@@ -1,44 +1,40 @@
+#[cfg(feature = "lean")]
+use pb_rust::ffitest;
+use pb_rust::networktest;
 use std::env::args;
 
-mod networktest {
-    pub mod libp2p_mdns;
-    pub mod libp2p_mdns_ping;
-    pub mod libp2p_mdns_request_response;
-    pub mod libp2p_rb;
-    mod rb_protocol;
-    #[allow(dead_code, unused_variables)]
-    pub mod sandbox;
-    #[allow(dead_code)]
-    pub mod tcp;
-}
-
-mod ffitest {
-    pub mod arrays;
-    pub mod globals;
-    pub mod lean_helpers;
-    pub mod simple;
-    pub mod structs;
-}
-
 fn main() {
     let _ = match args().nth(1).unwrap().as_str() {
         "m" => networktest::libp2p_mdns::main().unwrap(),
         "mp" => networktest::libp2p_mdns_ping::main().unwrap(),
         "mrr" => networktest::libp2p_mdns_request_response::main().unwrap(),
         "rb" => networktest::libp2p_rb::main().unwrap(),
+        "pb" => networktest::libp2p_pb::main().unwrap(),
+        "rbctl" => networktest::rb_control::client_main().unwrap(),
+        #[cfg(feature = "lean")]
         "sb" => networktest::sandbox::main(),
+        "sim" => networktest::simulator::main(),
+        "trace" => networktest::trace::main(),
+        #[cfg(feature = "lean")]
+        "mc" => networktest::model_checker::main(),
+        #[cfg(feature = "lean")]
+        "diff" => networktest::differential::main(),
+        #[cfg(feature = "lean")]
         "ffis" => {
             let module = args().nth(2).unwrap();
             ffitest::simple::main(module.as_str());
         }
+        #[cfg(feature = "lean")]
         "ffig" => {
             let module = args().nth(2).unwrap();
             ffitest::globals::main(module.as_str());
         }
+        #[cfg(feature = "lean")]
         "ffil" => {
             let module = args().nth(2).unwrap();
             ffitest::arrays::main(module.as_str());
         }
+        #[cfg(feature = "lean")]
         "ffist" => {
             let module = args().nth(2).unwrap();
             ffitest::structs::main(module.as_str());
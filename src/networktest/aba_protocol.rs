@@ -0,0 +1,550 @@
+use super::network_protocol::{DrivenProtocol, NetworkProtocol};
+use libp2p::identity::Keypair;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Display;
+
+// asynchronous binary byzantine agreement (ABA), after Mostéfaoui, Moumen and Raynal,
+// "signature-free asynchronous byzantine consensus with t < n/3 and O(n²) messages".
+// https://hal.science/hal-00944019/document
+//
+// every node proposes a bit, and every honest node decides the same bit, which some honest node
+// proposed. it runs in epochs, each of which is made of the same quorums as Bracha's RB:
+// - `Bval`: everyone sends its estimate. f + 1 `Bval`s for a bit are enough to send one too
+//   (like RB's votes), and with 2f + 1 of them, the bit goes into `bin_values`.
+// - `Aux`: everyone sends the first bit that got into its `bin_values`, and waits for N - f
+//   `Aux`s with bits in its `bin_values`.
+// - `Conf`: everyone sends the bits of those `Aux`s, and waits for N - f `Conf`s that fit in its
+//   `bin_values`. this extra step is HoneyBadgerBFT's fix for a liveness bug in the original.
+// then everyone flips the same coin. if all the bits it saw are the same bit b, it keeps b as its
+// estimate, and decides b if the coin agrees. otherwise, its next estimate is the coin.
+//
+// the coin is a stand-in for a threshold-signature coin: it's a hash of the session and the
+// epoch, so every node computes it locally, but so can the adversary. that's fine against the
+// simulator's random scheduler, but not against one that reorders packets on purpose.
+//
+// once a node decides, it sends `Term` and stops. a `Term` stands for the sender's `Bval`, `Aux`
+// and `Conf` for its bit in every later epoch, so the nodes that haven't decided yet still reach
+// their quorums, and f + 1 `Term`s for a bit are enough to decide it.
+//
+// this is a native protocol, and runs on the same driver as RB. an instance is keyed by its
+// session, so several of them (e.g. one per RB leader, as in HoneyBadgerBFT's ACS) flip
+// independent coins next to each other.
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    Bval {
+        epoch: usize,
+        b: bool,
+    },
+    Aux {
+        epoch: usize,
+        b: bool,
+    },
+    Conf {
+        epoch: usize,
+        values: BTreeSet<bool>,
+    },
+    // the sender decided `b` in `epoch`.
+    Term {
+        epoch: usize,
+        b: bool,
+    },
+}
+
+impl Message {
+    pub fn get_epoch(&self) -> usize {
+        match self {
+            Self::Bval { epoch, .. }
+            | Self::Aux { epoch, .. }
+            | Self::Conf { epoch, .. }
+            | Self::Term { epoch, .. } => *epoch,
+        }
+    }
+}
+
+fn bit(b: bool) -> u8 {
+    b as u8
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::Bval { epoch, b } => write!(f, "Bval @ epoch {epoch}: {}", bit(*b)),
+            Message::Aux { epoch, b } => write!(f, "Aux @ epoch {epoch}: {}", bit(*b)),
+            Message::Conf { epoch, values } => {
+                let values: Vec<String> = values.iter().map(|b| bit(*b).to_string()).collect();
+                write!(f, "Conf @ epoch {epoch}: {{{}}}", values.join(", "))
+            }
+            Message::Term { epoch, b } => write!(f, "Term @ epoch {epoch}: {}", bit(*b)),
+        }
+    }
+}
+
+pub type Packet = super::network_protocol::Packet<String, Message>;
+
+/// What a node decided, and in which epoch.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub value: bool,
+    pub epoch: usize,
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "decided {} in epoch {}", bit(self.value), self.epoch)
+    }
+}
+
+// everything a node got in one epoch, by sender index.
+#[derive(Default)]
+struct Epoch {
+    bval_sent: [bool; 2],
+    bvals: [HashSet<usize>; 2],
+    bin_values: BTreeSet<bool>,
+    aux_sent: bool,
+    auxes: HashMap<usize, bool>,
+    conf_sent: bool,
+    confs: HashMap<usize, BTreeSet<bool>>,
+}
+
+pub struct AbaState {
+    address: String,
+    est: Option<bool>,
+    epoch: usize,
+    epochs: BTreeMap<usize, Epoch>,
+    // the `Term` we got from each sender: the epoch it decided in, and its bit.
+    terms: HashMap<usize, (usize, bool)>,
+    decision: Option<Decision>,
+}
+
+impl AbaState {
+    /// The bit this node decided, if it has.
+    pub fn decided(&self) -> Option<bool> {
+        self.decision.as_ref().map(|decision| decision.value)
+    }
+}
+
+pub struct Aba {
+    node_list: Vec<String>,
+    session: String,
+}
+
+impl Aba {
+    pub fn new(node_list: Vec<String>, session: String) -> Result<Self, String> {
+        if node_list.is_empty() {
+            return Err(String::from("an agreement needs at least one member"));
+        }
+        Ok(Aba { node_list, session })
+    }
+
+    fn n(&self) -> usize {
+        self.node_list.len()
+    }
+
+    fn f(&self) -> usize {
+        (self.n() - 1) / 3
+    }
+
+    fn index_of(&self, address: &str) -> Option<usize> {
+        self.node_list.iter().position(|node| node == address)
+    }
+
+    /// The common coin for `epoch`. Every node of the session gets the same one.
+    pub fn coin(&self, epoch: usize) -> bool {
+        let hash = Sha256::new()
+            .chain_update(self.session.as_bytes())
+            .chain_update(epoch.to_le_bytes())
+            .finalize();
+        hash[0] & 1 == 1
+    }
+
+    /// Proposes `b`. Only the first proposal counts.
+    pub fn propose(&self, state: &mut AbaState, b: bool) -> Vec<Packet> {
+        self.proc_internal(state, b)
+    }
+
+    fn to_all(&self, src: &str, msg: Message) -> Vec<Packet> {
+        self.node_list
+            .iter()
+            .map(|dst| Packet {
+                src: src.to_string(),
+                dst: dst.clone(),
+                msg: msg.clone(),
+                consumed: false,
+            })
+            .collect()
+    }
+
+    // the senders that decided `b` before `epoch`, whose `Term` stands in for their messages.
+    fn terminated(state: &AbaState, epoch: usize, b: bool) -> impl Iterator<Item = usize> + '_ {
+        state
+            .terms
+            .iter()
+            .filter(move |(_, &(decided_in, value))| decided_in < epoch && value == b)
+            .map(|(&sender, _)| sender)
+    }
+
+    fn bval_count(state: &AbaState, epoch: usize, b: bool) -> usize {
+        let bvals = &state.epochs[&epoch].bvals[b as usize];
+        let terminated = Self::terminated(state, epoch, b).filter(|q| !bvals.contains(q));
+        bvals.len() + terminated.count()
+    }
+
+    fn decide(&self, state: &mut AbaState, b: bool) -> Vec<Packet> {
+        if state.decision.is_some() {
+            return Vec::new();
+        }
+        let epoch = state.epoch;
+        state.decision = Some(Decision { value: b, epoch });
+        self.to_all(&state.address, Message::Term { epoch, b })
+    }
+
+    // moves the current epoch along as far as the messages so far allow,
+    // and returns what that sends.
+    fn progress(&self, state: &mut AbaState) -> Vec<Packet> {
+        let (n, f) = (self.n(), self.f());
+        let mut packets = Vec::new();
+
+        while state.decision.is_none() {
+            let r = state.epoch;
+            state.epochs.entry(r).or_default();
+
+            for b in [false, true] {
+                let count = Self::bval_count(state, r, b);
+                let epoch = state.epochs.get_mut(&r).unwrap();
+                if count > f && !epoch.bval_sent[b as usize] {
+                    epoch.bval_sent[b as usize] = true;
+                    packets.extend(self.to_all(&state.address, Message::Bval { epoch: r, b }));
+                }
+                if count > 2 * f && epoch.bin_values.insert(b) && !epoch.aux_sent {
+                    epoch.aux_sent = true;
+                    packets.extend(self.to_all(&state.address, Message::Aux { epoch: r, b }));
+                }
+            }
+
+            // the `Aux`s (real or stood in for by a `Term`) whose bit is in `bin_values`.
+            let terms: Vec<(usize, bool)> = state
+                .terms
+                .iter()
+                .filter(|(_, &(decided_in, _))| decided_in < r)
+                .map(|(&sender, &(_, b))| (sender, b))
+                .collect();
+            let epoch = state.epochs.get_mut(&r).unwrap();
+            let mut auxes = epoch.auxes.clone();
+            let mut confs = epoch.confs.clone();
+            for (sender, b) in terms {
+                auxes.entry(sender).or_insert(b);
+                confs.entry(sender).or_insert_with(|| BTreeSet::from([b]));
+            }
+
+            if epoch.aux_sent && !epoch.conf_sent {
+                let fitting: Vec<bool> = auxes
+                    .values()
+                    .copied()
+                    .filter(|b| epoch.bin_values.contains(b))
+                    .collect();
+                if fitting.len() >= n - f {
+                    epoch.conf_sent = true;
+                    let values = fitting.into_iter().collect();
+                    packets.extend(self.to_all(&state.address, Message::Conf { epoch: r, values }));
+                }
+            }
+            if !epoch.conf_sent {
+                break;
+            }
+
+            let fitting: Vec<&BTreeSet<bool>> = confs
+                .values()
+                .filter(|values| values.is_subset(&epoch.bin_values))
+                .collect();
+            if fitting.len() < n - f {
+                break;
+            }
+            let values: BTreeSet<bool> = fitting.into_iter().flatten().copied().collect();
+
+            // on to the next epoch.
+            let coin = self.coin(r);
+            let est = match values.first() {
+                Some(&b) if values.len() == 1 => {
+                    if b == coin {
+                        packets.extend(self.decide(state, b));
+                    }
+                    b
+                }
+                _ => coin,
+            };
+            if state.decision.is_some() {
+                break;
+            }
+            state.est = Some(est);
+            state.epoch = r + 1;
+            let next = state.epochs.entry(r + 1).or_default();
+            if !next.bval_sent[est as usize] {
+                next.bval_sent[est as usize] = true;
+                packets.extend(self.to_all(
+                    &state.address,
+                    Message::Bval {
+                        epoch: r + 1,
+                        b: est,
+                    },
+                ));
+            }
+        }
+        packets
+    }
+}
+
+impl NetworkProtocol for Aba {
+    type Address = String;
+    type Message = Message;
+    // the bit to propose
+    type InternalTransition = bool;
+    type State = AbaState;
+    type Output = Decision;
+
+    fn local_init(&self, address: &String) -> AbaState {
+        AbaState {
+            address: address.clone(),
+            est: None,
+            epoch: 0,
+            epochs: BTreeMap::new(),
+            terms: HashMap::new(),
+            decision: None,
+        }
+    }
+
+    fn proc_internal(&self, state: &mut AbaState, b: bool) -> Vec<Packet> {
+        // a node that hasn't proposed yet still takes part (it relays `Bval`s, and so on),
+        // so by now, it may have an estimate anyway.
+        if state.est.is_some() || state.decision.is_some() {
+            return Vec::new();
+        }
+        state.est = Some(b);
+        let r = state.epoch;
+        let epoch = state.epochs.entry(r).or_default();
+        if epoch.bval_sent[b as usize] {
+            return Vec::new();
+        }
+        epoch.bval_sent[b as usize] = true;
+        self.to_all(&state.address, Message::Bval { epoch: r, b })
+    }
+
+    fn proc_message(&self, state: &mut AbaState, src: String, msg: Message) -> Vec<Packet> {
+        let Some(sender) = self.index_of(&src) else {
+            return Vec::new();
+        };
+        if state.decision.is_some() {
+            return Vec::new();
+        }
+
+        let f = self.f();
+        match msg {
+            Message::Bval { epoch, b } => {
+                let epoch = state.epochs.entry(epoch).or_default();
+                if !epoch.bvals[b as usize].insert(sender) {
+                    return Vec::new();
+                }
+            }
+            Message::Aux { epoch, b } => {
+                let epoch = state.epochs.entry(epoch).or_default();
+                // only the first one counts.
+                if epoch.auxes.contains_key(&sender) {
+                    return Vec::new();
+                }
+                epoch.auxes.insert(sender, b);
+            }
+            Message::Conf { epoch, values } => {
+                let epoch = state.epochs.entry(epoch).or_default();
+                if values.is_empty() || epoch.confs.contains_key(&sender) {
+                    return Vec::new();
+                }
+                epoch.confs.insert(sender, values);
+            }
+            Message::Term { epoch, b } => {
+                if state.terms.contains_key(&sender) {
+                    return Vec::new();
+                }
+                state.terms.insert(sender, (epoch, b));
+                // at least one of them is honest, so b is what everyone decides.
+                if state
+                    .terms
+                    .values()
+                    .filter(|&&(_, value)| value == b)
+                    .count()
+                    > f
+                {
+                    return self.decide(state, b);
+                }
+            }
+        }
+        self.progress(state)
+    }
+
+    fn outputs(&self, state: &AbaState) -> Vec<Decision> {
+        state.decision.iter().cloned().collect()
+    }
+}
+
+// the driver's proposals: "1"/"true" propose 1, "0"/"false" propose 0.
+fn parse_bit(value: &str) -> Result<bool, String> {
+    match value.trim() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        other => Err(format!(
+            "ABA proposes a bit (0/1/true/false), not {other:?}"
+        )),
+    }
+}
+
+impl DrivenProtocol for Aba {
+    type Config = ();
+
+    // ABA has no leader, but every node is initialized with the same one, so it names the session.
+    fn create(
+        _config: (),
+        node_list: Vec<String>,
+        leader: String,
+        _keypair: &Keypair,
+    ) -> Result<Self, String> {
+        Aba::new(node_list, leader)
+    }
+
+    fn validate(members: &[String], leader: &str) -> Result<(), String> {
+        Aba::new(members.to_vec(), leader.to_string()).map(drop)
+    }
+
+    fn check_value(value: &str) -> Result<(), String> {
+        parse_bit(value).map(drop)
+    }
+
+    // `check_value` has already turned down anything that isn't a bit.
+    fn broadcast(&self, _state: &mut AbaState, value: String) -> bool {
+        parse_bit(&value) == Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networktest::simulator::{node_address, ProtocolSimulator, SimConfig};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // runs an agreement where node i proposes `proposals[i]` (or nothing), and checks that every
+    // node decides, and on the same bit. returns that bit.
+    fn agree(proposals: &[Option<bool>], seed: u64) -> bool {
+        let nodes = proposals.len();
+        let members: Vec<String> = (0..nodes).map(node_address).collect();
+        let aba = Aba::new(members, format!("session-{seed}")).unwrap();
+        let config = SimConfig {
+            nodes,
+            seed,
+            ..SimConfig::default()
+        };
+        let mut simulator = ProtocolSimulator::new(aba, &config);
+        for (node, proposal) in proposals.iter().enumerate() {
+            if let Some(b) = proposal {
+                simulator.input(node, *b);
+            }
+        }
+
+        assert!(simulator.run(), "seed {seed}: the network never went quiet");
+        let decisions: Vec<Vec<Decision>> = (0..nodes).map(|i| simulator.outputs(i)).collect();
+        for (node, decision) in decisions.iter().enumerate() {
+            assert_eq!(decision.len(), 1, "seed {seed}: node {node} never decided");
+        }
+        let value = decisions[0][0].value;
+        assert!(
+            decisions.iter().all(|decision| decision[0].value == value),
+            "seed {seed}: nodes decided differently: {decisions:?}"
+        );
+        value
+    }
+
+    #[test]
+    fn unanimous_proposals_are_decided() {
+        for seed in 0..20 {
+            for b in [false, true] {
+                assert_eq!(agree(&[Some(b); 4], seed), b);
+                assert_eq!(agree(&[Some(b); 7], seed), b);
+            }
+        }
+    }
+
+    #[test]
+    fn split_proposals_terminate_in_agreement() {
+        let mut decided = HashSet::new();
+        for seed in 0..100 {
+            let proposals: Vec<Option<bool>> = (0..4).map(|i| Some(i % 2 == 0)).collect();
+            decided.insert(agree(&proposals, seed));
+            let proposals: Vec<Option<bool>> = (0..7).map(|i| Some(i < 3)).collect();
+            agree(&proposals, seed);
+        }
+        // neither bit wins every time.
+        assert_eq!(decided.len(), 2);
+    }
+
+    #[test]
+    fn nodes_that_never_propose_still_decide() {
+        for seed in 0..20 {
+            // 3 of 4 is N - f, which is all the proposals an epoch needs.
+            agree(&[Some(true), None, Some(false), Some(true)], seed);
+        }
+    }
+
+    #[test]
+    fn the_coin_is_common_to_a_session() {
+        let members: Vec<String> = (0..4).map(node_address).collect();
+        let a = Aba::new(members.clone(), String::from("a")).unwrap();
+        let b = Aba::new(members, String::from("a")).unwrap();
+        assert!((0..64).all(|epoch| a.coin(epoch) == b.coin(epoch)));
+        // and it does flip.
+        assert!((0..64).any(|epoch| a.coin(epoch)) && (0..64).any(|epoch| !a.coin(epoch)));
+    }
+
+    #[test]
+    fn a_silent_byzantine_node_doesnt_block_agreement() {
+        // node 3 is byzantine and never says anything, so everything it's sent is dropped.
+        let members: Vec<String> = (0..4).map(node_address).collect();
+        for seed in 0..20 {
+            let aba = Aba::new(members.clone(), format!("silent-{seed}")).unwrap();
+            let mut states: Vec<AbaState> = members.iter().map(|m| aba.local_init(m)).collect();
+            let mut in_flight: Vec<Packet> = Vec::new();
+            for (node, b) in [true, false, false].into_iter().enumerate() {
+                in_flight.extend(aba.propose(&mut states[node], b));
+            }
+            let mut rng = StdRng::seed_from_u64(seed);
+            while !in_flight.is_empty() {
+                let packet = in_flight.swap_remove(rng.gen_range(0..in_flight.len()));
+                let dst = members.iter().position(|m| *m == packet.dst).unwrap();
+                if dst == 3 {
+                    continue;
+                }
+                in_flight.extend(aba.proc_message(&mut states[dst], packet.src, packet.msg));
+            }
+
+            let decided: Vec<Option<bool>> = states[..3].iter().map(AbaState::decided).collect();
+            assert!(decided[0].is_some(), "seed {seed}: nobody decided");
+            assert!(
+                decided.iter().all(|d| *d == decided[0]),
+                "seed {seed}: {decided:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn the_driver_only_takes_bits() {
+        for (value, bit) in [
+            ("1", true),
+            ("true", true),
+            (" 0\n", false),
+            ("false", false),
+        ] {
+            assert_eq!(parse_bit(value), Ok(bit), "{value:?}");
+            assert_eq!(Aba::check_value(value), Ok(()));
+        }
+        for value in ["", "2", "yes", "TRUE", "01"] {
+            assert!(Aba::check_value(value).is_err(), "{value:?} was taken");
+        }
+    }
+}
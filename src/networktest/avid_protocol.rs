@@ -0,0 +1,655 @@
+use super::network_protocol::{DrivenProtocol, NetworkProtocol};
+use super::rb_protocol::Delivery;
+use libp2p::identity::Keypair;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+
+// erasure-coded reliable broadcast, in the style of AVID (Cachin and Tessaro, "asynchronous
+// verifiable information dispersal") and HoneyBadgerBFT's RBC.
+// https://eprint.iacr.org/2016/199.pdf (figure 2)
+//
+// in Bracha's RB, every echo and vote carries the whole value, so a broadcast costs O(N² |v|).
+// here, the leader splits its value into N Reed-Solomon fragments, any N - 2f of which are
+// enough to rebuild it, and commits to all of them with a merkle tree:
+// - the leader sends node i its fragment i, with a merkle proof against the root.
+// - node i echoes its fragment (and proof) to everyone.
+// - once a node has N - f valid echoes for a root, it rebuilds the value, re-encodes it,
+//   and sends `Ready` for the root if that gives the same root. (otherwise the leader is byzantine,
+//   and it never sends `Ready` for that root.)
+// - f + 1 readies for a root are enough to send our own, like Bracha's votes.
+// - once a node has 2f + 1 readies and N - 2f valid echoes for a root, it rebuilds the value and delivers.
+// every node sends one fragment to everyone, and fragments are O(|v| / N), so a broadcast
+// costs O(N |v|) (plus the proofs, O(N² log N) hashes).
+//
+// this is a native protocol (there's no lean version of it yet), and runs on the same driver as RB.
+
+pub type Hash = [u8; 32];
+
+fn hex(hash: &Hash) -> String {
+    hash.iter().take(4).map(|b| format!("{b:02x}")).collect()
+}
+
+/// A fragment of the leader's value, with the proof that it's the `index`-th leaf of the root.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fragment {
+    pub index: usize,
+    pub data: Vec<u8>,
+    pub proof: Vec<Hash>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    // from the leader: the recipient's own fragment.
+    Value {
+        r: usize,
+        root: Hash,
+        fragment: Fragment,
+    },
+    // the sender's fragment, which it got from the leader.
+    Echo {
+        r: usize,
+        root: Hash,
+        fragment: Fragment,
+    },
+    Ready {
+        r: usize,
+        root: Hash,
+    },
+}
+
+impl Message {
+    pub fn get_round(&self) -> usize {
+        match self {
+            Self::Value { r, .. } | Self::Echo { r, .. } | Self::Ready { r, .. } => *r,
+        }
+    }
+
+    /// Roughly how many bytes this message puts on the wire: its fragment and proof, if any.
+    pub fn payload_len(&self) -> usize {
+        match self {
+            Self::Value { fragment, .. } | Self::Echo { fragment, .. } => {
+                fragment.data.len() + 32 * fragment.proof.len()
+            }
+            Self::Ready { .. } => 0,
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::Value { r, root, fragment } => write!(
+                f,
+                "Value @ round {r}: fragment {} of {} ({} bytes)",
+                fragment.index,
+                hex(root),
+                fragment.data.len()
+            ),
+            Message::Echo { r, root, fragment } => write!(
+                f,
+                "Echo @ round {r}: fragment {} of {} ({} bytes)",
+                fragment.index,
+                hex(root),
+                fragment.data.len()
+            ),
+            Message::Ready { r, root } => write!(f, "Ready @ round {r}: {}", hex(root)),
+        }
+    }
+}
+
+pub type Packet = super::network_protocol::Packet<String, Message>;
+
+// erasure coding
+// ===
+
+/// Splits values into `n` fragments, any `k` of which are enough to rebuild them.
+pub struct Coder {
+    n: usize,
+    k: usize,
+    // `None` when there's no redundancy (k = n), which `ReedSolomon` doesn't support.
+    rs: Option<ReedSolomon>,
+}
+
+impl Coder {
+    pub fn new(n: usize, k: usize) -> Result<Self, String> {
+        if k == 0 || k > n {
+            return Err(format!(
+                "can't split a value into {n} fragments, {k} of which rebuild it"
+            ));
+        }
+        let rs = match n - k {
+            0 => None,
+            parity => Some(ReedSolomon::new(k, parity).map_err(|e| format!("{e:?}"))?),
+        };
+        Ok(Coder { n, k, rs })
+    }
+
+    /// The value's length comes first, so that `decode` can strip the padding.
+    pub fn encode(&self, value: &[u8]) -> Vec<Vec<u8>> {
+        let mut bytes = (value.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(value);
+        let fragment_len = bytes.len().div_ceil(self.k);
+        bytes.resize(fragment_len * self.k, 0);
+
+        let mut fragments: Vec<Vec<u8>> = bytes.chunks(fragment_len).map(<[u8]>::to_vec).collect();
+        fragments.resize(self.n, vec![0; fragment_len]);
+        if let Some(rs) = &self.rs {
+            rs.encode(&mut fragments)
+                .expect("fragments should all be the same, non-zero length");
+        }
+        fragments
+    }
+
+    /// Rebuilds a value from (at least `k` of) its fragments, by index.
+    /// Returns `None` if there aren't enough, or they don't make sense together.
+    pub fn decode(&self, fragments: &BTreeMap<usize, Vec<u8>>) -> Option<Vec<u8>> {
+        let mut shards: Vec<Option<Vec<u8>>> = vec![None; self.n];
+        for (&index, data) in fragments.iter().filter(|(&index, _)| index < self.n) {
+            shards[index] = Some(data.clone());
+        }
+        match &self.rs {
+            Some(rs) => rs.reconstruct_data(&mut shards).ok()?,
+            None if shards.iter().any(Option::is_none) => return None,
+            None => (),
+        }
+
+        let bytes: Vec<u8> = shards
+            .into_iter()
+            .take(self.k)
+            .flat_map(|shard| shard.unwrap_or_default())
+            .collect();
+        let len = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?) as usize;
+        bytes.get(8..8usize.checked_add(len)?).map(<[u8]>::to_vec)
+    }
+}
+
+// merkle trees
+// ===
+// leaves and inner nodes are hashed with different prefixes, so that one can't pass for the other.
+
+fn leaf_hash(data: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// A merkle tree over the fragments, padded to a power of two with empty leaves.
+pub struct MerkleTree {
+    // every level, from the leaves up to the root.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: &[Vec<u8>]) -> Self {
+        let width = leaves.len().next_power_of_two();
+        let mut level: Vec<Hash> = leaves.iter().map(|leaf| leaf_hash(leaf)).collect();
+        level.resize(width, [0; 32]);
+
+        let mut levels = vec![level];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], &pair[1]))
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree { levels }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels.last().unwrap()[0]
+    }
+
+    /// The siblings on the way from leaf `index` up to the root.
+    pub fn proof(&self, index: usize) -> Vec<Hash> {
+        let depth = self.levels.len() - 1;
+        (0..depth)
+            .map(|level| self.levels[level][(index >> level) ^ 1])
+            .collect()
+    }
+}
+
+/// Whether `data` is leaf `index` of the tree with `root` (and `leaves` leaves).
+pub fn verify_proof(root: &Hash, leaves: usize, index: usize, data: &[u8], proof: &[Hash]) -> bool {
+    let depth = leaves.next_power_of_two().trailing_zeros() as usize;
+    if index >= leaves || proof.len() != depth {
+        return false;
+    }
+    let hash = proof
+        .iter()
+        .enumerate()
+        .fold(leaf_hash(data), |hash, (level, sibling)| {
+            if (index >> level) & 1 == 0 {
+                node_hash(&hash, sibling)
+            } else {
+                node_hash(sibling, &hash)
+            }
+        });
+    hash == *root
+}
+
+// the protocol
+// ===
+
+/// AVID-style RB as a `NetworkProtocol`, for the driver (or the simulator) to run.
+pub struct Avid {
+    pub node_list: Vec<String>,
+    pub leader: String,
+    coder: Coder,
+}
+
+// what a node knows about one of the leader's broadcasts.
+#[derive(Default)]
+struct Round {
+    // whether we've echoed our fragment
+    echoed: bool,
+    ready_sent: bool,
+    delivered: bool,
+    // senders we've taken an echo (or a ready) from. one each per round, whatever the root.
+    echoed_by: HashSet<usize>,
+    readied_by: HashSet<usize>,
+    // root -> (sender's index -> its fragment)
+    echoes: HashMap<Hash, BTreeMap<usize, Vec<u8>>>,
+    readies: HashMap<Hash, usize>,
+    // roots whose fragments turned out not to encode any value, e.g. from a byzantine leader.
+    bad_roots: HashSet<Hash>,
+}
+
+pub struct AvidState {
+    address: String,
+    // our index in `node_list`, which is also the index of our fragment.
+    index: usize,
+    // the next round we lead, if we're the leader
+    next_round: usize,
+    rounds: HashMap<usize, Round>,
+    deliveries: Vec<Delivery>,
+}
+
+impl Avid {
+    pub fn new(node_list: Vec<String>, leader: String) -> Result<Self, String> {
+        if !node_list.contains(&leader) {
+            return Err(format!("the leader {leader} isn't a member"));
+        }
+        // any N - 2f fragments rebuild the value: that's how many honest nodes
+        // are guaranteed to have echoed theirs once anyone has N - f echoes.
+        let coder = Coder::new(node_list.len(), node_list.len() - 2 * Self::f(&node_list))?;
+        Ok(Avid {
+            node_list,
+            leader,
+            coder,
+        })
+    }
+
+    fn f(node_list: &[String]) -> usize {
+        (node_list.len().max(1) - 1) / 3
+    }
+
+    fn n(&self) -> usize {
+        self.node_list.len()
+    }
+
+    fn index_of(&self, address: &str) -> Option<usize> {
+        self.node_list.iter().position(|node| node == address)
+    }
+
+    fn to_all(&self, src: &str, msg: Message) -> Vec<Packet> {
+        self.node_list
+            .iter()
+            .map(|dst| Packet {
+                src: src.to_string(),
+                dst: dst.clone(),
+                msg: msg.clone(),
+                consumed: false,
+            })
+            .collect()
+    }
+
+    // the value behind `root`, if `fragments` rebuild one that really has that root.
+    fn rebuild(&self, root: &Hash, fragments: &BTreeMap<usize, Vec<u8>>) -> Option<Vec<u8>> {
+        let value = self.coder.decode(fragments)?;
+        let reencoded = self.coder.encode(&value);
+        (MerkleTree::new(&reencoded).root() == *root).then_some(value)
+    }
+
+    // sends `Ready` once we have N - f echoes for a root that checks out,
+    // or f + 1 readies (at least one of which is from an honest node).
+    fn maybe_ready(&self, state: &mut AvidState, r: usize, root: Hash) -> Vec<Packet> {
+        let (n, f) = (self.n(), Self::f(&self.node_list));
+        let round = state.rounds.entry(r).or_default();
+        if round.ready_sent || round.bad_roots.contains(&root) {
+            return Vec::new();
+        }
+
+        let echoes = round.echoes.get(&root).map_or(0, BTreeMap::len);
+        let readies = round.readies.get(&root).copied().unwrap_or(0);
+        let ready = if readies > f {
+            true
+        } else if echoes >= n - f {
+            let checks_out = self.rebuild(&root, &round.echoes[&root]).is_some();
+            if !checks_out {
+                round.bad_roots.insert(root);
+            }
+            checks_out
+        } else {
+            false
+        };
+
+        if !ready {
+            return Vec::new();
+        }
+        round.ready_sent = true;
+        self.to_all(&state.address, Message::Ready { r, root })
+    }
+
+    // delivers once we have 2f + 1 readies, and enough echoes to rebuild the value.
+    fn maybe_deliver(&self, state: &mut AvidState, r: usize, root: Hash) {
+        let (n, f) = (self.n(), Self::f(&self.node_list));
+        let round = state.rounds.entry(r).or_default();
+        let readies = round.readies.get(&root).copied().unwrap_or(0);
+        let echoes = round.echoes.get(&root).map_or(0, BTreeMap::len);
+        if round.delivered || readies < 2 * f + 1 || echoes < n - 2 * f {
+            return;
+        }
+
+        // 2f + 1 readies mean an honest node checked the root, so this only fails if some of the
+        // echoes came from byzantine nodes, which can't happen: echoes are checked against the root.
+        let Some(value) = self.rebuild(&root, &round.echoes[&root]) else {
+            return;
+        };
+        round.delivered = true;
+        state.deliveries.push(Delivery {
+            leader: self.leader.clone(),
+            round: r,
+            // the leader's value is a string. if it's not valid UTF-8, every honest node
+            // still turns the same bytes into the same string.
+            value: String::from_utf8_lossy(&value).into_owned(),
+        });
+    }
+}
+
+impl NetworkProtocol for Avid {
+    type Address = String;
+    type Message = Message;
+    // the value to broadcast, in the leader's next round
+    type InternalTransition = String;
+    type State = AvidState;
+    type Output = Delivery;
+
+    fn local_init(&self, address: &String) -> AvidState {
+        AvidState {
+            address: address.clone(),
+            index: self
+                .index_of(address)
+                .expect("every node should be a member"),
+            next_round: 0,
+            rounds: HashMap::new(),
+            deliveries: Vec::new(),
+        }
+    }
+
+    fn proc_internal(&self, state: &mut AvidState, value: String) -> Vec<Packet> {
+        if state.address != self.leader {
+            return Vec::new();
+        }
+        let r = state.next_round;
+        state.next_round += 1;
+
+        let fragments = self.coder.encode(value.as_bytes());
+        let tree = MerkleTree::new(&fragments);
+        let root = tree.root();
+        fragments
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| Packet {
+                src: state.address.clone(),
+                dst: self.node_list[index].clone(),
+                msg: Message::Value {
+                    r,
+                    root,
+                    fragment: Fragment {
+                        index,
+                        data,
+                        proof: tree.proof(index),
+                    },
+                },
+                consumed: false,
+            })
+            .collect()
+    }
+
+    fn proc_message(&self, state: &mut AvidState, src: String, msg: Message) -> Vec<Packet> {
+        let Some(sender) = self.index_of(&src) else {
+            return Vec::new();
+        };
+        let n = self.n();
+
+        match msg {
+            Message::Value { r, root, fragment } => {
+                let round = state.rounds.entry(r).or_default();
+                let valid = src == self.leader
+                    && fragment.index == state.index
+                    && verify_proof(&root, n, fragment.index, &fragment.data, &fragment.proof);
+                if round.echoed || !valid {
+                    return Vec::new();
+                }
+                round.echoed = true;
+                self.to_all(&state.address, Message::Echo { r, root, fragment })
+            }
+            Message::Echo { r, root, fragment } => {
+                let round = state.rounds.entry(r).or_default();
+                // everyone echoes their own fragment, and only once.
+                let valid = fragment.index == sender
+                    && verify_proof(&root, n, fragment.index, &fragment.data, &fragment.proof);
+                if !valid || !round.echoed_by.insert(sender) {
+                    return Vec::new();
+                }
+                round
+                    .echoes
+                    .entry(root)
+                    .or_default()
+                    .insert(sender, fragment.data);
+
+                let packets = self.maybe_ready(state, r, root);
+                self.maybe_deliver(state, r, root);
+                packets
+            }
+            Message::Ready { r, root } => {
+                let round = state.rounds.entry(r).or_default();
+                if !round.readied_by.insert(sender) {
+                    return Vec::new();
+                }
+                *round.readies.entry(root).or_default() += 1;
+
+                let packets = self.maybe_ready(state, r, root);
+                self.maybe_deliver(state, r, root);
+                packets
+            }
+        }
+    }
+
+    fn outputs(&self, state: &AvidState) -> Vec<Delivery> {
+        state.deliveries.clone()
+    }
+}
+
+impl DrivenProtocol for Avid {
+    type Config = ();
+
+    fn create(
+        _config: (),
+        node_list: Vec<String>,
+        leader: String,
+        _keypair: &Keypair,
+    ) -> Result<Self, String> {
+        Avid::new(node_list, leader)
+    }
+
+    // building the coder is cheap, and it's where most of the limits are.
+    fn validate(members: &[String], leader: &str) -> Result<(), String> {
+        Avid::new(members.to_vec(), leader.to_string()).map(drop)
+    }
+
+    fn broadcast(&self, _state: &mut AvidState, value: String) -> String {
+        value
+    }
+
+    // fragments aren't RB messages, so only the deliveries show up in traces.
+    fn traced_output(delivery: &Delivery) -> Option<Delivery> {
+        Some(delivery.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networktest::simulator::{node_address, ProtocolSimulator, SimConfig};
+
+    #[test]
+    fn any_k_fragments_rebuild_the_value() {
+        let coder = Coder::new(7, 3).unwrap();
+        let value = b"erasure coded".to_vec();
+        let fragments = coder.encode(&value);
+
+        for skip in 0..5 {
+            let some: BTreeMap<usize, Vec<u8>> = fragments
+                .iter()
+                .cloned()
+                .enumerate()
+                .skip(skip)
+                .take(3)
+                .collect();
+            assert_eq!(coder.decode(&some), Some(value.clone()));
+        }
+        let too_few: BTreeMap<usize, Vec<u8>> = fragments.into_iter().enumerate().take(2).collect();
+        assert_eq!(coder.decode(&too_few), None);
+    }
+
+    #[test]
+    fn merkle_proofs_only_verify_their_own_leaf() {
+        let leaves: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 3]).collect();
+        let tree = MerkleTree::new(&leaves);
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert!(verify_proof(&tree.root(), 5, i, leaf, &tree.proof(i)));
+            assert!(!verify_proof(
+                &tree.root(),
+                5,
+                (i + 1) % 5,
+                leaf,
+                &tree.proof(i)
+            ));
+            assert!(!verify_proof(&tree.root(), 5, i, b"forged", &tree.proof(i)));
+        }
+    }
+
+    // runs one broadcast of `value` on the simulator's network, and checks that every node
+    // delivers it. returns how many payload bytes went over the network.
+    fn broadcast(nodes: usize, seed: u64, value: &str) -> usize {
+        let members: Vec<String> = (0..nodes).map(node_address).collect();
+        let avid = Avid::new(members.clone(), members[0].clone()).unwrap();
+        let config = SimConfig {
+            nodes,
+            seed,
+            ..SimConfig::default()
+        };
+        let mut simulator = ProtocolSimulator::new(avid, &config);
+        simulator.input(0, value.to_string());
+
+        let mut bytes = 0;
+        assert!(simulator.run_with(|packet| bytes += packet.msg.payload_len()));
+        for node in 0..nodes {
+            let deliveries = simulator.outputs(node);
+            assert_eq!(deliveries.len(), 1, "node {node} should deliver once");
+            assert!(
+                deliveries[0].value == value,
+                "node {node} delivered the wrong value"
+            );
+        }
+        bytes
+    }
+
+    #[test]
+    fn every_node_delivers_a_multi_megabyte_value() {
+        // 2 MB, which would be 2 MB per echo and per vote in Bracha's RB.
+        let value: String = (0..2 << 20)
+            .map(|i| (b'a' + (i % 26) as u8) as char)
+            .collect();
+        for seed in 0..2 {
+            let bytes = broadcast(4, seed, &value);
+            // N = 4 and f = 1, so every fragment is half the value, and there are (at most,
+            // counting duplicates) 2 N² of them: N from the leader, and N² echoes.
+            assert!(bytes <= 2 * 4 * 4 * (value.len() / 2 + 1024));
+        }
+        broadcast(7, 0, &value);
+    }
+
+    #[test]
+    fn every_node_delivers_without_redundancy() {
+        // with fewer than 4 nodes, f = 0, and every fragment is needed.
+        broadcast(1, 0, "alone");
+        broadcast(3, 0, "three");
+    }
+
+    #[test]
+    fn init_is_turned_down_before_the_actor_sees_it() {
+        let members: Vec<String> = (0..4).map(node_address).collect();
+        assert!(Avid::validate(&members, &members[0]).is_ok());
+        assert!(Avid::validate(&members, "outsider").is_err());
+        // the coder can't split a value into more than 256 fragments.
+        let too_many: Vec<String> = (0..257).map(node_address).collect();
+        assert!(Avid::validate(&too_many, &too_many[0]).is_err());
+    }
+
+    #[test]
+    fn fragments_that_dont_encode_the_root_are_never_delivered() {
+        let members: Vec<String> = (0..4).map(node_address).collect();
+        let avid = Avid::new(members.clone(), members[0].clone()).unwrap();
+        let mut states: Vec<_> = members.iter().map(|m| avid.local_init(m)).collect();
+
+        // a byzantine leader commits to fragments that aren't a codeword:
+        // every fragment checks out on its own, but they don't rebuild anything with this root.
+        let fragments: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 16]).collect();
+        let tree = MerkleTree::new(&fragments);
+        let mut in_flight: Vec<Packet> = fragments
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| Packet {
+                src: members[0].clone(),
+                dst: members[index].clone(),
+                msg: Message::Value {
+                    r: 0,
+                    root: tree.root(),
+                    fragment: Fragment {
+                        index,
+                        data,
+                        proof: tree.proof(index),
+                    },
+                },
+                consumed: false,
+            })
+            .collect();
+        while let Some(packet) = in_flight.pop() {
+            let dst = members.iter().position(|m| *m == packet.dst).unwrap();
+            let packets = avid.proc_message(&mut states[dst], packet.src, packet.msg);
+            assert!(packets
+                .iter()
+                .all(|packet| !matches!(packet.msg, Message::Ready { .. })));
+            in_flight.extend(packets);
+        }
+        assert!(states.iter().all(|state| avid.outputs(state).is_empty()));
+    }
+}
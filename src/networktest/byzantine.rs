@@ -0,0 +1,389 @@
+use crate::networktest::rb_protocol::{Message, Packet};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+// byzantine node strategies for reliable broadcast.
+//
+// a byzantine node doesn't run the lean protocol at all. it crafts raw `Packet`s instead,
+// within the limits of `RBAdversary` in `ReliableBroadcast.lean`: it may send ANY packet,
+// as long as it doesn't forge the origin, i.e. `packet.src` must be its own address.
+// whoever delivers the packets (the simulator, or the libp2p driver) enforces that,
+// so a strategy that tries anyway just gets its forgeries dropped.
+
+/// What a byzantine node knows about the protocol it's attacking.
+pub struct NodeContext<'a> {
+    pub address: &'a str,
+    pub nodes: &'a [String],
+}
+
+impl NodeContext<'_> {
+    // sends `msg` to every node (including ourselves, like `Packet.broadcast` does).
+    fn to_all(&self, msg: Message) -> Vec<Packet> {
+        self.nodes
+            .iter()
+            .map(|node| self.to(node, msg.clone()))
+            .collect()
+    }
+
+    fn to(&self, dst: &str, msg: Message) -> Packet {
+        Packet {
+            src: self.address.to_string(),
+            dst: dst.to_string(),
+            msg,
+            consumed: false,
+        }
+    }
+}
+
+pub trait ByzantineStrategy: Send {
+    /// We are the leader, and have been asked to broadcast `value` in `round`.
+    fn on_broadcast(&mut self, ctx: &NodeContext, round: usize, value: &str) -> Vec<Packet>;
+
+    /// A packet has arrived for us.
+    fn on_packet(&mut self, ctx: &NodeContext, packet: &Packet) -> Vec<Packet>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ByzantineKind {
+    #[serde(rename = "silent")]
+    Silent,
+    #[serde(rename = "equivocate")]
+    EquivocatingLeader,
+    #[serde(rename = "spam")]
+    EchoVoteSpam,
+    #[serde(rename = "replay")]
+    Replay,
+}
+
+impl FromStr for ByzantineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "silent" => Ok(ByzantineKind::Silent),
+            "equivocate" => Ok(ByzantineKind::EquivocatingLeader),
+            "spam" => Ok(ByzantineKind::EchoVoteSpam),
+            "replay" => Ok(ByzantineKind::Replay),
+            other => Err(format!(
+                "unknown byzantine strategy: {other} (expected silent, equivocate, spam or replay)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ByzantineKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ByzantineKind::Silent => "silent",
+            ByzantineKind::EquivocatingLeader => "equivocate",
+            ByzantineKind::EchoVoteSpam => "spam",
+            ByzantineKind::Replay => "replay",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl ByzantineKind {
+    pub fn build(&self) -> Box<dyn ByzantineStrategy> {
+        match self {
+            ByzantineKind::Silent => Box::new(Silent),
+            ByzantineKind::EquivocatingLeader => Box::<EquivocatingLeader>::default(),
+            ByzantineKind::EchoVoteSpam => Box::<EchoVoteSpam>::default(),
+            ByzantineKind::Replay => Box::<Replay>::default(),
+        }
+    }
+}
+
+// crashed from the start: never says anything.
+pub struct Silent;
+
+impl ByzantineStrategy for Silent {
+    fn on_broadcast(&mut self, _: &NodeContext, _: usize, _: &str) -> Vec<Packet> {
+        Vec::new()
+    }
+
+    fn on_packet(&mut self, _: &NodeContext, _: &Packet) -> Vec<Packet> {
+        Vec::new()
+    }
+}
+
+// a leader that tells the first half of the nodes one value and the second half another,
+// then keeps backing each half up with echoes and votes for the value that half got.
+#[derive(Default)]
+pub struct EquivocatingLeader {
+    // round -> the value each node was told
+    told: HashMap<usize, Vec<String>>,
+    backed_up: HashSet<usize>,
+}
+
+impl ByzantineStrategy for EquivocatingLeader {
+    fn on_broadcast(&mut self, ctx: &NodeContext, round: usize, value: &str) -> Vec<Packet> {
+        let half = ctx.nodes.len() / 2;
+        let values: Vec<String> = (0..ctx.nodes.len())
+            .map(|i| {
+                if i < half {
+                    value.to_string()
+                } else {
+                    format!("{value} (equivocated)")
+                }
+            })
+            .collect();
+
+        let packets = ctx
+            .nodes
+            .iter()
+            .zip(values.iter())
+            .map(|(node, v)| {
+                ctx.to(
+                    node,
+                    Message::InitialMsg {
+                        r: round,
+                        v: v.clone(),
+                    },
+                )
+            })
+            .collect();
+        self.told.insert(round, values);
+        packets
+    }
+
+    fn on_packet(&mut self, ctx: &NodeContext, packet: &Packet) -> Vec<Packet> {
+        let round = packet.msg.get_round();
+        let Some(values) = self.told.get(&round) else {
+            return Vec::new();
+        };
+        if !self.backed_up.insert(round) {
+            return Vec::new();
+        }
+
+        let originator = ctx.address.to_string();
+        ctx.nodes
+            .iter()
+            .zip(values.iter())
+            .flat_map(|(node, v)| {
+                let echo = Message::EchoMsg {
+                    originator: originator.clone(),
+                    r: round,
+                    v: v.clone(),
+                };
+                let vote = Message::VoteMsg {
+                    originator: originator.clone(),
+                    r: round,
+                    v: v.clone(),
+                };
+                [ctx.to(node, echo), ctx.to(node, vote)]
+            })
+            .collect()
+    }
+}
+
+// echoes and votes for a value nobody ever proposed, in every broadcast it hears about.
+#[derive(Default)]
+pub struct EchoVoteSpam {
+    spammed: HashSet<(String, usize)>,
+}
+
+impl ByzantineStrategy for EchoVoteSpam {
+    fn on_broadcast(&mut self, ctx: &NodeContext, round: usize, value: &str) -> Vec<Packet> {
+        // as the leader, we at least start the broadcast, so there is something to spam.
+        ctx.to_all(Message::InitialMsg {
+            r: round,
+            v: value.to_string(),
+        })
+    }
+
+    fn on_packet(&mut self, ctx: &NodeContext, packet: &Packet) -> Vec<Packet> {
+        let (originator, r) = match &packet.msg {
+            Message::InitialMsg { r, .. } => (packet.src.clone(), *r),
+            Message::EchoMsg { originator, r, .. } | Message::VoteMsg { originator, r, .. } => {
+                (originator.clone(), *r)
+            }
+        };
+        if !self.spammed.insert((originator.clone(), r)) {
+            return Vec::new();
+        }
+
+        let v = format!("never proposed (round {r})");
+        let mut packets = ctx.to_all(Message::EchoMsg {
+            originator: originator.clone(),
+            r,
+            v: v.clone(),
+        });
+        packets.extend(ctx.to_all(Message::VoteMsg { originator, r, v }));
+        packets
+    }
+}
+
+// remembers everything it hears, and once a new round starts,
+// replays every message from the previous rounds (under its own name, since it can't forge).
+#[derive(Default)]
+pub struct Replay {
+    heard: Vec<Message>,
+    latest_round: Option<usize>,
+}
+
+impl ByzantineStrategy for Replay {
+    fn on_broadcast(&mut self, ctx: &NodeContext, round: usize, value: &str) -> Vec<Packet> {
+        ctx.to_all(Message::InitialMsg {
+            r: round,
+            v: value.to_string(),
+        })
+    }
+
+    fn on_packet(&mut self, ctx: &NodeContext, packet: &Packet) -> Vec<Packet> {
+        let round = packet.msg.get_round();
+        let new_round = self.latest_round.map_or(true, |latest| round > latest);
+        self.heard.push(packet.msg.clone());
+        if !new_round {
+            return Vec::new();
+        }
+        self.latest_round = Some(round);
+
+        self.heard
+            .iter()
+            .filter(|msg| msg.get_round() < round)
+            .flat_map(|msg| ctx.to_all(msg.clone()))
+            .collect()
+    }
+}
+
+/// Parses a set of byzantine nodes, e.g. `1=silent,3=spam`.
+pub fn parse_assignments(s: &str) -> Result<Vec<(usize, ByzantineKind)>, String> {
+    s.split(',')
+        .map(|assignment| {
+            let (node, kind) = assignment
+                .split_once('=')
+                .ok_or_else(|| format!("expected <node>=<strategy>, got {assignment}"))?;
+            let node = node
+                .parse()
+                .map_err(|e| format!("invalid node index {node}: {e}"))?;
+            Ok((node, kind.parse()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networktest::simulator::{simulate, SimConfig};
+    use std::collections::BTreeMap;
+
+    // runs RB with `kind` on as many nodes as it tolerates (f < N/3), once with a byzantine leader
+    // and once with byzantine followers, and checks that the honest nodes still satisfy RB.
+    fn assert_rb_holds_against(kind: ByzantineKind) {
+        for nodes in [4, 7] {
+            let f = (nodes - 1) / 3;
+            for faulty in [0..f, nodes - f..nodes] {
+                for seed in 0..10 {
+                    let byzantine: BTreeMap<usize, ByzantineKind> =
+                        faulty.clone().map(|node| (node, kind)).collect();
+                    let report = simulate(SimConfig {
+                        nodes,
+                        rounds: 2,
+                        seed,
+                        byzantine,
+                        ..SimConfig::default()
+                    });
+                    assert!(report.is_ok(), "{kind} on nodes {faulty:?}: {report}");
+                    // strategies stay within what `RBAdversary` allows.
+                    assert_eq!(report.forgeries, 0, "{kind} forged packets: {report}");
+
+                    // with an honest leader, every honest node delivers both rounds.
+                    if !faulty.contains(&0) {
+                        assert_eq!(report.deliveries.len(), 2 * (nodes - f), "{report}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn silent_nodes_dont_break_rb() {
+        assert_rb_holds_against(ByzantineKind::Silent);
+    }
+
+    #[test]
+    fn equivocating_leaders_dont_break_rb() {
+        assert_rb_holds_against(ByzantineKind::EquivocatingLeader);
+    }
+
+    #[test]
+    fn echo_and_vote_spam_doesnt_break_rb() {
+        assert_rb_holds_against(ByzantineKind::EchoVoteSpam);
+    }
+
+    #[test]
+    fn replayed_messages_dont_break_rb() {
+        assert_rb_holds_against(ByzantineKind::Replay);
+    }
+
+    #[test]
+    fn equivocating_leaders_tell_each_half_something_else() {
+        let nodes: Vec<String> = (0..4).map(|i| format!("node{i}")).collect();
+        let ctx = NodeContext {
+            address: &nodes[0],
+            nodes: &nodes,
+        };
+        let mut leader = EquivocatingLeader::default();
+        let told: Vec<(String, String)> = leader
+            .on_broadcast(&ctx, 0, "hello")
+            .into_iter()
+            .map(|packet| match packet.msg {
+                Message::InitialMsg { v, .. } if packet.src == nodes[0] => (packet.dst, v),
+                other => panic!("unexpected {other:?} from {}", packet.src),
+            })
+            .collect();
+        assert_eq!(
+            told,
+            vec![
+                (nodes[0].clone(), String::from("hello")),
+                (nodes[1].clone(), String::from("hello")),
+                (nodes[2].clone(), String::from("hello (equivocated)")),
+                (nodes[3].clone(), String::from("hello (equivocated)")),
+            ]
+        );
+    }
+
+    #[test]
+    fn strategies_parse_from_their_names() {
+        for kind in [
+            ByzantineKind::Silent,
+            ByzantineKind::EquivocatingLeader,
+            ByzantineKind::EchoVoteSpam,
+            ByzantineKind::Replay,
+        ] {
+            assert_eq!(kind.to_string().parse(), Ok(kind));
+        }
+    }
+
+    #[test]
+    fn assignments_parse() {
+        assert_eq!(
+            parse_assignments("1=silent,3=spam"),
+            Ok(vec![
+                (1, ByzantineKind::Silent),
+                (3, ByzantineKind::EchoVoteSpam)
+            ])
+        );
+        assert_eq!(
+            parse_assignments("0=equivocate"),
+            Ok(vec![(0, ByzantineKind::EquivocatingLeader)])
+        );
+    }
+
+    #[test]
+    fn bad_assignments_are_reported() {
+        let error = |s: &str| parse_assignments(s).unwrap_err();
+        assert_eq!(error("1"), "expected <node>=<strategy>, got 1");
+        assert_eq!(error(""), "expected <node>=<strategy>, got ");
+        assert_eq!(error("1=silent,"), "expected <node>=<strategy>, got ");
+        assert!(error("x=silent").starts_with("invalid node index x: "));
+        assert!(error("-1=silent").starts_with("invalid node index -1: "));
+        assert_eq!(
+            error("1=silent,2=sneaky"),
+            "unknown byzantine strategy: sneaky (expected silent, equivocate, spam or replay)"
+        );
+        // spaces aren't trimmed
+        assert!(error("1 = silent").starts_with("invalid node index 1 : "));
+    }
+}
@@ -0,0 +1,434 @@
+use super::network_protocol::{DrivenProtocol, NetworkProtocol};
+use super::rb_protocol::{self, Delivery};
+use libp2p::identity::Keypair;
+
+// consistent broadcast, as specified in `lib/ConsistentBroadcast.lean` and exported by
+// `lib/CBProtocol.lean`. it's RB without the vote phase (see the lean file), so the messages
+// and the binding mirror `rb_protocol`'s, minus `VoteMsg`.
+//
+// there's no native port of it, so this only exists with lean.
+
+// the messages and packets of `ConsistentBroadcast.lean`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    InitialMsg {
+        r: usize,
+        v: String,
+    },
+    EchoMsg {
+        originator: String,
+        r: usize,
+        v: String,
+    },
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::InitialMsg { r, v } => write!(f, "InitialMsg @ round {}: {}", r, v),
+            Message::EchoMsg { originator, r, v } => {
+                write!(f, "EchoMsg from {} @ round {}: {}", originator, r, v)
+            }
+        }
+    }
+}
+
+impl Message {
+    pub fn get_round(&self) -> usize {
+        match &self {
+            Self::InitialMsg { r, .. } | Self::EchoMsg { r, .. } => *r,
+        }
+    }
+
+    // CB's messages are a subset of RB's, so they can show up in RB's traces.
+    fn to_rb(&self) -> rb_protocol::Message {
+        match self.clone() {
+            Self::InitialMsg { r, v } => rb_protocol::Message::InitialMsg { r, v },
+            Self::EchoMsg { originator, r, v } => {
+                rb_protocol::Message::EchoMsg { originator, r, v }
+            }
+        }
+    }
+}
+
+pub type Packet = super::network_protocol::Packet<String, Message>;
+
+pub mod lean {
+    use crate::ffitest::lean_helpers::{self, lean_string_to_rust, rust_string_to_lean, Mode};
+    use lean_sys::*;
+    use std::cell::RefCell;
+
+    pub use super::{Message, Packet};
+
+    // CB is linked into the same fat library as RB, and initialized along with it.
+    pub use crate::networktest::rb_protocol::lean::initialize;
+
+    #[link(name = "ProtocolFat", kind = "static")]
+    extern "C" {
+        fn cb_create_protocol(node_arr: lean_sys::lean_obj_arg) -> lean_sys::lean_obj_res;
+        fn cb_create_initial(r: usize, v: lean_sys::lean_obj_arg) -> lean_sys::lean_obj_res;
+        fn cb_create_echo(
+            originator: lean_sys::lean_obj_arg,
+            r: usize,
+            v: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+        fn cb_create_packet(
+            src: lean_sys::lean_obj_arg,
+            dst: lean_sys::lean_obj_arg,
+            msg: lean_sys::lean_obj_arg,
+            consumed: u8,
+        ) -> lean_sys::lean_obj_res;
+        fn cb_init_node_state(
+            p: lean_sys::lean_obj_arg,
+            node_address: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+        fn cb_send_message(
+            p: lean_sys::lean_obj_arg,
+            node_state: lean_sys::lean_obj_arg,
+            round: usize,
+        ) -> lean_sys::lean_obj_res;
+        fn cb_handle_message(
+            p: lean_sys::lean_obj_arg,
+            node_state: lean_sys::lean_obj_arg,
+            src: lean_sys::lean_obj_arg,
+            msg: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+        fn cb_check_output(
+            node_state: lean_sys::lean_obj_arg,
+            leader: lean_sys::lean_obj_arg,
+            round: usize,
+        ) -> lean_sys::lean_obj_res;
+    }
+
+    // the value of the broadcast that's currently calling into lean on this thread.
+    // lean only asks for it from inside `cb_send_message`, so (unlike RB's global table)
+    // there's nothing to share between threads or nodes.
+    thread_local! {
+        static SENDING: RefCell<Option<String>> = const { RefCell::new(None) };
+    }
+
+    // the callback for `cb_get_node_value` in `CBProtocol.lean`. the address is borrowed,
+    // and always the node that's sending, so we don't need it.
+    #[no_mangle]
+    pub unsafe extern "C" fn cb_get_node_value(
+        _node_address: *mut lean_object,
+    ) -> *mut lean_object {
+        let value = SENDING
+            .with(|sending| sending.borrow().clone())
+            .expect("lean should only ask for a value from inside `cb_send_message`");
+        rust_string_to_lean(value)
+    }
+
+    // `CB.Message` is polymorphic in the round, so the round is boxed,
+    // and the fields are in declaration order:
+    // | InitialMsg { r, v }
+    // | EchoMsg { originator, r, v }
+    impl Message {
+        /// Converts a Lean message to its Rust representation. The message is borrowed.
+        pub unsafe fn from_lean(msg_lean: *mut lean_object) -> Self {
+            match lean_ptr_tag(msg_lean) {
+                0 => Message::InitialMsg {
+                    r: lean_unbox_usize(lean_ctor_get(msg_lean, 0)),
+                    v: lean_string_to_rust(lean_ctor_get(msg_lean, 1), Mode::Borrow),
+                },
+                1 => Message::EchoMsg {
+                    originator: lean_string_to_rust(lean_ctor_get(msg_lean, 0), Mode::Borrow),
+                    r: lean_unbox_usize(lean_ctor_get(msg_lean, 1)),
+                    v: lean_string_to_rust(lean_ctor_get(msg_lean, 2), Mode::Borrow),
+                },
+                _ => panic!("unexpected tag"),
+            }
+        }
+
+        // Takes ownership of the Rust Message.
+        pub unsafe fn to_lean(self) -> *mut lean_object {
+            match self {
+                Self::InitialMsg { r, v } => cb_create_initial(r, rust_string_to_lean(v)),
+                Self::EchoMsg { originator, r, v } => {
+                    cb_create_echo(rust_string_to_lean(originator), r, rust_string_to_lean(v))
+                }
+            }
+        }
+    }
+
+    impl Packet {
+        /// Converts a Lean packet to its Rust representation. The packet is borrowed.
+        pub unsafe fn from_lean(packet_lean: *mut lean_object) -> Self {
+            let consumed_lean_offset: std::ffi::c_uint =
+                (3 * lean_helpers::VOID_PTR_SIZE).try_into().unwrap();
+            Packet {
+                src: lean_string_to_rust(lean_ctor_get(packet_lean, 0), Mode::Borrow),
+                dst: lean_string_to_rust(lean_ctor_get(packet_lean, 1), Mode::Borrow),
+                msg: Message::from_lean(lean_ctor_get(packet_lean, 2)),
+                consumed: lean_ctor_get_uint8(packet_lean, consumed_lean_offset) != 0,
+            }
+        }
+
+        // Takes ownership of the rust packet.
+        pub unsafe fn to_lean(self) -> *mut lean_object {
+            cb_create_packet(
+                rust_string_to_lean(self.src),
+                rust_string_to_lean(self.dst),
+                Message::to_lean(self.msg),
+                self.consumed as u8,
+            )
+        }
+    }
+
+    /// One node running the lean CB.
+    pub struct Protocol {
+        protocol: *mut lean_object,
+        node_state: *mut lean_object,
+        round: usize,
+        leader: String,
+    }
+
+    impl Protocol {
+        pub unsafe fn create(node_list: Vec<String>, address: String, leader: String) -> Self {
+            let node_array_lean = lean_helpers::rust_string_vec_to_lean_array(node_list);
+            let protocol = cb_create_protocol(node_array_lean);
+
+            // RC: exports take ownership of their arguments, and we keep using `protocol`.
+            lean_inc(protocol);
+            let node_state = cb_init_node_state(protocol, rust_string_to_lean(address));
+
+            Protocol {
+                protocol,
+                node_state,
+                round: 0,
+                leader,
+            }
+        }
+
+        /// Deconstructs a Lean (new_state, packets_to_send) tuple into its Rust representation.
+        /// This function TAKES OWNERSHIP of `state_and_packets`, and returns ownership of the new state.
+        unsafe fn deconstruct_state_and_packets(
+            state_and_packets: *mut lean_object,
+        ) -> (*mut lean_object, Vec<Packet>) {
+            assert!(lean_is_ctor(state_and_packets));
+            let new_state = lean_ctor_get(state_and_packets, 0);
+            let packets_arr_lean = lean_ctor_get(state_and_packets, 1);
+            assert!(lean_is_array(packets_arr_lean));
+
+            let packets = (0..lean_array_size(packets_arr_lean))
+                .map(|i| Packet::from_lean(lean_array_get_core(packets_arr_lean, i)))
+                .collect();
+
+            // RC: keep the state alive, and free the tuple along with the packets.
+            lean_inc(new_state);
+            lean_dec(state_and_packets);
+            (new_state, packets)
+        }
+
+        /// Broadcasts `value` in this node's next round.
+        pub unsafe fn send_message(&mut self, value: String) -> Vec<Packet> {
+            SENDING.with(|sending| *sending.borrow_mut() = Some(value));
+            lean_inc(self.protocol);
+            let state_and_packets = cb_send_message(self.protocol, self.node_state, self.round);
+            SENDING.with(|sending| *sending.borrow_mut() = None);
+
+            let (new_state, packets) = Self::deconstruct_state_and_packets(state_and_packets);
+            self.node_state = new_state;
+            self.round += 1;
+            packets
+        }
+
+        pub unsafe fn handle_packet(&mut self, packet: Packet) -> Vec<Packet> {
+            let src_lean = rust_string_to_lean(packet.src);
+            let msg_lean = packet.msg.to_lean();
+            lean_inc(self.protocol);
+            let state_and_packets =
+                cb_handle_message(self.protocol, self.node_state, src_lean, msg_lean);
+            let (new_state, packets) = Self::deconstruct_state_and_packets(state_and_packets);
+            self.node_state = new_state;
+            packets
+        }
+
+        /// Returns the value output by this node for the leader's broadcast in `round`, if any.
+        pub unsafe fn check_output(&self, round: usize) -> Option<String> {
+            lean_inc(self.node_state);
+            let output = cb_check_output(
+                self.node_state,
+                rust_string_to_lean(self.leader.clone()),
+                round,
+            );
+            let values = lean_helpers::lean_string_array_to_rust(output);
+            lean_dec(output);
+            values.into_iter().next()
+        }
+    }
+
+    impl Drop for Protocol {
+        fn drop(&mut self) {
+            unsafe {
+                lean_dec(self.protocol);
+                lean_dec(self.node_state);
+            }
+        }
+    }
+}
+
+/// CB as a `NetworkProtocol`, for the driver to run.
+pub struct ConsistentBroadcast {
+    pub node_list: Vec<String>,
+    pub leader: String,
+}
+
+pub struct CBState {
+    address: String,
+    protocol: lean::Protocol,
+    deliveries: Vec<Delivery>,
+}
+
+impl NetworkProtocol for ConsistentBroadcast {
+    type Address = String;
+    type Message = Message;
+    // the value to broadcast, in the leader's next round
+    type InternalTransition = String;
+    type State = CBState;
+    // the same as RB's: a value, from the leader, in a round.
+    type Output = Delivery;
+
+    fn local_init(&self, address: &String) -> CBState {
+        lean::initialize();
+        CBState {
+            address: address.clone(),
+            protocol: unsafe {
+                lean::Protocol::create(self.node_list.clone(), address.clone(), self.leader.clone())
+            },
+            deliveries: Vec::new(),
+        }
+    }
+
+    fn proc_internal(&self, state: &mut CBState, value: String) -> Vec<Packet> {
+        unsafe { state.protocol.send_message(value) }
+    }
+
+    fn proc_message(&self, state: &mut CBState, src: String, msg: Message) -> Vec<Packet> {
+        let round = msg.get_round();
+        let packet = Packet {
+            src,
+            dst: state.address.clone(),
+            msg,
+            consumed: false,
+        };
+        let packets = unsafe { state.protocol.handle_packet(packet) };
+
+        // a round is delivered at most once.
+        let delivered = state
+            .deliveries
+            .iter()
+            .any(|delivery| delivery.round == round);
+        if !delivered {
+            if let Some(value) = unsafe { state.protocol.check_output(round) } {
+                state.deliveries.push(Delivery {
+                    leader: self.leader.clone(),
+                    round,
+                    value,
+                });
+            }
+        }
+        packets
+    }
+
+    fn outputs(&self, state: &CBState) -> Vec<Delivery> {
+        state.deliveries.clone()
+    }
+}
+
+impl DrivenProtocol for ConsistentBroadcast {
+    type Config = ();
+
+    fn create(
+        _config: (),
+        node_list: Vec<String>,
+        leader: String,
+        _keypair: &Keypair,
+    ) -> Result<Self, String> {
+        Ok(ConsistentBroadcast { node_list, leader })
+    }
+
+    fn broadcast(&self, _state: &mut CBState, value: String) -> String {
+        value
+    }
+
+    fn traced_packet(packet: &Packet) -> Option<rb_protocol::Packet> {
+        Some(rb_protocol::Packet {
+            src: packet.src.clone(),
+            dst: packet.dst.clone(),
+            msg: packet.msg.to_rb(),
+            consumed: packet.consumed,
+        })
+    }
+
+    fn traced_output(delivery: &Delivery) -> Option<Delivery> {
+        Some(delivery.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networktest::simulator::node_address;
+    use std::collections::VecDeque;
+
+    // runs one broadcast from node 0 to completion, dropping every packet `drop` matches.
+    // returns how many packets were sent, and what every node delivered.
+    fn run(n: usize, drop: impl Fn(&Packet) -> bool) -> (usize, Vec<Vec<Delivery>>) {
+        let members: Vec<String> = (0..n).map(node_address).collect();
+        let cb = ConsistentBroadcast {
+            node_list: members.clone(),
+            leader: members[0].clone(),
+        };
+        let mut nodes: Vec<_> = members.iter().map(|m| cb.local_init(m)).collect();
+
+        let mut sent = 0;
+        let mut in_flight: VecDeque<_> = cb
+            .proc_internal(&mut nodes[0], String::from("hello"))
+            .into();
+        while let Some(packet) = in_flight.pop_front() {
+            sent += 1;
+            if drop(&packet) {
+                continue;
+            }
+            let dst = members.iter().position(|m| *m == packet.dst).unwrap();
+            in_flight.extend(cb.proc_message(&mut nodes[dst], packet.src, packet.msg));
+        }
+
+        let outputs = nodes.iter().map(|node| cb.outputs(node)).collect();
+        (sent, outputs)
+    }
+
+    #[test]
+    fn everyone_delivers_after_one_echo_phase() {
+        // N = 4: the leader's N initial messages, then N echoes from each node.
+        let (sent, outputs) = run(4, |_| false);
+        assert_eq!(sent, 4 + 4 * 4);
+        for output in outputs {
+            assert_eq!(
+                output,
+                vec![Delivery {
+                    leader: node_address(0),
+                    round: 0,
+                    value: String::from("hello"),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn needs_more_than_half_plus_f_echoes() {
+        // N = 4, f = 1: ⌈(4 + 1 + 1) / 2⌉ = 3 echoes. with node 3 cut off from the leader,
+        // everyone still gets 3 echoes, but with node 2 cut off too, only 2 nodes echo.
+        let (_, outputs) = run(4, |packet| {
+            matches!(packet.msg, Message::InitialMsg { .. }) && packet.dst == node_address(3)
+        });
+        assert!(outputs.iter().all(|output| output.len() == 1));
+
+        let (_, outputs) = run(4, |packet| {
+            matches!(packet.msg, Message::InitialMsg { .. })
+                && [node_address(2), node_address(3)].contains(&packet.dst)
+        });
+        assert!(outputs.iter().all(|output| output.is_empty()));
+    }
+}
@@ -0,0 +1,311 @@
+use crate::networktest::rb_native;
+use crate::networktest::rb_protocol::{lean, Message, Packet};
+use crate::networktest::simulator::node_address;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt::Display;
+
+// differential testing between the compiled lean RB (`rb_protocol::lean`) and its native port
+// (`rb_native`).
+//
+// every node runs both implementations side by side. they start from the same broadcasts,
+// and are fed exactly the same packets in exactly the same order, so at every step they must
+// send out the same packets (in the same order) and have output the same values.
+// the first step where they don't is reported, along with the packets that led up to it.
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DiffConfig {
+    pub nodes: usize,
+    pub rounds: usize,
+    // step `i` delivers the `schedule[i]`-th packet in flight (modulo the number in flight).
+    // once the script runs out, packets are delivered oldest first.
+    pub schedule: Vec<usize>,
+    // extra packets put in flight at the start, e.g. ones no honest node would send
+    pub injected: Vec<Packet>,
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub step: usize,
+    pub what: String,
+    pub lean: String,
+    pub native: String,
+    pub trace: Vec<String>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "lean and native diverged at step {}: {}",
+            self.step, self.what
+        )?;
+        writeln!(f, "  lean:   {}", self.lean)?;
+        writeln!(f, "  native: {}", self.native)?;
+        writeln!(f, "  trace:")?;
+        for (step, packet) in self.trace.iter().enumerate() {
+            writeln!(f, "    {step}: {packet}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a run didn't end with both implementations agreeing.
+#[derive(Debug)]
+pub enum Failure {
+    Diverged(Divergence),
+    // packets were still in flight after `MAX_STEPS` steps, so we never got to compare the end.
+    Unfinished { in_flight: usize },
+}
+
+impl From<Divergence> for Failure {
+    fn from(divergence: Divergence) -> Self {
+        Failure::Diverged(divergence)
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Diverged(divergence) => divergence.fmt(f),
+            Failure::Unfinished { in_flight } => writeln!(
+                f,
+                "{in_flight} packets still in flight after {MAX_STEPS} steps"
+            ),
+        }
+    }
+}
+
+const MAX_STEPS: usize = 100_000;
+
+struct Pair {
+    lean: lean::Protocol,
+    native: rb_native::Protocol,
+}
+
+fn compare<T: PartialEq + std::fmt::Debug>(
+    step: usize,
+    what: impl FnOnce() -> String,
+    lean: &T,
+    native: &T,
+    trace: &[String],
+) -> Result<(), Divergence> {
+    if lean == native {
+        return Ok(());
+    }
+    Err(Divergence {
+        step,
+        what: what(),
+        lean: format!("{lean:?}"),
+        native: format!("{native:?}"),
+        trace: trace.to_vec(),
+    })
+}
+
+/// Runs both implementations on the same schedule. Returns the number of steps taken.
+pub fn run(config: &DiffConfig) -> Result<usize, Failure> {
+    lean::initialize();
+
+    let addresses: Vec<String> = (0..config.nodes).map(node_address).collect();
+    let leader = addresses[0].clone();
+    let mut nodes: Vec<Pair> = addresses
+        .iter()
+        .map(|address| Pair {
+            lean: unsafe {
+                lean::Protocol::create(addresses.clone(), address.clone(), leader.clone())
+            },
+            native: rb_native::Protocol::create(addresses.clone(), address.clone(), leader.clone()),
+        })
+        .collect();
+
+    let result = run_nodes(config, &addresses, &mut nodes);
+    // however the run ended, the lean objects would leak otherwise.
+    for node in nodes {
+        unsafe { node.lean.release() };
+    }
+    result
+}
+
+fn run_nodes(
+    config: &DiffConfig,
+    addresses: &[String],
+    nodes: &mut [Pair],
+) -> Result<usize, Failure> {
+    let leader = &addresses[0];
+    let mut trace = Vec::new();
+    let mut in_flight = Vec::new();
+    for round in 0..config.rounds {
+        let value = format!("v{round}");
+        let from_lean = unsafe { nodes[0].lean.send_message(leader.clone(), value.clone()) };
+        let from_native = nodes[0].native.send_message(leader.clone(), value);
+        compare(
+            0,
+            || format!("broadcast of round {round}"),
+            &from_lean,
+            &from_native,
+            &trace,
+        )?;
+        in_flight.extend(from_lean);
+    }
+    in_flight.extend(config.injected.iter().cloned());
+
+    let mut step = 0;
+    while !in_flight.is_empty() && step < MAX_STEPS {
+        let i = config
+            .schedule
+            .get(step)
+            .map_or(0, |choice| choice % in_flight.len());
+        let packet = in_flight.remove(i);
+        step += 1;
+
+        let Some(dst) = addresses.iter().position(|a| *a == packet.dst) else {
+            continue;
+        };
+        trace.push(packet.to_string());
+        let node = &mut nodes[dst];
+
+        let from_lean = unsafe { node.lean.handle_packet(packet.clone()) };
+        let from_native = node.native.handle_packet(packet);
+        compare(
+            step,
+            || String::from("packets sent"),
+            &from_lean,
+            &from_native,
+            &trace,
+        )?;
+
+        for round in 0..config.rounds {
+            let lean_output = unsafe { node.lean.check_output(round) };
+            let native_output = node.native.check_output(round);
+            compare(
+                step,
+                || format!("{} output for round {round}", addresses[dst]),
+                &lean_output,
+                &native_output,
+                &trace,
+            )?;
+        }
+
+        in_flight.extend(from_lean);
+    }
+
+    if !in_flight.is_empty() {
+        return Err(Failure::Unfinished {
+            in_flight: in_flight.len(),
+        });
+    }
+    Ok(step)
+}
+
+// a mix of plausible and implausible packets, from any node to any node
+fn random_packet(rng: &mut StdRng, nodes: usize, rounds: usize) -> Packet {
+    let src = node_address(rng.gen_range(0..nodes));
+    let dst = node_address(rng.gen_range(0..nodes));
+    let originator = node_address(rng.gen_range(0..nodes));
+    let r = rng.gen_range(0..rounds + 1);
+    let v = format!("v{}", rng.gen_range(0..rounds + 1));
+    let msg = match rng.gen_range(0..3) {
+        0 => Message::InitialMsg { r, v },
+        1 => Message::EchoMsg { originator, r, v },
+        _ => Message::VoteMsg { originator, r, v },
+    };
+    Packet {
+        src,
+        dst,
+        msg,
+        consumed: false,
+    }
+}
+
+pub fn random_config(nodes: usize, rounds: usize, seed: u64) -> DiffConfig {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let schedule = (0..1000).map(|_| rng.gen()).collect();
+    let injected_count = rng.gen_range(0..=nodes * 2);
+    let injected = (0..injected_count)
+        .map(|_| random_packet(&mut rng, nodes, rounds))
+        .collect();
+    DiffConfig {
+        nodes,
+        rounds,
+        schedule,
+        injected,
+    }
+}
+
+// cargo run -- diff [nodes] [rounds] [first seed] [number of seeds]
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let arg = |i: usize, default: u64| -> u64 {
+        args.get(i)
+            .map(|a| a.parse().expect("arguments should be numbers"))
+            .unwrap_or(default)
+    };
+    let nodes = arg(0, 4) as usize;
+    let rounds = arg(1, 1) as usize;
+    let first_seed = arg(2, 0);
+    let seeds = arg(3, 100);
+
+    let mut failures = 0;
+    for seed in first_seed..first_seed + seeds {
+        match run(&random_config(nodes, rounds, seed)) {
+            Ok(steps) => println!("seed {seed}: lean and native agree over {steps} steps"),
+            Err(failure) => {
+                failures += 1;
+                print!("seed {seed}: {failure}");
+            }
+        }
+    }
+    println!("{failures} of {seeds} runs failed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn packet(nodes: usize) -> impl Strategy<Value = Packet> {
+        let address = (0..nodes).prop_map(node_address);
+        let value = (0usize..3).prop_map(|v| format!("v{v}"));
+        let msg =
+            prop_oneof![
+                (0usize..3, value.clone()).prop_map(|(r, v)| Message::InitialMsg { r, v }),
+                (address.clone(), 0usize..3, value.clone())
+                    .prop_map(|(originator, r, v)| Message::EchoMsg { originator, r, v }),
+                (address.clone(), 0usize..3, value)
+                    .prop_map(|(originator, r, v)| Message::VoteMsg { originator, r, v }),
+            ];
+        (address.clone(), address, msg).prop_map(|(src, dst, msg)| Packet {
+            src,
+            dst,
+            msg,
+            consumed: false,
+        })
+    }
+
+    fn config() -> impl Strategy<Value = DiffConfig> {
+        (1usize..=7, 1usize..=2).prop_flat_map(|(nodes, rounds)| {
+            (
+                prop::collection::vec(any::<usize>(), 0..400),
+                prop::collection::vec(packet(nodes), 0..10),
+            )
+                .prop_map(move |(schedule, injected)| DiffConfig {
+                    nodes,
+                    rounds,
+                    schedule,
+                    injected,
+                })
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn lean_and_native_agree(config in config()) {
+            if let Err(failure) = run(&config) {
+                let replay = serde_json::to_string(&config).unwrap();
+                prop_assert!(false, "{failure}config: {replay}");
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use crate::ffitest::lean_helpers::{lean_string_to_rust, rust_string_to_lean, Mode};
+use crate::networktest::network_protocol::{NetworkProtocol, Packet};
+use crate::networktest::rb_protocol::{self, Delivery};
+use lean_sys::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+// the rust side of the JSON bridge (`lib/JsonBridge.lean`, `lib/JsonProtocols.lean`).
+// ===
+// a protocol behind the bridge takes and returns JSON, so serde does all the (de)serializing, and
+// the unsafe code here is the same for every protocol. to plug in a new one, register it in
+// `JsonProtocols.lean`, and implement `JsonProtocol` for a marker type here, with types whose
+// serde encoding matches the lean side's `ToJson`/`FromJson`.
+// (serde's default, externally tagged enums line up with lean's derived instances.)
+//
+// the bridge is built into `ProtocolFat`, so `rb_protocol::initialize` also initializes it.
+
+#[link(name = "ProtocolFat", kind = "static")]
+extern "C" {
+    fn json_create(name: lean_obj_arg, members: lean_obj_arg, me: lean_obj_arg) -> lean_obj_res;
+    fn json_proc_internal(node: lean_obj_arg, transition: lean_obj_arg) -> lean_obj_res;
+    fn json_proc_message(node: lean_obj_arg, src: lean_obj_arg, msg: lean_obj_arg) -> lean_obj_res;
+    fn json_outputs(node: lean_obj_arg) -> lean_obj_res;
+}
+
+/// A protocol registered in `JsonProtocols.lean`.
+pub trait JsonProtocol {
+    /// The name it's registered under.
+    const NAME: &'static str;
+    type Address: Clone + Eq + Debug + Serialize + DeserializeOwned;
+    type Message: Clone + Debug + Serialize + DeserializeOwned;
+    type InternalTransition: Serialize;
+    type Output: Clone + Debug + DeserializeOwned;
+}
+
+/// What every export returns besides the node (see `JsonBridge.result`).
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LeanResult<T> {
+    Ok(T),
+    Error(String),
+}
+
+/// A node behind the bridge. Owns its lean `JsonBridge.Node`.
+pub struct BridgedNode {
+    node: *mut lean_object,
+}
+
+impl Drop for BridgedNode {
+    fn drop(&mut self) {
+        unsafe { lean_dec(self.node) };
+    }
+}
+
+impl BridgedNode {
+    // takes apart the `Node × String` every export but `json_outputs` returns,
+    // keeping the node, and decoding the string.
+    unsafe fn take_result<T: DeserializeOwned>(
+        node_and_result: *mut lean_object,
+    ) -> (*mut lean_object, Result<T, String>) {
+        // RC: `lean_ctor_get` borrows, so we take our own references before dropping the pair.
+        let node = lean_ctor_get(node_and_result, 0);
+        let result = lean_ctor_get(node_and_result, 1);
+        lean_inc(node);
+        let result = lean_string_to_rust(result, Mode::Borrow);
+        lean_dec(node_and_result);
+
+        let result = match serde_json::from_str::<LeanResult<T>>(&result) {
+            Ok(LeanResult::Ok(value)) => Ok(value),
+            Ok(LeanResult::Error(e)) => Err(e),
+            Err(e) => Err(format!("bad result from lean ({e}): {result}")),
+        };
+        (node, result)
+    }
+
+    // runs one export on the node, replacing the node with the one it returns.
+    // the export takes ownership of the node, so we don't bump it.
+    unsafe fn step<T: DeserializeOwned>(
+        &mut self,
+        export: impl FnOnce(*mut lean_object) -> *mut lean_object,
+    ) -> Result<T, String> {
+        let (node, result) = Self::take_result(export(self.node));
+        self.node = node;
+        result
+    }
+}
+
+fn to_lean_json<T: Serialize>(value: &T) -> *mut lean_object {
+    let json = serde_json::to_string(value).expect("should be able to serialize to JSON");
+    unsafe { rust_string_to_lean(json) }
+}
+
+/// A protocol behind the bridge, as a `NetworkProtocol`.
+///
+/// The lean side can only fail if the two sides disagree on the JSON encoding,
+/// which is a bug, so the transitions panic with lean's error.
+pub struct Bridged<J: JsonProtocol> {
+    pub members: Vec<J::Address>,
+    protocol: PhantomData<J>,
+}
+
+impl<J: JsonProtocol> Bridged<J> {
+    pub fn new(members: Vec<J::Address>) -> Self {
+        // the bridge lives in the same fat library as RB.
+        rb_protocol::initialize();
+        Bridged {
+            members,
+            protocol: PhantomData,
+        }
+    }
+}
+
+impl<J: JsonProtocol> NetworkProtocol for Bridged<J> {
+    type Address = J::Address;
+    type Message = J::Message;
+    type InternalTransition = J::InternalTransition;
+    type State = BridgedNode;
+    type Output = J::Output;
+
+    fn local_init(&self, address: &J::Address) -> BridgedNode {
+        rb_protocol::initialize();
+        unsafe {
+            let name = rust_string_to_lean(J::NAME.to_string());
+            let node_and_result =
+                json_create(name, to_lean_json(&self.members), to_lean_json(address));
+            let (node, result) = BridgedNode::take_result::<()>(node_and_result);
+            let node = BridgedNode { node };
+            if let Err(e) = result {
+                panic!("[json_bridge] failed to create a {} node: {e}", J::NAME);
+            }
+            node
+        }
+    }
+
+    fn proc_internal(
+        &self,
+        state: &mut BridgedNode,
+        transition: J::InternalTransition,
+    ) -> Vec<Packet<J::Address, J::Message>> {
+        let transition = to_lean_json(&transition);
+        unsafe { state.step(|node| json_proc_internal(node, transition)) }
+            .unwrap_or_else(|e| panic!("[json_bridge] {} procInternal failed: {e}", J::NAME))
+    }
+
+    fn proc_message(
+        &self,
+        state: &mut BridgedNode,
+        src: J::Address,
+        msg: J::Message,
+    ) -> Vec<Packet<J::Address, J::Message>> {
+        let (src, msg) = (to_lean_json(&src), to_lean_json(&msg));
+        unsafe { state.step(|node| json_proc_message(node, src, msg)) }
+            .unwrap_or_else(|e| panic!("[json_bridge] {} procMessage failed: {e}", J::NAME))
+    }
+
+    fn outputs(&self, state: &BridgedNode) -> Vec<J::Output> {
+        let outputs = unsafe {
+            // RC: `json_outputs` takes ownership of the node, which we keep.
+            lean_inc(state.node);
+            lean_string_to_rust(json_outputs(state.node), Mode::Owned)
+        };
+        serde_json::from_str(&outputs)
+            .unwrap_or_else(|e| panic!("[json_bridge] bad {} outputs ({e}): {outputs}", J::NAME))
+    }
+}
+
+/// RB behind the bridge. Unlike `rb_protocol::Protocol`, the leader's value comes with the
+/// internal transition, as `(round, value)`, so nothing goes through `get_node_value`.
+pub struct Rb;
+
+impl JsonProtocol for Rb {
+    const NAME: &'static str = "rb";
+    type Address = String;
+    type Message = rb_protocol::Message;
+    type InternalTransition = (usize, String);
+    type Output = Delivery;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networktest::simulator::node_address;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn bridged_rb_delivers_everywhere() {
+        let members: Vec<String> = (0..4).map(node_address).collect();
+        let rb = Bridged::<Rb>::new(members.clone());
+        let mut nodes: Vec<_> = members.iter().map(|m| rb.local_init(m)).collect();
+
+        let mut in_flight: VecDeque<_> = rb
+            .proc_internal(&mut nodes[0], (0, String::from("hello")))
+            .into();
+        while let Some(packet) = in_flight.pop_front() {
+            let dst = members.iter().position(|m| *m == packet.dst).unwrap();
+            in_flight.extend(rb.proc_message(&mut nodes[dst], packet.src, packet.msg));
+        }
+
+        for node in &nodes {
+            assert_eq!(
+                rb.outputs(node),
+                vec![Delivery {
+                    leader: members[0].clone(),
+                    round: 0,
+                    value: String::from("hello"),
+                }]
+            );
+        }
+    }
+}
@@ -0,0 +1,509 @@
+use crate::networktest::libp2p_rb::{self, Options, ProtocolKind};
+use crate::networktest::network_protocol::{self, DrivenProtocol, NetworkProtocol};
+use crate::networktest::pb_protocol;
+use crate::protocol::{Message, NodeState, PbMessage, PbPacket, PbParams, ProvableBroadcast};
+use crate::signature::{Committee, Ed25519Multisig, Keyring, SignatureScheme};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+
+// provable broadcast over libp2p (`cargo run -- pb [options]`), running the native `crate::protocol`
+// on the shared driver. it's `rb --protocol pb`, except that certificates are exported by default.
+//
+// the sender's `Init` goes out to every member, and each replica answers with its `Echo`
+// (a partial signature). once `signature_threshold()` echoes are in, the sender's output is the
+// certificate, which the driver exports as JSON (see `ProvableBroadcastNode::export`).
+//
+// nodes sign with the ed25519 key behind their peer id. a member's public key can be read back
+// out of its peer id, so the member list is all anyone needs to check a certificate
+// (as long as they know the members themselves: the certificate's own list proves nothing).
+
+/// A certificate as the sender exports it. Everything in it is a string (or a number),
+/// so it can be checked without this crate's types.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportedCertificate {
+    pub sender: String,
+    pub round: usize,
+    pub value: String,
+    // every member's peer id, sorted.
+    pub members: Vec<String>,
+    pub threshold: usize,
+    // signer's peer id -> hex-encoded partial signature
+    pub signatures: BTreeMap<String, String>,
+}
+
+impl std::fmt::Display for ExportedCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "certificate for '{}' from {} in round {} ({} signatures)",
+            self.value,
+            self.sender,
+            self.round,
+            self.signatures.len()
+        )
+    }
+}
+
+impl ExportedCertificate {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("can't read certificate {}: {e}", path.display()))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("invalid certificate {}: {e}", path.display()))
+    }
+
+    /// Whether enough of `members` signed the sender's value. `members` is who the verifier
+    /// trusts to be taking part: a certificate that names anyone else doesn't check out,
+    /// or it could pick its own members, and with them its own threshold.
+    /// The public keys come from the members' peer ids, and `threshold` is only informative.
+    pub fn verify(&self, members: &[String]) -> bool {
+        let named: BTreeSet<&String> = self.members.iter().collect();
+        if named != members.iter().collect() {
+            return false;
+        }
+        let Ok(public_keys) = public_keys(members) else {
+            return false;
+        };
+        if !public_keys.contains_key(&self.sender) {
+            return false;
+        }
+        let mut signature = BTreeMap::new();
+        for (signer, partial) in &self.signatures {
+            let partial: Option<[u8; 64]> =
+                from_hex(partial).and_then(|bytes| bytes.try_into().ok());
+            match partial {
+                Some(partial) if public_keys.contains_key(signer) => {
+                    signature.insert(signer.clone(), partial);
+                }
+                _ => return false,
+            }
+        }
+        let n = public_keys.len();
+        let committee: Committee<Ed25519Multisig, String> =
+            Committee::new(public_keys, signature_threshold(n));
+        committee.verify_combined(
+            &echo_bytes(&self.sender, self.round, &self.value),
+            &signature,
+        )
+    }
+}
+
+// N - f, with as many byzantine nodes as PB tolerates.
+fn signature_threshold(n: usize) -> usize {
+    n - num_byzantine(n)
+}
+
+fn num_byzantine(n: usize) -> usize {
+    (n - 1) / 3
+}
+
+// every member's public key, by peer id.
+// `ProvableBroadcast` addresses members by their peer ids too, so that's what they sign over.
+fn public_keys(peer_ids: &[String]) -> Result<HashMap<String, VerifyingKey>, String> {
+    let mut public_keys = HashMap::new();
+    for peer_id in peer_ids {
+        let parsed = PeerId::from_str(peer_id)
+            .map_err(|e| format!("invalid member peer id {peer_id}: {e}"))?;
+        let public_key = ed25519_public_key(&parsed)
+            .ok_or_else(|| format!("member {peer_id} doesn't have an ed25519 key"))?;
+        public_keys.insert(peer_id.clone(), public_key);
+    }
+    if public_keys.is_empty() {
+        return Err(String::from("there are no members"));
+    }
+    Ok(public_keys)
+}
+
+// ed25519 peer ids are "identity" multihashes: the protobuf-encoded public key itself,
+// after the hash code (0) and the length.
+fn ed25519_public_key(peer_id: &PeerId) -> Option<VerifyingKey> {
+    let bytes = peer_id.to_bytes();
+    let [0, length, encoded @ ..] = bytes.as_slice() else {
+        return None;
+    };
+    if *length as usize != encoded.len() {
+        return None;
+    }
+    let public_key = libp2p::identity::PublicKey::try_decode_protobuf(encoded).ok()?;
+    let public_key = public_key.try_into_ed25519().ok()?;
+    VerifyingKey::from_bytes(&public_key.to_bytes()).ok()
+}
+
+fn ed25519_secret_key(keypair: &Keypair) -> Result<SigningKey, String> {
+    let keypair = keypair
+        .clone()
+        .try_into_ed25519()
+        .map_err(|e| format!("PB needs an ed25519 keypair: {e}"))?;
+    // the secret key, followed by the public key
+    let bytes = keypair.to_bytes();
+    let secret: [u8; 32] = bytes[..32].try_into().expect("slice is 32 bytes");
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+// what gets signed. the proof and the echoes sign different things, so that the sender's proof
+// can't be passed off as its echo (or the other way round).
+fn proof_bytes(sender: &str, round: usize, value: &str) -> Vec<u8> {
+    format!("pb-proof/{sender}/{round}/{value}").into_bytes()
+}
+
+fn echo_bytes(sender: &str, round: usize, value: &str) -> Vec<u8> {
+    format!("pb-echo/{sender}/{round}/{value}").into_bytes()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// this node's `PbParams`: values come from the command line, the sender's proof is its
+// signature on the value, and every member signs with its own key.
+struct NodeParams {
+    keyring: Keyring<Ed25519Multisig, String>,
+}
+
+impl NodeParams {
+    fn prove(&self, sender: &str, round: usize, value: &str) -> [u8; 64] {
+        self.keyring.sign(&proof_bytes(sender, round, value))
+    }
+}
+
+impl PbParams for NodeParams {
+    type Round = usize;
+    type Value = String;
+    type Proof = [u8; 64];
+    type Address = String;
+    type PartialSignature = [u8; 64];
+    type CombinedSignature = BTreeMap<String, [u8; 64]>;
+
+    // values come with the broadcast (see `ProvableBroadcastNode::proc_internal`),
+    // which starts PB with `send_value`, so PB never has to come up with one.
+    fn value_bft(&self, _sender: &String, _round: &usize) -> (String, [u8; 64]) {
+        unreachable!("PB's broadcasts are started with `send_value`")
+    }
+
+    fn externally_validate(
+        &self,
+        sender: &String,
+        round: &usize,
+        value: &String,
+        proof: &[u8; 64],
+    ) -> bool {
+        self.keyring
+            .committee()
+            .verify_partial(sender, &proof_bytes(sender, *round, value), proof)
+    }
+
+    fn partially_sign(&self, sender: &String, round: &usize, value: &String) -> [u8; 64] {
+        self.keyring.sign(&echo_bytes(sender, *round, value))
+    }
+
+    fn partially_validate(
+        &self,
+        signer: &String,
+        sender: &String,
+        round: &usize,
+        value: &String,
+        signature: &[u8; 64],
+    ) -> bool {
+        self.keyring.committee().verify_partial(
+            signer,
+            &echo_bytes(sender, *round, value),
+            signature,
+        )
+    }
+
+    fn combine_partial_signatures(
+        &self,
+        signatures: Vec<(String, [u8; 64])>,
+    ) -> BTreeMap<String, [u8; 64]> {
+        Ed25519Multisig::combine(signatures)
+    }
+
+    fn verify_combined(
+        &self,
+        sender: &String,
+        round: &usize,
+        value: &String,
+        signature: &BTreeMap<String, [u8; 64]>,
+    ) -> bool {
+        self.keyring
+            .committee()
+            .verify_combined(&echo_bytes(sender, *round, value), signature)
+    }
+}
+
+// this node's PB, among the members with `public_keys`.
+fn provable_broadcast(
+    secret_key: SigningKey,
+    public_keys: HashMap<String, VerifyingKey>,
+) -> ProvableBroadcast<NodeParams> {
+    let n = public_keys.len();
+    let mut members: Vec<String> = public_keys.keys().cloned().collect();
+    members.sort();
+    let committee = Committee::new(public_keys, signature_threshold(n));
+    let params = NodeParams {
+        keyring: Keyring::new(secret_key, committee),
+    };
+    ProvableBroadcast::new(params, members, num_byzantine(n))
+}
+
+// the certificate for `state`'s broadcast in `round`, if it has one.
+fn export_certificate(
+    pb: &ProvableBroadcast<NodeParams>,
+    state: &NodeState<NodeParams>,
+    round: usize,
+) -> Option<ExportedCertificate> {
+    let certificate = state.certificate(&round)?;
+    Some(ExportedCertificate {
+        sender: certificate.sender,
+        round,
+        value: certificate.value,
+        members: pb.node_addresses().to_vec(),
+        threshold: pb.signature_threshold(),
+        signatures: certificate
+            .signature
+            .iter()
+            .map(|(signer, partial)| (signer.clone(), to_hex(partial)))
+            .collect(),
+    })
+}
+
+/// The native PB as a `NetworkProtocol`, so that the shared driver can run it too
+/// (`rb --protocol pb`). Its packets carry the lean PB's `pb_protocol::Message`s, with proofs and
+/// partial signatures hex-encoded, and its outputs are the certificates of its own broadcasts.
+pub struct ProvableBroadcastNode {
+    pb: ProvableBroadcast<NodeParams>,
+}
+
+pub struct ProvableBroadcastState {
+    state: NodeState<NodeParams>,
+    // our next round as the sender
+    next_round: usize,
+    certificates: Vec<ExportedCertificate>,
+}
+
+impl ProvableBroadcastNode {
+    fn to_wire(packet: PbPacket<NodeParams>) -> pb_protocol::Packet {
+        let msg = match packet.msg {
+            Message::Init {
+                round,
+                value,
+                proof,
+            } => pb_protocol::Message::InitMsg {
+                r: round,
+                v: value,
+                proof: to_hex(&proof),
+            },
+            Message::Echo {
+                round,
+                partial_signature,
+            } => pb_protocol::Message::EchoMsg {
+                r: round,
+                partial_signature: to_hex(&partial_signature),
+            },
+        };
+        network_protocol::Packet {
+            src: packet.src,
+            dst: packet.dst,
+            msg,
+            consumed: packet.received,
+        }
+    }
+
+    // `None` if a signature isn't 64 bytes of hex.
+    fn from_wire(msg: pb_protocol::Message) -> Option<PbMessage<NodeParams>> {
+        let signature = |hex: &str| -> Option<[u8; 64]> { from_hex(hex)?.try_into().ok() };
+        match msg {
+            pb_protocol::Message::InitMsg { r, v, proof } => Some(Message::Init {
+                round: r,
+                value: v,
+                proof: signature(&proof)?,
+            }),
+            pb_protocol::Message::EchoMsg {
+                r,
+                partial_signature,
+            } => Some(Message::Echo {
+                round: r,
+                partial_signature: signature(&partial_signature)?,
+            }),
+        }
+    }
+
+    fn certify(&self, state: &mut ProvableBroadcastState, round: usize) {
+        // echoes that arrive after the threshold don't change the output.
+        if state
+            .certificates
+            .iter()
+            .any(|exported| exported.round == round)
+        {
+            return;
+        }
+        if let Some(exported) = export_certificate(&self.pb, &state.state, round) {
+            state.certificates.push(exported);
+        }
+    }
+}
+
+impl NetworkProtocol for ProvableBroadcastNode {
+    type Address = String;
+    type Message = pb_protocol::Message;
+    // (round, value)
+    type InternalTransition = (usize, String);
+    type State = ProvableBroadcastState;
+    type Output = ExportedCertificate;
+
+    fn local_init(&self, address: &String) -> ProvableBroadcastState {
+        assert!(
+            self.pb.node_addresses().contains(address),
+            "a node only runs PB among members it's one of"
+        );
+        ProvableBroadcastState {
+            state: NodeState::new(address.clone()),
+            next_round: 0,
+            certificates: Vec::new(),
+        }
+    }
+
+    fn proc_internal(
+        &self,
+        state: &mut ProvableBroadcastState,
+        (round, value): (usize, String),
+    ) -> Vec<pb_protocol::Packet> {
+        let proof = self
+            .pb
+            .params()
+            .prove(&state.state.address(), round, &value);
+        let packets = self.pb.send_value(&mut state.state, round, value, proof);
+        self.certify(state, round);
+        packets.into_iter().map(Self::to_wire).collect()
+    }
+
+    fn proc_message(
+        &self,
+        state: &mut ProvableBroadcastState,
+        src: String,
+        msg: pb_protocol::Message,
+    ) -> Vec<pb_protocol::Packet> {
+        // (`proc_msg` ignores anyone who isn't a member.)
+        let Some(msg) = Self::from_wire(msg) else {
+            return Vec::new();
+        };
+        let round = match &msg {
+            Message::Init { round, .. } | Message::Echo { round, .. } => *round,
+        };
+        let packets = self.pb.proc_msg(&mut state.state, src, msg);
+        self.certify(state, round);
+        packets.into_iter().map(Self::to_wire).collect()
+    }
+
+    fn outputs(&self, state: &ProvableBroadcastState) -> Vec<ExportedCertificate> {
+        state.certificates.clone()
+    }
+}
+
+impl DrivenProtocol for ProvableBroadcastNode {
+    type Config = ();
+
+    // PB has no leader: every member broadcasts its own values.
+    fn create(
+        _config: (),
+        members: Vec<String>,
+        _leader: String,
+        keypair: &Keypair,
+    ) -> Result<Self, String> {
+        let public_keys = public_keys(&members)?;
+        let pb = provable_broadcast(ed25519_secret_key(keypair)?, public_keys);
+        Ok(ProvableBroadcastNode { pb })
+    }
+
+    fn validate(members: &[String], _leader: &str) -> Result<(), String> {
+        public_keys(members).map(drop)
+    }
+
+    fn broadcast(&self, state: &mut ProvableBroadcastState, value: String) -> (usize, String) {
+        let round = state.next_round;
+        state.next_round += 1;
+        (round, value)
+    }
+
+    // a certificate goes to `certificate-<sender>-<round>.json`.
+    fn export(certificate: &ExportedCertificate) -> Option<(String, String)> {
+        let name = format!(
+            "certificate-{}-{}.json",
+            certificate.sender, certificate.round
+        );
+        let json = serde_json::to_string_pretty(certificate).expect("certificates serialize");
+        Some((name, json))
+    }
+}
+
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let mut options = Options::from_args(std::env::args().skip(2))?;
+    if options.byzantine.is_some() {
+        return Err("--byzantine only works with RB".into());
+    }
+    options.protocol = ProtocolKind::Pb;
+    options.export.get_or_insert_with(|| PathBuf::from("."));
+    libp2p_rb::run(options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips() {
+        let bytes = [0u8, 1, 0x7f, 0x80, 0xff];
+        assert_eq!(to_hex(&bytes), "00017f80ff");
+        assert_eq!(from_hex(&to_hex(&bytes)), Some(bytes.to_vec()));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn public_keys_come_out_of_peer_ids() {
+        let keypair = libp2p_rb::keypair(Some(1)).unwrap();
+        let secret_key = ed25519_secret_key(&keypair).unwrap();
+        let peer_id = keypair.public().to_peer_id();
+        assert_eq!(
+            ed25519_public_key(&peer_id),
+            Some(secret_key.verifying_key())
+        );
+    }
+
+    #[test]
+    fn certificates_export_as_json() {
+        let certificate = ExportedCertificate {
+            sender: String::from("sender"),
+            round: 3,
+            value: String::from("hello"),
+            members: vec![String::from("sender")],
+            threshold: 1,
+            signatures: BTreeMap::from([(String::from("sender"), String::from("00"))]),
+        };
+        let (name, json) = ProvableBroadcastNode::export(&certificate).unwrap();
+        assert_eq!(name, "certificate-sender-3.json");
+        let parsed: ExportedCertificate = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, certificate);
+    }
+}
@@ -1,17 +1,341 @@
-use crate::ffitest::lean_helpers;
-use crate::networktest::rb_protocol;
+use crate::networktest::aba_protocol::Aba;
+use crate::networktest::avid_protocol::Avid;
+use crate::networktest::byzantine::ByzantineKind;
+#[cfg(feature = "lean")]
+use crate::networktest::cb_protocol::ConsistentBroadcast;
+use crate::networktest::libp2p_pb::ProvableBroadcastNode;
+use crate::networktest::network_protocol::{
+    DrivenProtocol, NetworkProtocol, Packet, Request, Response,
+};
+use crate::networktest::rb_actor::{self, Command, Event, ProtocolHandle};
+use crate::networktest::rb_batch::Outbox;
+use crate::networktest::rb_chaos::{Chaos, ChaosProfile};
+use crate::networktest::rb_control::{self, ControlMessage, ControlRequest, ControlResult};
+use crate::networktest::rb_peers::PeerTracker;
+use crate::networktest::rb_protocol::ReliableBroadcast;
+use crate::networktest::trace::{self, TraceEvent, TraceWriter};
 use futures::prelude::*;
+use libp2p::core::transport::MemoryTransport;
+use libp2p::core::{upgrade, Transport as _};
 use libp2p::identity::Keypair;
 use libp2p::request_response::{ProtocolSupport, ResponseChannel};
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
-use libp2p::{mdns, request_response, PeerId, StreamProtocol, Swarm};
+use libp2p::{mdns, noise, request_response, tls, yamux, Multiaddr, PeerId, StreamProtocol, Swarm};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
 use std::error::Error;
+use std::fmt::Debug;
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use tokio::{io, io::AsyncBufReadExt, select};
 use tracing_subscriber::EnvFilter;
 
-use super::rb_protocol::{RBRequest, RBResponse};
+// bounds the channels between the swarm task and the protocol actor.
+const ACTOR_CHANNEL_CAPACITY: usize = 256;
+const METRICS_INTERVAL: Duration = Duration::from_secs(10);
+const CONTROL_CHANNEL_CAPACITY: usize = 16;
+// how often we check whether a lost member is due for a redial.
+// the actual delay between attempts is decided by `PeerTracker`'s backoff.
+const REDIAL_INTERVAL: Duration = Duration::from_secs(1);
+// how often we release delayed requests and check whether a partition is over.
+const CHAOS_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    // TCP + TLS + yamux
+    Tcp,
+    Quic,
+    // websocket (over TCP) + TLS + yamux
+    Websocket,
+    // in-process only, so that a whole cluster can run inside one test.
+    // memory + noise + yamux
+    Memory,
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(TransportKind::Tcp),
+            "quic" => Ok(TransportKind::Quic),
+            "ws" | "websocket" => Ok(TransportKind::Websocket),
+            "memory" => Ok(TransportKind::Memory),
+            other => Err(format!(
+                "unknown transport: {other} (expected one of tcp, quic, ws, memory)"
+            )),
+        }
+    }
+}
+
+// which protocol the driver runs. every protocol is a `DrivenProtocol` (see `network_protocol.rs`),
+// and runs on the same network runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolKind {
+    // reliable broadcast: the lean one, or the native port without lean.
+    Rb,
+    // the native provable broadcast (see `libp2p_pb.rs`).
+    Pb,
+    // consistent broadcast: RB without the vote phase (see `cb_protocol.rs`). lean only.
+    #[cfg(feature = "lean")]
+    Cb,
+    // erasure-coded RB, for large values (see `avid_protocol.rs`).
+    Avid,
+    // binary agreement: every node proposes a bit (see `aba_protocol.rs`).
+    Aba,
+}
+
+impl FromStr for ProtocolKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rb" => Ok(ProtocolKind::Rb),
+            "pb" => Ok(ProtocolKind::Pb),
+            #[cfg(feature = "lean")]
+            "cb" => Ok(ProtocolKind::Cb),
+            "avid" => Ok(ProtocolKind::Avid),
+            "aba" => Ok(ProtocolKind::Aba),
+            #[cfg(not(feature = "lean"))]
+            "cb" => Err(String::from("cb needs the `lean` feature")),
+            #[cfg(feature = "lean")]
+            other => Err(format!(
+                "unknown protocol: {other} (expected rb, pb, cb, avid or aba)"
+            )),
+            #[cfg(not(feature = "lean"))]
+            other => Err(format!(
+                "unknown protocol: {other} (expected rb, pb, avid or aba)"
+            )),
+        }
+    }
+}
+
+impl TransportKind {
+    // listens on all interfaces with a random, OS-assigned port.
+    pub(crate) fn default_listen_address(&self) -> Multiaddr {
+        let address = match self {
+            TransportKind::Tcp => "/ip4/0.0.0.0/tcp/0",
+            TransportKind::Quic => "/ip4/0.0.0.0/udp/0/quic-v1",
+            TransportKind::Websocket => "/ip4/0.0.0.0/tcp/0/ws",
+            TransportKind::Memory => "/memory/0",
+        };
+        address
+            .parse()
+            .expect("default listen addresses are well-formed")
+    }
+}
+
+// command line options for `cargo run -- rb [options]`
+#[derive(Debug)]
+pub struct Options {
+    pub protocol: ProtocolKind,
+    // serve the control API on this unix socket instead of reading commands from stdin.
+    pub daemon: Option<PathBuf>,
+    pub transport: TransportKind,
+    // addresses to listen on. if empty, we listen on the transport's default address.
+    pub listen: Vec<Multiaddr>,
+    // peers to dial on startup, for when there's no mDNS to find them for us.
+    pub peers: Vec<Multiaddr>,
+    pub mdns: bool,
+    // outbound packets to the same peer are coalesced into one request.
+    // a batch goes out once it has `batch_size` packets, or every `batch_interval`.
+    // with a zero interval, we only coalesce the packets produced by a single protocol step.
+    pub batch_interval: Duration,
+    pub batch_size: usize,
+    // misbehave with this strategy instead of running the protocol honestly. (RB only.)
+    pub byzantine: Option<ByzantineKind>,
+    // derive our keypair (and so our peer id) from this seed, instead of generating a fresh one.
+    // a node restarted with the same seed is the same member of the protocol.
+    // (obviously insecure, so only meant for tests and local clusters.)
+    pub key_seed: Option<u64>,
+    // record every send, receive, state transition and delivery to this file (see `trace.rs`).
+    pub trace: Option<PathBuf>,
+    // inject faults into our outbound requests (see `rb_chaos.rs`).
+    pub chaos: Option<ChaosProfile>,
+    // write the protocol's outputs to files in this directory, for those it exports.
+    // (PB's certificates.)
+    pub export: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            protocol: ProtocolKind::Rb,
+            daemon: None,
+            transport: TransportKind::Tcp,
+            listen: Vec::new(),
+            peers: Vec::new(),
+            mdns: true,
+            batch_interval: Duration::from_millis(10),
+            batch_size: 64,
+            byzantine: None,
+            key_seed: None,
+            trace: None,
+            chaos: None,
+            export: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        fn next_value(
+            args: &mut impl Iterator<Item = String>,
+            flag: &str,
+        ) -> Result<String, String> {
+            args.next().ok_or_else(|| format!("{flag} expects a value"))
+        }
+        fn parse_multiaddr(s: String) -> Result<Multiaddr, String> {
+            s.parse().map_err(|e| format!("invalid multiaddr {s}: {e}"))
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--protocol" => {
+                    options.protocol = next_value(&mut args, "--protocol")?.parse()?;
+                }
+                "--daemon" => {
+                    let path = next_value(&mut args, "--daemon")?;
+                    options.daemon = Some(PathBuf::from(path));
+                }
+                "--transport" => {
+                    options.transport = next_value(&mut args, "--transport")?.parse()?;
+                }
+                "--listen" => {
+                    let address = parse_multiaddr(next_value(&mut args, "--listen")?)?;
+                    options.listen.push(address);
+                }
+                "--peer" => {
+                    let address = parse_multiaddr(next_value(&mut args, "--peer")?)?;
+                    options.peers.push(address);
+                }
+                "--no-mdns" => options.mdns = false,
+                "--batch-interval" => {
+                    let millis = next_value(&mut args, "--batch-interval")?;
+                    let millis = millis
+                        .parse()
+                        .map_err(|e| format!("invalid --batch-interval {millis}: {e}"))?;
+                    options.batch_interval = Duration::from_millis(millis);
+                }
+                "--batch-size" => {
+                    let size = next_value(&mut args, "--batch-size")?;
+                    options.batch_size = size
+                        .parse()
+                        .map_err(|e| format!("invalid --batch-size {size}: {e}"))?;
+                }
+                "--byzantine" => {
+                    options.byzantine = Some(next_value(&mut args, "--byzantine")?.parse()?);
+                }
+                "--key-seed" => {
+                    let seed = next_value(&mut args, "--key-seed")?;
+                    options.key_seed = Some(
+                        seed.parse()
+                            .map_err(|e| format!("invalid --key-seed {seed}: {e}"))?,
+                    );
+                }
+                "--trace" => {
+                    let path = next_value(&mut args, "--trace")?;
+                    options.trace = Some(PathBuf::from(path));
+                }
+                "--chaos" => {
+                    let path = PathBuf::from(next_value(&mut args, "--chaos")?);
+                    options.chaos = Some(ChaosProfile::load(&path)?);
+                }
+                "--export" => {
+                    let path = next_value(&mut args, "--export")?;
+                    options.export = Some(PathBuf::from(path));
+                }
+                other => return Err(format!("unknown option: {other}")),
+            }
+        }
+
+        // mDNS can't find anything on the memory transport.
+        if options.transport == TransportKind::Memory {
+            options.mdns = false;
+        }
+        if options.byzantine.is_some() && options.protocol != ProtocolKind::Rb {
+            return Err(String::from("--byzantine only works with RB"));
+        }
+        Ok(options)
+    }
+}
+
+type ProtocolPacket<P> = Packet<String, <P as NetworkProtocol>::Message>;
+type ProtocolRequest<P> = Request<<P as NetworkProtocol>::Message>;
+type Behaviour<P> = RequestResponseMDNSBehaviour<<P as NetworkProtocol>::Message>;
+
+// what the driver knows about the protocol, independently of the actor.
+// this is what the control API reports on.
+struct NodeStatus<P: DrivenProtocol> {
+    keypair: Keypair,
+    // set once the protocol has been initialized
+    leader: Option<String>,
+    members: Vec<String>,
+    // e.g. RB's deliveries
+    outputs: Vec<P::Output>,
+    peers: PeerTracker<ProtocolRequest<P>>,
+    outbox: Outbox<ProtocolPacket<P>>,
+    byzantine: Option<ByzantineKind>,
+    trace: Option<TraceWriter>,
+    chaos: Chaos<ProtocolRequest<P>>,
+    // where exported outputs go (`--export`)
+    export: Option<PathBuf>,
+    // every packet we've sent to or received from another node, to compare protocols' costs.
+    packets_sent: u64,
+    packets_received: u64,
+}
+
+impl<P: DrivenProtocol> NodeStatus<P> {
+    fn new(options: &Options, keypair: Keypair) -> Result<Self, String> {
+        let chaos = match options.chaos.clone() {
+            Some(profile) => Chaos::new(profile)?,
+            None => Chaos::default(),
+        };
+        if let Some(dir) = &options.export {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("can't create {}: {e}", dir.display()))?;
+        }
+        Ok(NodeStatus {
+            keypair,
+            leader: None,
+            members: Vec::new(),
+            outputs: Vec::new(),
+            peers: PeerTracker::default(),
+            outbox: Outbox::new(options.batch_size),
+            byzantine: options.byzantine,
+            trace: None,
+            chaos,
+            export: options.export.clone(),
+            packets_sent: 0,
+            packets_received: 0,
+        })
+    }
+
+    fn record(&mut self, event: TraceEvent) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.record(&event);
+        }
+    }
+
+    fn record_send(&mut self, packet: &ProtocolPacket<P>) {
+        self.packets_sent += 1;
+        if let Some(packet) = P::traced_packet(packet) {
+            self.record(TraceEvent::send(trace::now_micros(), &packet));
+        }
+    }
+
+    fn record_receive(&mut self, time: u64, packet: &ProtocolPacket<P>) {
+        self.packets_received += 1;
+        if let Some(packet) = P::traced_packet(packet) {
+            self.record(TraceEvent::receive(time, &packet));
+        }
+    }
+}
 
 fn truncate_peer_id(peer_id: &PeerId) -> String {
     let peer_id_string_ = peer_id.to_string();
@@ -21,115 +345,521 @@ fn truncate_peer_id(peer_id: &PeerId) -> String {
 }
 
 // define a custom behaviour, aggregating:
-// - mdns behaviour for peer discovery
+// - mdns behaviour for peer discovery (which can be switched off)
 // - request_response behaviour for sending messages
 //   - cbor as serialization mechanism
-//   - <RBRequest, RBResponse> as the request and response type respectively
+//   - <Request, Response> as the request and response type respectively,
+//     over the protocol's messages `M`
 #[derive(NetworkBehaviour)]
-struct RequestResponseMDNSBehaviour {
-    mdns: mdns::tokio::Behaviour,
-    request_response:
-        request_response::cbor::Behaviour<rb_protocol::RBRequest, rb_protocol::RBResponse>,
-}
-
-impl RequestResponseMDNSBehaviour {
-    fn new(keypair: &Keypair) -> Self {
-        let local_peer_id = keypair.public().to_peer_id();
-        let mdns_config = mdns::Config {
-            ttl: Duration::from_secs(30),
-            query_interval: Duration::from_secs(5),
-            enable_ipv6: false,
-        };
+struct RequestResponseMDNSBehaviour<M>
+where
+    M: Clone + Debug + Serialize + DeserializeOwned + Send + 'static,
+{
+    mdns: Toggle<mdns::tokio::Behaviour>,
+    request_response: request_response::cbor::Behaviour<Request<M>, Response>,
+}
+
+impl<M> RequestResponseMDNSBehaviour<M>
+where
+    M: Clone + Debug + Serialize + DeserializeOwned + Send + 'static,
+{
+    // every protocol gets its own stream protocol, so nodes running different ones can't talk.
+    fn new(keypair: &Keypair, enable_mdns: bool, stream_protocol: StreamProtocol) -> Self {
         Self {
-            mdns: mdns::tokio::Behaviour::new(mdns_config, local_peer_id).unwrap(),
-            request_response: request_response::cbor::Behaviour::<RBRequest, RBResponse>::new(
-                [(
-                    StreamProtocol::new("/verse-lab/reliable-broadcast/1"),
-                    ProtocolSupport::Full,
-                )],
+            mdns: mdns_behaviour(keypair, enable_mdns),
+            request_response: request_response::cbor::Behaviour::<Request<M>, Response>::new(
+                [(stream_protocol, ProtocolSupport::Full)],
                 request_response::Config::default(),
             ),
         }
     }
 }
 
+// peer discovery on the local network, if enabled.
+pub(crate) fn mdns_behaviour(
+    keypair: &Keypair,
+    enable_mdns: bool,
+) -> Toggle<mdns::tokio::Behaviour> {
+    let local_peer_id = keypair.public().to_peer_id();
+    let mdns_config = mdns::Config {
+        ttl: Duration::from_secs(30),
+        query_interval: Duration::from_secs(5),
+        enable_ipv6: false,
+    };
+    let mdns =
+        enable_mdns.then(|| mdns::tokio::Behaviour::new(mdns_config, local_peer_id).unwrap());
+    Toggle::from(mdns)
+}
+
+// derives the keypair from `key_seed` if there is one, or generates a fresh one.
+pub(crate) fn keypair(key_seed: Option<u64>) -> Result<Keypair, Box<dyn Error>> {
+    match key_seed {
+        Some(seed) => Ok(seeded_keypair(seed)),
+        None => Ok(Keypair::generate_ed25519()),
+    }
+}
+
+fn seeded_keypair(seed: u64) -> Keypair {
+    let mut secret = [0u8; 32];
+    secret[..8].copy_from_slice(&seed.to_le_bytes());
+    Keypair::ed25519_from_bytes(secret).expect("any 32 bytes make an ed25519 secret key")
+}
+
+/// The peer id of a node started with `key_seed: Some(seed)`, so that a cluster's membership
+/// can be known before any of its nodes are up.
+pub fn peer_id_for_seed(seed: u64) -> PeerId {
+    seeded_keypair(seed).public().to_peer_id()
+}
+
+async fn build_swarm<P: DrivenProtocol>(
+    options: &Options,
+    keypair: Keypair,
+) -> Result<Swarm<Behaviour<P>>, Box<dyn Error>> {
+    let enable_mdns = options.mdns;
+    let stream_protocol = match options.protocol {
+        ProtocolKind::Rb => StreamProtocol::new("/verse-lab/reliable-broadcast/2"),
+        ProtocolKind::Pb => StreamProtocol::new("/verse-lab/provable-broadcast/driven/1"),
+        #[cfg(feature = "lean")]
+        ProtocolKind::Cb => StreamProtocol::new("/verse-lab/consistent-broadcast/1"),
+        ProtocolKind::Avid => StreamProtocol::new("/verse-lab/avid-broadcast/1"),
+        ProtocolKind::Aba => StreamProtocol::new("/verse-lab/binary-agreement/1"),
+    };
+    let behaviour = move |keypair: &Keypair| {
+        RequestResponseMDNSBehaviour::new(keypair, enable_mdns, stream_protocol)
+    };
+    build_swarm_with(options.transport, keypair, behaviour).await
+}
+
+// sets up the p2p network over the chosen transport.
+// every transport ends up as the same boxed `Swarm`, so nothing after this cares which one we picked.
+// (shared with the other drivers, which only differ in their behaviour.)
+pub(crate) async fn build_swarm_with<B: NetworkBehaviour>(
+    transport: TransportKind,
+    keypair: Keypair,
+    behaviour: impl FnOnce(&Keypair) -> B,
+) -> Result<Swarm<B>, Box<dyn Error>> {
+    // Allows us to observe pings indefinitely.
+    let idle_timeout = Duration::from_secs(u64::MAX);
+    let builder = libp2p::SwarmBuilder::with_existing_identity(keypair).with_tokio();
+
+    let swarm = match transport {
+        TransportKind::Tcp => builder
+            .with_tcp(
+                libp2p::tcp::Config::default(),
+                tls::Config::new,
+                yamux::Config::default,
+            )?
+            .with_behaviour(behaviour)?
+            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(idle_timeout))
+            .build(),
+        TransportKind::Quic => builder
+            .with_quic()
+            .with_behaviour(behaviour)?
+            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(idle_timeout))
+            .build(),
+        TransportKind::Websocket => builder
+            .with_websocket(tls::Config::new, yamux::Config::default)
+            .await?
+            .with_behaviour(behaviour)?
+            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(idle_timeout))
+            .build(),
+        TransportKind::Memory => builder
+            .with_other_transport(|keypair| -> Result<_, Box<dyn Error + Send + Sync>> {
+                Ok(MemoryTransport::default()
+                    .upgrade(upgrade::Version::V1)
+                    .authenticate(noise::Config::new(keypair)?)
+                    .multiplex(yamux::Config::default()))
+            })?
+            .with_behaviour(behaviour)?
+            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(idle_timeout))
+            .build(),
+    };
+
+    Ok(swarm)
+}
+
 // request and response handlers
-fn send_packet(
-    swarm: &mut Swarm<RequestResponseMDNSBehaviour>,
-    protocol: &mut rb_protocol::lean::Protocol,
-    packet: rb_protocol::lean::Packet,
+fn send_packet<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    status: &mut NodeStatus<P>,
+    packet: ProtocolPacket<P>,
 ) {
-    // note: `request_response::send_request` will automatically dial a peer
-    // to send a message to them, if we don't yet have an active connection to them.
+    // packets addressed to ourselves never get here, since the protocol actor loops them back.
     let dst_id =
         PeerId::from_str(packet.dst.as_str()).expect("expected well-formed destination address");
 
-    let self_id = swarm.local_peer_id();
-
-    // libp2p does not support sending
-    match &dst_id == self_id {
-        false => {
-            println!("sending packet to external destination:");
-            dbg!(&packet);
-            swarm
-                .behaviour_mut()
-                .request_response
-                .send_request(&dst_id, RBRequest { packet });
-        }
-        true => {
-            println!("sending packet to self:");
-            dbg!(&packet);
-            let round = packet.msg.get_round();
-
-            let packets_to_send = unsafe { protocol.handle_packet(packet) };
-            dbg!(&packets_to_send);
-            packets_to_send
-                .into_iter()
-                .for_each(|packet| send_packet(swarm, protocol, packet));
+    tracing::debug!(?packet, "queueing packet for another node");
+    status.record_send(&packet);
+    if let Some(packets) = status.outbox.push(dst_id, packet) {
+        send_request(swarm, status, dst_id, Request { packets });
+    }
+}
 
-            unsafe {
-                protocol.check_output(round);
-            };
-        }
+// sends out every batch in the outbox, however small.
+fn flush_outbox<P: DrivenProtocol>(swarm: &mut Swarm<Behaviour<P>>, status: &mut NodeStatus<P>) {
+    for (dst_id, packets) in status.outbox.drain() {
+        send_request(swarm, status, dst_id, Request { packets });
+    }
+}
+
+fn send_request<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    status: &mut NodeStatus<P>,
+    dst_id: PeerId,
+    request: ProtocolRequest<P>,
+) {
+    // we've lost this member (or it refused us): hold the request until it's ready for it.
+    if status.peers.should_hold(&dst_id) {
+        let truncated_peer_id = truncate_peer_id(&dst_id);
+        println!("holding packets for unreachable member {truncated_peer_id}");
+        status.peers.hold(dst_id, request);
+        return;
     }
+
+    send_now(swarm, status, dst_id, request);
+}
+
+// sends a request without checking whether it should be held.
+fn send_now<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    status: &mut NodeStatus<P>,
+    dst_id: PeerId,
+    request: ProtocolRequest<P>,
+) {
+    // whatever the chaos profile delays comes back out of `Chaos::due` later.
+    for request in status.chaos.outbound(dst_id, request, Instant::now()) {
+        transmit::<P>(swarm, &mut status.peers, dst_id, request);
+    }
+}
+
+fn transmit<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    peers: &mut PeerTracker<ProtocolRequest<P>>,
+    dst_id: PeerId,
+    request: ProtocolRequest<P>,
+) {
+    // note: `request_response::send_request` will automatically dial a peer
+    // to send a message to them, if we don't yet have an active connection to them.
+    let request_id = swarm
+        .behaviour_mut()
+        .request_response
+        .send_request(&dst_id, request.clone());
+    peers.sent(request_id, dst_id, request);
 }
 
-fn handle_request(
-    swarm: &mut Swarm<RequestResponseMDNSBehaviour>,
-    request: RBRequest,
-    channel: ResponseChannel<RBResponse>,
-    protocol: &mut rb_protocol::lean::Protocol,
+fn handle_request<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    peer_id: &PeerId,
+    request: ProtocolRequest<P>,
+    channel: ResponseChannel<Response>,
+    actor: &mut ProtocolHandle<P>,
+    status: &mut NodeStatus<P>,
 ) {
-    // acknowledge the packet
-    let response = RBResponse::Ack;
+    // we're partitioned from this peer, so its request never made it here.
+    // not answering it at all makes the request fail on the sender's side, which retries it later.
+    if status.chaos.is_partitioned(peer_id) {
+        let truncated_peer_id = truncate_peer_id(peer_id);
+        println!("ignoring request from partitioned peer {truncated_peer_id}");
+        return;
+    }
+
+    // only members of the protocol get to send us packets.
+    // (before initialization, nobody is a member yet.
+    // members we refuse then hold on to their packets, and try again later.)
+    if !status.peers.is_member(peer_id) {
+        let truncated_peer_id = truncate_peer_id(peer_id);
+        println!("refusing request from non-member {truncated_peer_id}");
+        // the peer may have gone away already, in which case there is nobody to refuse.
+        let _ = swarm
+            .behaviour_mut()
+            .request_response
+            .send_response(channel, Response::Refused);
+        return;
+    }
+
+    // acknowledge the whole batch
+    let response = Response::Ack;
     swarm
         .behaviour_mut()
         .request_response
         .send_response(channel, response)
         .expect("should be able to ack a request");
 
-    println!("received request:");
-    dbg!(&request.packet);
-    let round = request.packet.msg.get_round();
+    tracing::debug!(packets = ?request.packets, "received request");
 
-    // generate new packets to send, and broadcast them
-    let packets_to_send = unsafe { protocol.handle_packet(request.packet) };
-    dbg!(&packets_to_send);
-
-    packets_to_send
+    // unforgeable channels: a peer can only send packets under its own name.
+    // anything claiming to come from someone else is dropped.
+    let sender = peer_id.to_string();
+    let (packets, forged): (Vec<_>, Vec<_>) = request
+        .packets
         .into_iter()
-        .for_each(|packet| send_packet(swarm, protocol, packet));
+        .partition(|packet| packet.src == sender);
+    if !forged.is_empty() {
+        let truncated_peer_id = truncate_peer_id(peer_id);
+        println!(
+            "dropping {} forged packets from {truncated_peer_id}",
+            forged.len()
+        );
+    }
 
-    unsafe {
-        protocol.check_output(round);
-    };
+    let now = trace::now_micros();
+    for packet in packets.iter() {
+        status.record_receive(now, packet);
+    }
+
+    // hand the batch over to the protocol actor, which processes it in one step.
+    // any packets it generates come back as an `Event::Outbound`.
+    actor.submit(Command::HandlePackets { packets });
 }
 
-fn handle_response(peer_id: &PeerId, response: &rb_protocol::RBResponse) {
-    // in PB, this is where we would update the partial signature and generate a combined signature.
-    // we would likely also be calling lean functions here.
+fn handle_response<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    peer_id: &PeerId,
+    request_id: &request_response::RequestId,
+    response: &Response,
+    status: &mut NodeStatus<P>,
+) {
     let truncated_peer_id = truncate_peer_id(peer_id);
     println!("{truncated_peer_id}: {response}");
+    match response {
+        // a refusing member hasn't been initialized yet.
+        // its packets are held, and resent once it's had some time to catch up.
+        Response::Refused => status.peers.refused(request_id),
+        Response::Ack => {
+            // the peer is taking our packets now, so it gets everything it refused before.
+            if let Some((dst_id, held)) = status.peers.acked(request_id) {
+                for request in held {
+                    send_request(swarm, status, dst_id, request);
+                }
+            }
+        }
+    }
+}
+
+fn handle_actor_event<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    event: Event<P>,
+    status: &mut NodeStatus<P>,
+    batch_interval: Duration,
+) {
+    match event {
+        Event::Initialized => println!(">> initialized!"),
+        Event::InitFailed { error } => {
+            // undo what `init_protocol` did, so that the node can be initialized again.
+            println!(">> failed to initialize: {error}");
+            status.leader = None;
+            status.members.clear();
+            status.peers.set_members(Vec::new());
+        }
+        Event::Outbound { packets } => {
+            tracing::debug!(?packets, "outbound packets from the protocol");
+            if let Some(first) = packets.first() {
+                let traced: Vec<_> = packets.iter().filter_map(P::traced_packet).collect();
+                for event in trace::transitions(trace::now_micros(), &first.src, &traced) {
+                    status.record(event);
+                }
+            }
+            packets
+                .into_iter()
+                .for_each(|packet| send_packet(swarm, status, packet));
+            // without a flush interval, batches only span a single protocol step.
+            if batch_interval.is_zero() {
+                flush_outbox(swarm, status);
+            }
+        }
+        Event::Output { output } => {
+            println!(">> output: {output}");
+            if let Some(delivery) = P::traced_output(&output) {
+                status.record(TraceEvent::Deliver {
+                    time: trace::now_micros(),
+                    node: swarm.local_peer_id().to_string(),
+                    leader: delivery.leader,
+                    round: delivery.round,
+                    value: delivery.value,
+                });
+            }
+            export_output(status, &output);
+            status.outputs.push(output)
+        }
+    }
+}
+
+// writes `output` to a file, if the protocol exports it and we were asked to (`--export`).
+// whatever an earlier run left at the same path is overwritten.
+fn export_output<P: DrivenProtocol>(status: &NodeStatus<P>, output: &P::Output) {
+    let (Some(dir), Some((name, contents))) = (status.export.as_ref(), P::export(output)) else {
+        return;
+    };
+    let path = dir.join(name);
+    match std::fs::write(&path, contents) {
+        Ok(()) => println!(">> exported to {}", path.display()),
+        Err(e) => println!(">> failed to export to {}: {e}", path.display()),
+    }
+}
+
+// initializes the protocol with the given members, or if there are none,
+// using a snapshot of the current network state
+// (i.e., create a protocol with all current nodes in the network)
+fn init_protocol<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    actor: &mut ProtocolHandle<P>,
+    status: &mut NodeStatus<P>,
+    leader: String,
+    members: Option<Vec<String>>,
+) -> Result<(), String> {
+    if status.leader.is_some() {
+        return Err(String::from("protocol is already initialized"));
+    }
+
+    let my_peer_id = *swarm.local_peer_id();
+    let my_address = my_peer_id.to_string();
+    let other_members: Vec<PeerId> = match members {
+        // static membership: we don't have to be connected to the members yet.
+        Some(members) => {
+            let mut peers = Vec::new();
+            for member in members {
+                let peer_id = PeerId::from_str(&member)
+                    .map_err(|e| format!("invalid member peer id {member}: {e}"))?;
+                if peer_id != my_peer_id && !peers.contains(&peer_id) {
+                    peers.push(peer_id);
+                }
+            }
+            peers
+        }
+        None => swarm.connected_peers().copied().collect(),
+    };
+    let mut all_peers: Vec<String> = other_members.iter().map(PeerId::to_string).collect();
+    all_peers.push(my_address.clone());
+    tracing::debug!(members = ?all_peers, "initializing the protocol");
+    P::validate(&all_peers, &leader)?;
+
+    // the member set is fixed from here on, even if some of these peers go away later.
+    status.peers.set_members(other_members);
+
+    actor.submit(Command::Init {
+        node_list: all_peers.clone(),
+        address: my_address,
+        leader: leader.clone(),
+        keypair: status.keypair.clone(),
+    });
+    status.leader = Some(leader);
+    status.members = all_peers;
+    Ok(())
+}
+
+fn broadcast<P: DrivenProtocol>(
+    actor: &mut ProtocolHandle<P>,
+    status: &NodeStatus<P>,
+    message: String,
+) -> Result<(), String> {
+    if status.leader.is_none() {
+        return Err(String::from(
+            "not yet initialized. run the 'init' command first!",
+        ));
+    }
+    P::check_value(&message)?;
+
+    // the actor generates packets to send from the protocol (e.g. from lean),
+    // and we send them via libp2p once they come back as an `Event::Outbound`.
+    actor.submit(Command::Broadcast { message });
+    Ok(())
+}
+
+// returns true if the node should shut down.
+fn handle_control<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    actor: &mut ProtocolHandle<P>,
+    status: &mut NodeStatus<P>,
+    control: ControlMessage,
+) -> bool {
+    let mut shutdown = false;
+    let result: ControlResult = match control.request {
+        ControlRequest::Init { leader, members } => {
+            init_protocol(swarm, actor, status, leader, members).map(|()| json!(null))
+        }
+        ControlRequest::Broadcast { value } => {
+            broadcast(actor, status, value).map(|()| json!(null))
+        }
+        ControlRequest::Status => Ok(json!({
+            "peer_id": swarm.local_peer_id().to_string(),
+            "initialized": status.leader.is_some(),
+            "leader": status.leader,
+            "members": status.members,
+            "connected_peers": swarm.connected_peers().count(),
+            "deliveries": status.outputs.len(),
+            "packets_sent": status.packets_sent,
+            "packets_received": status.packets_received,
+            "held_packets": status.peers.held_count(),
+            "byzantine": status.byzantine.map(|kind| kind.to_string()),
+            "chaos": status.chaos.stats(),
+            "partitioned_from": status.chaos.partitioned_peers(),
+            "actor": actor.metrics(),
+        })),
+        ControlRequest::Peers => {
+            let peers: Vec<String> = swarm.connected_peers().map(PeerId::to_string).collect();
+            Ok(json!(peers))
+        }
+        // deliveries (the protocol's outputs) are numbered in the order this node made them.
+        // clients poll with the `next` value from the previous response.
+        ControlRequest::Deliveries { since } => {
+            let new_deliveries = status.outputs.get(since..).unwrap_or(&[]);
+            Ok(json!({
+                "deliveries": new_deliveries,
+                "next": status.outputs.len().max(since),
+            }))
+        }
+        // closes every connection to the peer, as if the network had dropped them.
+        // members are redialed like any other lost member.
+        ControlRequest::Disconnect { peer } => PeerId::from_str(&peer)
+            .map_err(|e| format!("invalid peer id {peer}: {e}"))
+            .and_then(|peer_id| {
+                swarm
+                    .disconnect_peer_id(peer_id)
+                    .map_err(|()| format!("not connected to {peer}"))
+            })
+            .map(|()| json!(null)),
+        // cuts us off from the given peers (on our side only: to partition the cluster,
+        // partition each side from the other). requests across the partition are held until
+        // it heals, after `duration_ms` or on `heal`.
+        ControlRequest::Partition { peers, duration_ms } => peers
+            .iter()
+            .map(|peer| PeerId::from_str(peer).map_err(|e| format!("invalid peer id {peer}: {e}")))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|peer_ids| {
+                let duration = duration_ms.map(Duration::from_millis);
+                let released = status.chaos.partition(peer_ids, duration, Instant::now());
+                resend(swarm, status, released);
+                json!(null)
+            }),
+        ControlRequest::Heal => {
+            let released = status.chaos.heal();
+            resend(swarm, status, released);
+            Ok(json!(null))
+        }
+        ControlRequest::Shutdown => {
+            actor.submit(Command::Shutdown);
+            shutdown = true;
+            Ok(json!(null))
+        }
+    };
+
+    // the client may have hung up already, which is fine.
+    let _ = control.reply.send(result);
+    shutdown
+}
+
+// requests released by a partition healing go through the chaos profile again.
+fn resend<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
+    status: &mut NodeStatus<P>,
+    requests: Vec<(PeerId, ProtocolRequest<P>)>,
+) {
+    if !requests.is_empty() {
+        println!("partition healed, resending {} requests", requests.len());
+    }
+    for (dst_id, request) in requests {
+        send_request(swarm, status, dst_id, request);
+    }
 }
 
 // stdin is used for 2 different things.
@@ -139,59 +869,36 @@ fn handle_response(peer_id: &PeerId, response: &rb_protocol::RBResponse) {
 //
 // 2) if the protocol has been initialized, sending any message (including "init")
 // will cause us to send that message to all other nodes using the given protocol.
-fn handle_stdin(
-    swarm: &mut Swarm<RequestResponseMDNSBehaviour>,
+fn handle_stdin<P: DrivenProtocol>(
+    swarm: &mut Swarm<Behaviour<P>>,
     line: &str,
-    // a mutable reference might not be correct here.
-    // may want to do something like a Box<T>? not sure
-    protocol: &mut Option<rb_protocol::lean::Protocol>,
+    actor: &mut ProtocolHandle<P>,
+    status: &mut NodeStatus<P>,
 ) {
-    let my_address = swarm.local_peer_id().to_string();
-    match (&protocol, line) {
-        (None, command) => {
+    match (status.leader.is_some(), line) {
+        (false, command) => {
             let cmd_args: Vec<&str> = command.split_ascii_whitespace().collect();
 
             // initialization command:
-            // init <peer id of leader node>
-            if cmd_args.len() == 2 && cmd_args[0] == "init" {
-                // initialize lean & protocol
-                unsafe {
-                    lean_helpers::initialize_lean_environment(
-                        rb_protocol::lean::initialize_Protocol,
-                    );
-
-                    let mut all_peers: Vec<String> =
-                        swarm.connected_peers().map(PeerId::to_string).collect();
-                    all_peers.push(my_address.clone());
-                    dbg!(&all_peers);
-
-                    let new_protocol = rb_protocol::lean::Protocol::create(
-                        all_peers,
-                        my_address,
-                        String::from(cmd_args[1]),
-                    );
-
-                    protocol.replace(new_protocol);
-
-                    println!(">> initialized!");
+            // init [peer id of leader node]
+            // without a leader, we lead. (protocols without a leader, like PB, ignore it.)
+            if matches!(cmd_args.len(), 1 | 2) && cmd_args[0] == "init" {
+                let leader = cmd_args
+                    .get(1)
+                    .map_or_else(|| swarm.local_peer_id().to_string(), |s| s.to_string());
+                if let Err(e) = init_protocol(swarm, actor, status, leader, None) {
+                    println!(">> {e}");
                 }
             } else {
                 // do nothing. before the protocol is initialized, we only accept the "init" command.
                 println!(">> not yet initialized. run the 'init' command first!")
             }
         }
-        (Some(_), message) => {
-            // generates packets to send from lean,
-            let p = protocol.as_mut().unwrap();
-            dbg!(&p);
-
-            let packets_to_send = unsafe { p.send_message(my_address, String::from(message)) };
-
-            // ..., then send them via libp2p.
-            println!("[libp2p_rb::handle_stdin] sending packets");
-            packets_to_send
-                .into_iter()
-                .for_each(|packet| send_packet(swarm, p, packet));
+        (true, message) => {
+            println!("[libp2p_rb::handle_stdin] broadcasting message");
+            if let Err(e) = broadcast(actor, status, String::from(message)) {
+                println!(">> {e}");
+            }
         }
     }
 }
@@ -203,74 +910,212 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
+    let options = Options::from_args(std::env::args().skip(2))?;
+    run(options).await
+}
+
+/// Runs a node with the protocol picked in `options`, until it's told to shut down.
+pub async fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    match options.protocol {
+        ProtocolKind::Rb => {
+            let byzantine = options.byzantine;
+            run_protocol::<ReliableBroadcast>(options, byzantine).await
+        }
+        ProtocolKind::Pb => run_protocol::<ProvableBroadcastNode>(options, ()).await,
+        #[cfg(feature = "lean")]
+        ProtocolKind::Cb => run_protocol::<ConsistentBroadcast>(options, ()).await,
+        ProtocolKind::Avid => run_protocol::<Avid>(options, ()).await,
+        ProtocolKind::Aba => run_protocol::<Aba>(options, ()).await,
+    }
+}
+
+pub async fn run_protocol<P: DrivenProtocol>(
+    options: Options,
+    config: P::Config,
+) -> Result<(), Box<dyn Error>> {
     // set up p2p network
-    let mut swarm = libp2p::SwarmBuilder::with_new_identity()
-        .with_tokio()
-        .with_tcp(
-            libp2p::tcp::Config::default(),
-            libp2p::tls::Config::new,
-            libp2p::yamux::Config::default,
-        )?
-        .with_behaviour(|keypair| RequestResponseMDNSBehaviour::new(keypair))?
-        .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(u64::MAX))) // Allows us to observe pings indefinitely.
-        .build();
-
-    // Tell the swarm to listen on all interfaces and a random, OS-assigned port.
-    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+    let keypair = keypair(options.key_seed)?;
+    let mut swarm = build_swarm::<P>(&options, keypair.clone()).await?;
+
+    // Tell the swarm where to listen. by default, that's all interfaces and a random, OS-assigned port.
+    if options.listen.is_empty() {
+        swarm.listen_on(options.transport.default_listen_address())?;
+    }
+    for address in options.listen.iter() {
+        swarm.listen_on(address.clone())?;
+    }
+
+    // without mDNS, we only know about the peers we were told about.
+    for address in options.peers.iter() {
+        swarm.dial(address.clone())?;
+    }
 
     let my_peer_id = swarm.local_peer_id();
     println!("my peer id: {my_peer_id}");
 
-    // stdin reader
+    // stdin reader. in daemon mode, we take commands from the control API instead.
     let mut stdin = io::BufReader::new(io::stdin()).lines();
+    let read_stdin = options.daemon.is_none();
 
-    // reliable broadcast protocol
-    // TODO: maybe replace this option with a OnceCell?
-    let mut protocol = None;
+    let (control_tx, mut control_rx) = mpsc::channel(CONTROL_CHANNEL_CAPACITY);
+    if let Some(path) = options.daemon.clone() {
+        tokio::spawn(async move {
+            if let Err(e) = rb_control::serve(path, control_tx).await {
+                println!("control api stopped: {e}");
+            }
+        });
+    }
+
+    // the protocol, running on its own thread.
+    let (mut actor, mut actor_events) = rb_actor::spawn::<P>(ACTOR_CHANNEL_CAPACITY, config);
+    let mut status = NodeStatus::<P>::new(&options, keypair)?;
+    if let Some(path) = options.trace.as_deref() {
+        status.trace = Some(TraceWriter::create(path)?);
+    }
+
+    // flushes partially filled batches. (`interval` panics on a zero period,
+    // but in that case the branch below is disabled anyway.)
+    let batching = !options.batch_interval.is_zero();
+    let mut batch_interval =
+        tokio::time::interval(options.batch_interval.max(Duration::from_millis(1)));
+
+    // periodically report backpressure, but only when something has changed.
+    let mut metrics_interval = tokio::time::interval(METRICS_INTERVAL);
+    let mut last_metrics = None;
+
+    let mut redial_interval = tokio::time::interval(REDIAL_INTERVAL);
+
+    let mut chaos_interval = tokio::time::interval(CHAOS_INTERVAL);
 
     loop {
         select! {
-            Ok(Some(line)) = stdin.next_line() => {
-              handle_stdin(&mut swarm, &line, &mut protocol);
+            Ok(Some(line)) = stdin.next_line(), if read_stdin => {
+              handle_stdin(&mut swarm, &line, &mut actor, &mut status);
+            }
+
+            // requests from the control API
+            Some(control) = control_rx.recv() => {
+                if handle_control(&mut swarm, &mut actor, &mut status, control) {
+                    break;
+                }
+            }
+
+            // packets (and other events) coming back from the protocol actor
+            Some(event) = actor_events.recv() => {
+                handle_actor_event(&mut swarm, event, &mut status, options.batch_interval);
+            }
+
+            // move backlogged commands into the actor once it has room for them
+            _ = actor.flush(), if actor.has_backlog() => {}
+
+            _ = metrics_interval.tick() => {
+                let metrics = actor.metrics();
+                let backpressure = (metrics.command_backpressure, metrics.event_backpressure);
+                if last_metrics != Some(backpressure) {
+                    println!("[libp2p_rb] actor metrics: {metrics}");
+                    last_metrics = Some(backpressure);
+                }
+            }
+
+            _ = batch_interval.tick(), if batching && !status.outbox.is_empty() => {
+                flush_outbox(&mut swarm, &mut status);
+            }
+
+            // delayed requests, and partitions that are over
+            _ = chaos_interval.tick(), if status.chaos.is_busy() => {
+                let now = Instant::now();
+                for (dst_id, request) in status.chaos.due(now) {
+                    transmit::<P>(&mut swarm, &mut status.peers, dst_id, request);
+                }
+                let released = status.chaos.expire(now);
+                resend(&mut swarm, &mut status, released);
+            }
+
+            // redial members we've lost, backing off between attempts
+            _ = redial_interval.tick() => {
+                for (peer_id, addresses) in status.peers.due_redials() {
+                    let truncated_peer_id = truncate_peer_id(&peer_id);
+                    println!("redialing member {truncated_peer_id}");
+                    let opts = DialOpts::peer_id(peer_id).addresses(addresses).build();
+                    if let Err(e) = swarm.dial(opts) {
+                        println!("failed to redial {truncated_peer_id}: {e}");
+                    }
+                }
+                // members that are still connected, but refused (or failed) what we sent them
+                for (peer_id, held) in status.peers.due_resends() {
+                    let truncated_peer_id = truncate_peer_id(&peer_id);
+                    println!("resending {} held packets to {truncated_peer_id}", held.len());
+                    for request in held {
+                        send_now(&mut swarm, &mut status, peer_id, request);
+                    }
+                }
             }
 
-            // handle a swarm event (poll the swarm)
-            event = swarm.select_next_some() => match event {
+            // handle a swarm event (poll the swarm).
+            // we stop reading from the network while the actor is saturated.
+            event = swarm.select_next_some(), if !actor.is_saturated() => match event {
                 SwarmEvent::NewListenAddr { address, .. } => println!("Listening on {address:?}"),
                 // MDNS: new peer discovered
                 SwarmEvent::Behaviour(RequestResponseMDNSBehaviourEvent::Mdns(
                     mdns::Event::Discovered(list),
                 )) => {
-                    for (peer_id, _multiaddr) in list {
+                    for (peer_id, multiaddr) in list {
                         // upon discovery of new peer, dial them
+                        status.peers.discovered(peer_id, multiaddr);
                         let truncated_peer_id = truncate_peer_id(&peer_id);
                         println!("mdns discovered a new peer: {truncated_peer_id}");
-                        swarm.dial(peer_id)?;
+                        // e.g. already dialing it: a failed dial shouldn't take the node down.
+                        if let Err(e) = swarm.dial(peer_id) {
+                            println!("failed to dial {truncated_peer_id}: {e}");
+                        }
                     }
                 }
                 // MDNS: peer expired
-                // TODO: determine what happens to the protocol when a peer expires and re-connects.
+                // an expired peer stays in the protocol. if we've also lost our connection to it,
+                // we hold its packets and redial it until it comes back.
                 SwarmEvent::Behaviour(RequestResponseMDNSBehaviourEvent::Mdns(
                     mdns::Event::Expired(list),
                 )) => {
                     for (peer_id, _multiaddr) in list {
                         let truncated_peer_id = truncate_peer_id(&peer_id);
                         println!("mdns discover peer has expired: {truncated_peer_id}");
+                        status.peers.expired(peer_id);
+                    }
+                }
+                // (re-)connected to a peer: send everything we held for it
+                SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                    // remember where we reached the peer, in case we have to redial it
+                    // and there's no mDNS record to fall back on.
+                    if endpoint.is_dialer() {
+                        status.peers.discovered(peer_id, endpoint.get_remote_address().clone());
+                    }
+                    let held = status.peers.connected(peer_id);
+                    if !held.is_empty() {
+                        let truncated_peer_id = truncate_peer_id(&peer_id);
+                        println!("flushing {} held packets to {truncated_peer_id}", held.len());
+                    }
+                    for request in held {
+                        send_request(&mut swarm, &mut status, peer_id, request);
                     }
                 }
+                SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                    let truncated_peer_id = truncate_peer_id(&peer_id);
+                    println!("lost connection to {truncated_peer_id}");
+                    status.peers.disconnected(peer_id);
+                }
                 // Request-Response: received a request
                 SwarmEvent::Behaviour(RequestResponseMDNSBehaviourEvent::RequestResponse(
                     request_response::Event::Message {
+                        peer,
                         message:
                             request_response::Message::Request {
                                 request,
                                 channel,
                                 ..
                             },
-                            ..
                     },
                 )) => {
-                    handle_request(&mut swarm,  request, channel, protocol.as_mut().expect("protocol should be initialized"));
+                    handle_request(&mut swarm, &peer, request, channel, &mut actor, &mut status);
                 }
                 // Request-Response: received a response
                 SwarmEvent::Behaviour(RequestResponseMDNSBehaviourEvent::RequestResponse(
@@ -278,16 +1123,218 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
                         peer,
                         message:
                             request_response::Message::Response {
-                                request_id: _,
+                                request_id,
                                 response,
                             },
                     },
                 )) => {
-                    handle_response(&peer,  &response);
+                    handle_response(&mut swarm, &peer, &request_id, &response, &mut status);
+                }
+                // Request-Response: a request never made it.
+                // requests to members are held and retried once we reconnect.
+                SwarmEvent::Behaviour(RequestResponseMDNSBehaviourEvent::RequestResponse(
+                    request_response::Event::OutboundFailure {
+                        peer,
+                        request_id,
+                        error,
+                    },
+                )) => {
+                    let truncated_peer_id = truncate_peer_id(&peer);
+                    println!("request to {truncated_peer_id} failed: {error}");
+                    status.peers.failed(&request_id);
                 }
                 // Ignore all other events.
                 _ => {}
             }
         }
     }
+
+    if let Some(path) = options.daemon {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn every_transport_has_a_name() {
+        let names = [
+            ("tcp", TransportKind::Tcp),
+            ("quic", TransportKind::Quic),
+            ("ws", TransportKind::Websocket),
+            ("websocket", TransportKind::Websocket),
+            ("memory", TransportKind::Memory),
+        ];
+        for (name, transport) in names {
+            assert_eq!(name.parse(), Ok(transport));
+        }
+        assert_eq!(
+            "udp".parse::<TransportKind>(),
+            Err(String::from(
+                "unknown transport: udp (expected one of tcp, quic, ws, memory)"
+            ))
+        );
+        // names are case-sensitive
+        assert!("TCP".parse::<TransportKind>().is_err());
+    }
+
+    #[test]
+    fn default_listen_addresses_pick_any_port() {
+        let addresses = [
+            (TransportKind::Tcp, "/ip4/0.0.0.0/tcp/0"),
+            (TransportKind::Quic, "/ip4/0.0.0.0/udp/0/quic-v1"),
+            (TransportKind::Websocket, "/ip4/0.0.0.0/tcp/0/ws"),
+            (TransportKind::Memory, "/memory/0"),
+        ];
+        for (transport, address) in addresses {
+            assert_eq!(transport.default_listen_address().to_string(), address);
+        }
+    }
+
+    #[test]
+    fn no_arguments_give_the_defaults() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(options.protocol, ProtocolKind::Rb);
+        assert_eq!(options.transport, TransportKind::Tcp);
+        assert!(options.mdns);
+        assert!(options.listen.is_empty() && options.peers.is_empty());
+        assert_eq!(options.batch_interval, Duration::from_millis(10));
+        assert_eq!(options.batch_size, 64);
+        assert_eq!(options.key_seed, None);
+        assert_eq!(options.daemon, None);
+        assert_eq!(options.export, None);
+    }
+
+    #[test]
+    fn arguments_set_their_options() {
+        let options = parse(&[
+            "--protocol",
+            "aba",
+            "--daemon",
+            "/tmp/node.sock",
+            "--transport",
+            "ws",
+            "--listen",
+            "/ip4/127.0.0.1/tcp/4001/ws",
+            "--peer",
+            "/ip4/127.0.0.1/tcp/4002/ws",
+            "--peer",
+            "/ip4/127.0.0.1/tcp/4003/ws",
+            "--no-mdns",
+            "--batch-interval",
+            "0",
+            "--batch-size",
+            "1",
+            "--key-seed",
+            "7",
+            "--trace",
+            "/tmp/node.trace",
+            "--export",
+            "/tmp/exports",
+        ])
+        .unwrap();
+        assert_eq!(options.protocol, ProtocolKind::Aba);
+        assert_eq!(options.daemon, Some(PathBuf::from("/tmp/node.sock")));
+        assert_eq!(options.transport, TransportKind::Websocket);
+        assert_eq!(
+            options.listen,
+            vec!["/ip4/127.0.0.1/tcp/4001/ws".parse::<Multiaddr>().unwrap()]
+        );
+        assert_eq!(options.peers.len(), 2);
+        assert!(!options.mdns);
+        assert_eq!(options.batch_interval, Duration::ZERO);
+        assert_eq!(options.batch_size, 1);
+        assert_eq!(options.key_seed, Some(7));
+        assert_eq!(options.trace, Some(PathBuf::from("/tmp/node.trace")));
+        assert_eq!(options.export, Some(PathBuf::from("/tmp/exports")));
+
+        let options = parse(&["--byzantine", "equivocate"]).unwrap();
+        assert_eq!(options.byzantine, Some(ByzantineKind::EquivocatingLeader));
+    }
+
+    #[test]
+    fn the_memory_transport_turns_mdns_off() {
+        // there's nothing for mDNS to find in memory, whatever the flags say.
+        assert!(!parse(&["--transport", "memory"]).unwrap().mdns);
+        assert!(parse(&["--transport", "quic"]).unwrap().mdns);
+    }
+
+    #[test]
+    fn missing_values_are_reported() {
+        for flag in [
+            "--protocol",
+            "--daemon",
+            "--transport",
+            "--listen",
+            "--peer",
+            "--batch-interval",
+            "--batch-size",
+            "--byzantine",
+            "--key-seed",
+            "--trace",
+            "--chaos",
+            "--export",
+        ] {
+            assert_eq!(
+                parse(&[flag]).unwrap_err(),
+                format!("{flag} expects a value")
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        let error = |args: &[&str]| parse(args).unwrap_err();
+        assert_eq!(
+            error(&["--transport", "carrier-pigeon"]),
+            "unknown transport: carrier-pigeon (expected one of tcp, quic, ws, memory)"
+        );
+        #[cfg(feature = "lean")]
+        assert_eq!(
+            error(&["--protocol", "paxos"]),
+            "unknown protocol: paxos (expected rb, pb, cb, avid or aba)"
+        );
+        #[cfg(not(feature = "lean"))]
+        {
+            assert_eq!(
+                error(&["--protocol", "paxos"]),
+                "unknown protocol: paxos (expected rb, pb, avid or aba)"
+            );
+            assert_eq!(error(&["--protocol", "cb"]), "cb needs the `lean` feature");
+        }
+        assert!(error(&["--listen", "localhost:4001"])
+            .starts_with("invalid multiaddr localhost:4001: "));
+        assert!(error(&["--peer", "nowhere"]).starts_with("invalid multiaddr nowhere: "));
+        assert!(error(&["--batch-interval", "-1"]).starts_with("invalid --batch-interval -1: "));
+        assert!(error(&["--batch-size", "many"]).starts_with("invalid --batch-size many: "));
+        assert!(error(&["--key-seed", "0x10"]).starts_with("invalid --key-seed 0x10: "));
+        assert!(error(&["--byzantine", "sneaky"]).starts_with("unknown byzantine strategy: sneaky"));
+        assert_eq!(error(&["--verbose"]), "unknown option: --verbose");
+        // a value that looks like a flag is still taken as the value
+        assert_eq!(
+            error(&["--transport", "--no-mdns"]),
+            "unknown transport: --no-mdns (expected one of tcp, quic, ws, memory)"
+        );
+    }
+
+    #[test]
+    fn byzantine_strategies_are_only_for_rb() {
+        assert_eq!(
+            parse(&["--protocol", "pb", "--byzantine", "silent"]).unwrap_err(),
+            "--byzantine only works with RB"
+        );
+        // whatever order the flags come in
+        assert_eq!(
+            parse(&["--byzantine", "silent", "--protocol", "avid"]).unwrap_err(),
+            "--byzantine only works with RB"
+        );
+        assert!(parse(&["--protocol", "rb", "--byzantine", "silent"]).is_ok());
+    }
 }
@@ -0,0 +1,406 @@
+use crate::networktest::rb_protocol::lean::{self, Message, NodeSnapshot, Packet, Protocol};
+use crate::networktest::simulator::node_address;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+
+// a bounded, exhaustive model checker for the compiled lean RB protocol.
+//
+// starting from the leader's broadcasts, it explores every order in which the in-flight packets
+// can be delivered, calling the real exported `handle_message` (through `Protocol`) at each step.
+// this complements the lean proofs: it tests the compiled artifact and the FFI glue,
+// not the protocol on paper.
+//
+// states are deduplicated by a hash of every node's marshalled state (see `NodeSnapshot`)
+// plus the multiset of packets in flight, so each distinct state is only expanded once.
+// the search space grows very quickly, so it's only meant for small clusters (N <= 4, 1-2 rounds),
+// and gives up (reporting an incomplete search) after `max_states` distinct states.
+//
+// every node is honest, so in every reachable state:
+// - integrity: each node has output at most one value per round
+// - agreement: nodes that have output agree on the value
+// - validity: the only value ever output is the leader's
+// and once nothing is in flight, every node must have output every round, or we've deadlocked.
+
+#[derive(Debug, Clone)]
+pub struct CheckConfig {
+    pub nodes: usize,
+    pub rounds: usize,
+    pub max_states: usize,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        CheckConfig {
+            nodes: 3,
+            rounds: 1,
+            max_states: 1_000_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Finding {
+    Violation {
+        description: String,
+        trace: Vec<String>,
+    },
+    // nothing left in flight, but some nodes never output
+    Deadlock {
+        missing: Vec<String>,
+        trace: Vec<String>,
+    },
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let trace = match self {
+            Finding::Violation { description, trace } => {
+                writeln!(f, "VIOLATION: {description}")?;
+                trace
+            }
+            Finding::Deadlock { missing, trace } => {
+                writeln!(f, "DEADLOCK: {}", missing.join(", "))?;
+                trace
+            }
+        };
+        writeln!(f, "trace:")?;
+        for (step, packet) in trace.iter().enumerate() {
+            writeln!(f, "  {step}: {packet}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckReport {
+    pub states: usize,
+    pub transitions: usize,
+    // false if we hit `max_states` before running out of states
+    pub complete: bool,
+    pub finding: Option<Finding>,
+}
+
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = match (&self.finding, self.complete) {
+            (Some(_), _) => "stopped at the first finding",
+            (None, true) => "complete",
+            (None, false) => "incomplete: hit the state limit",
+        };
+        writeln!(
+            f,
+            "explored {} distinct states ({} transitions), search {outcome}",
+            self.states, self.transitions
+        )?;
+        match &self.finding {
+            Some(finding) => write!(f, "{finding}"),
+            None => writeln!(f, "no violations or deadlocks found"),
+        }
+    }
+}
+
+struct State {
+    nodes: Vec<Protocol>,
+    in_flight: Vec<Packet>,
+    trace: Vec<String>,
+}
+
+impl State {
+    unsafe fn fork(&self) -> Self {
+        State {
+            nodes: self.nodes.iter().map(|node| node.fork()).collect(),
+            in_flight: self.in_flight.clone(),
+            trace: self.trace.clone(),
+        }
+    }
+
+    unsafe fn release(self) {
+        self.nodes.into_iter().for_each(|node| node.release());
+    }
+}
+
+pub struct ModelChecker {
+    config: CheckConfig,
+    addresses: Vec<String>,
+    values: Vec<String>,
+    // every message that has been sent on any path so far.
+    // snapshots only list messages a node has received, so growing this doesn't change them.
+    messages: Vec<Message>,
+    message_keys: HashSet<String>,
+    // fingerprints of the states we've already expanded.
+    // (a hash collision would make us skip a state, which we accept.)
+    visited: HashSet<u64>,
+    transitions: usize,
+}
+
+impl ModelChecker {
+    pub fn new(config: CheckConfig) -> Self {
+        ModelChecker {
+            addresses: (0..config.nodes).map(node_address).collect(),
+            values: (0..config.rounds)
+                .map(|round| format!("v{round}"))
+                .collect(),
+            config,
+            messages: Vec::new(),
+            message_keys: HashSet::new(),
+            visited: HashSet::new(),
+            transitions: 0,
+        }
+    }
+
+    fn remember(&mut self, packets: &[Packet]) {
+        for packet in packets {
+            if self.message_keys.insert(packet.msg.to_string()) {
+                self.messages.push(packet.msg.clone());
+            }
+        }
+    }
+
+    // every node starts fresh, and the leader has already broadcast every round.
+    unsafe fn initial_state(&mut self) -> State {
+        let leader = self.addresses[0].clone();
+        let mut nodes: Vec<Protocol> = self
+            .addresses
+            .iter()
+            .map(|address| {
+                Protocol::create(self.addresses.clone(), address.clone(), leader.clone())
+            })
+            .collect();
+
+        let mut in_flight = Vec::new();
+        for value in self.values.iter() {
+            in_flight.extend(nodes[0].send_message(leader.clone(), value.clone()));
+        }
+        self.remember(&in_flight);
+
+        State {
+            nodes,
+            in_flight,
+            trace: Vec::new(),
+        }
+    }
+
+    unsafe fn snapshots(&self, state: &State) -> Vec<NodeSnapshot> {
+        state
+            .nodes
+            .iter()
+            .zip(self.addresses.iter())
+            .map(|(node, address)| {
+                node.snapshot(address, &self.addresses, self.config.rounds, &self.messages)
+            })
+            .collect()
+    }
+
+    fn fingerprint(snapshots: &[NodeSnapshot], state: &State) -> u64 {
+        let mut in_flight: Vec<String> = state.in_flight.iter().map(Packet::to_string).collect();
+        in_flight.sort();
+
+        let mut hasher = DefaultHasher::new();
+        snapshots.hash(&mut hasher);
+        in_flight.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // the leader's outputs for `round`, per node
+    fn outputs<'a>(
+        &self,
+        snapshots: &'a [NodeSnapshot],
+        round: usize,
+    ) -> Vec<(&'a str, &'a [String])> {
+        let leader = &self.addresses[0];
+        snapshots
+            .iter()
+            .map(|snapshot| {
+                let values = snapshot
+                    .output
+                    .iter()
+                    .find(|(originator, r, _)| originator == leader && *r == round)
+                    .map_or(&[][..], |(_, _, values)| values.as_slice());
+                (snapshot.address.as_str(), values)
+            })
+            .collect()
+    }
+
+    fn check_safety(&self, snapshots: &[NodeSnapshot]) -> Option<String> {
+        for (round, input) in self.values.iter().enumerate() {
+            let outputs = self.outputs(snapshots, round);
+            let mut agreed: Option<(&str, &String)> = None;
+
+            for (node, values) in outputs {
+                if values.len() > 1 {
+                    return Some(format!(
+                        "integrity @ round {round}: {node} output {values:?}"
+                    ));
+                }
+                let Some(value) = values.first() else {
+                    continue;
+                };
+                if value != input {
+                    return Some(format!(
+                        "validity @ round {round}: {node} output '{value}' instead of '{input}'"
+                    ));
+                }
+                match agreed {
+                    Some((other, agreed_value)) if agreed_value != value => {
+                        return Some(format!(
+                            "agreement @ round {round}: {other} output '{agreed_value}' but {node} output '{value}'"
+                        ));
+                    }
+                    Some(_) => (),
+                    None => agreed = Some((node, value)),
+                }
+            }
+        }
+        None
+    }
+
+    fn check_deadlock(&self, snapshots: &[NodeSnapshot]) -> Vec<String> {
+        let mut missing = Vec::new();
+        for round in 0..self.config.rounds {
+            for (node, values) in self.outputs(snapshots, round) {
+                if values.is_empty() {
+                    missing.push(format!("{node} never output round {round}"));
+                }
+            }
+        }
+        missing
+    }
+
+    // delivers the `i`-th in-flight packet in a copy of `state`.
+    unsafe fn successor(&mut self, state: &State, i: usize) -> State {
+        let mut next = state.fork();
+        let packet = next.in_flight.remove(i);
+        let dst = self
+            .addresses
+            .iter()
+            .position(|address| *address == packet.dst)
+            .expect("packets should only be addressed to known nodes");
+
+        next.trace.push(packet.to_string());
+        let packets = next.nodes[dst].handle_packet(packet);
+        self.remember(&packets);
+        next.in_flight.extend(packets);
+        self.transitions += 1;
+        next
+    }
+
+    /// Explores every reachable state (up to `max_states`), depth first,
+    /// and stops at the first violation or deadlock.
+    pub fn run(mut self) -> CheckReport {
+        lean::initialize();
+
+        let mut stack = vec![unsafe { self.initial_state() }];
+        let mut finding = None;
+        let mut complete = true;
+
+        while let Some(state) = stack.pop() {
+            let snapshots = unsafe { self.snapshots(&state) };
+            if !self.visited.insert(Self::fingerprint(&snapshots, &state)) {
+                unsafe { state.release() };
+                continue;
+            }
+
+            if let Some(description) = self.check_safety(&snapshots) {
+                finding = Some(Finding::Violation {
+                    description,
+                    trace: state.trace.clone(),
+                });
+                unsafe { state.release() };
+                break;
+            }
+
+            if state.in_flight.is_empty() {
+                let missing = self.check_deadlock(&snapshots);
+                if !missing.is_empty() {
+                    finding = Some(Finding::Deadlock {
+                        missing,
+                        trace: state.trace.clone(),
+                    });
+                    unsafe { state.release() };
+                    break;
+                }
+            }
+
+            if self.visited.len() >= self.config.max_states {
+                complete = false;
+                unsafe { state.release() };
+                break;
+            }
+
+            // identical packets lead to identical states, so only deliver one of each.
+            let mut seen = HashSet::new();
+            for i in 0..state.in_flight.len() {
+                if seen.insert(state.in_flight[i].to_string()) {
+                    stack.push(unsafe { self.successor(&state, i) });
+                }
+            }
+            unsafe { state.release() };
+        }
+
+        stack
+            .into_iter()
+            .for_each(|state| unsafe { state.release() });
+
+        CheckReport {
+            states: self.visited.len(),
+            transitions: self.transitions,
+            complete,
+            finding,
+        }
+    }
+}
+
+// cargo run -- mc [nodes] [rounds] [max states]
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let defaults = CheckConfig::default();
+    let arg = |i: usize, default: usize| -> usize {
+        args.get(i)
+            .map(|a| {
+                a.parse()
+                    .expect("model checker arguments should be numbers")
+            })
+            .unwrap_or(default)
+    };
+    let config = CheckConfig {
+        nodes: arg(0, defaults.nodes),
+        rounds: arg(1, defaults.rounds),
+        max_states: arg(2, defaults.max_states),
+    };
+    if config.nodes > 4 || config.rounds > 2 {
+        println!("warning: the state space for {config:?} is probably too large to explore");
+    }
+
+    let report = ModelChecker::new(config).run();
+    print!("{report}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_nodes_are_exhaustively_safe() {
+        let report = ModelChecker::new(CheckConfig {
+            nodes: 2,
+            rounds: 1,
+            ..CheckConfig::default()
+        })
+        .run();
+        assert!(report.finding.is_none(), "{report}");
+        assert!(report.complete, "{report}");
+    }
+
+    #[test]
+    fn four_nodes_two_rounds_are_safe_up_to_the_bound() {
+        let report = ModelChecker::new(CheckConfig {
+            nodes: 4,
+            rounds: 2,
+            max_states: 5_000,
+        })
+        .run();
+        assert!(report.finding.is_none(), "{report}");
+    }
+}
@@ -0,0 +1,146 @@
+use crate::networktest::rb_protocol::{self, Delivery};
+use libp2p::identity::Keypair;
+use serde::{de::DeserializeOwned, Serialize};
+use std::error::Error;
+use std::fmt::{Debug, Display};
+
+// a rust mirror of lean-sts's `NetworkProtocol` (`LeanSts/BFT/Network.lean`), so that one network
+// runtime (`libp2p_rb`, and its protocol actor) can run any protocol: the lean RB, the native PB,
+// and whatever comes next.
+//
+// like in lean, the protocol itself is a value (its parameters, e.g. the members), and every node's
+// state is separate from it. unlike lean, the transitions update the state in place.
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Packet<A, M> {
+    pub src: A,
+    pub dst: A,
+    pub msg: M,
+    pub consumed: bool,
+}
+
+impl<A: Display, M: Display> Display for Packet<A, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "packet from {} to {} with message '{}' (consumed: {})",
+            self.src, self.dst, self.msg, self.consumed
+        )
+    }
+}
+
+pub trait NetworkProtocol {
+    type Address: Clone + Eq + Debug;
+    /// What goes over the wire, so it has to be serializable.
+    type Message: Clone + Debug + Serialize + DeserializeOwned;
+    type InternalTransition;
+    type State;
+    type Output: Clone + Debug;
+
+    fn local_init(&self, address: &Self::Address) -> Self::State;
+
+    fn proc_internal(
+        &self,
+        state: &mut Self::State,
+        transition: Self::InternalTransition,
+    ) -> Vec<Packet<Self::Address, Self::Message>>;
+
+    fn proc_message(
+        &self,
+        state: &mut Self::State,
+        src: Self::Address,
+        msg: Self::Message,
+    ) -> Vec<Packet<Self::Address, Self::Message>>;
+
+    /// Everything the node has output so far, in the order it output them.
+    /// Outputs are never taken back, so the driver only reports the ones past what it's seen.
+    fn outputs(&self, state: &Self::State) -> Vec<Self::Output>;
+}
+
+/// What the libp2p driver needs on top of `NetworkProtocol`.
+/// Addresses are peer ids, and everything that crosses threads has to be `Send`.
+pub trait DrivenProtocol:
+    NetworkProtocol<
+        Address = String,
+        Message: Display + Send + 'static,
+        Output: Display + Serialize + Send + 'static,
+    > + Sized
+    + 'static
+{
+    /// Whatever the protocol is set up with, besides its members (e.g. RB's byzantine strategy).
+    type Config: Clone + Send + 'static;
+
+    /// Sets up the protocol once the members are fixed.
+    /// This runs on the protocol actor's thread, which is the only one that touches the protocol.
+    /// `keypair` is the node's libp2p identity, for protocols that sign with it.
+    fn create(
+        config: Self::Config,
+        members: Vec<String>,
+        leader: String,
+        keypair: &Keypair,
+    ) -> Result<Self, String>;
+
+    /// Turns down members and leaders that `create` would fail on, before they get to the actor.
+    /// The driver answers the `init` command with the error.
+    fn validate(_members: &[String], _leader: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Turns down values that the protocol can't broadcast, before they get to the actor.
+    /// The driver answers the `broadcast` command with the error.
+    fn check_value(_value: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The internal transition that broadcasts `value`, once it's passed `check_value`.
+    fn broadcast(&self, state: &mut Self::State, value: String) -> Self::InternalTransition;
+
+    // traces (see `trace.rs`) are in terms of RB's messages and deliveries,
+    // so by default, a protocol doesn't show up in them.
+    fn traced_packet(_packet: &Packet<String, Self::Message>) -> Option<rb_protocol::Packet> {
+        None
+    }
+
+    fn traced_output(_output: &Self::Output) -> Option<Delivery> {
+        None
+    }
+
+    /// An output as a file (its name and contents), for protocols that export their outputs,
+    /// e.g. PB's certificates. The driver writes them out with `--export <dir>`.
+    fn export(_output: &Self::Output) -> Option<(String, String)> {
+        None
+    }
+}
+
+// the driver sends all packets via `Request`s, and acknowledges receiving them via a `Response`.
+// packets to the same peer are batched, so one request (and one ack) can carry several of them.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Request<M> {
+    pub packets: Vec<Packet<String, M>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub enum Response {
+    Ack,
+    // the request came from a peer that isn't a member of the protocol.
+    Refused,
+}
+
+// may want to consider using a crate like `derive_more` to help us derive
+// `Display` here.
+impl<M> Display for Request<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request: batch of {} packets", self.packets.len())
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Response::Ack => write!(f, "response: ack"),
+            Response::Refused => write!(f, "response: refused"),
+        }
+    }
+}
+
+impl Error for Response {}
@@ -0,0 +1,496 @@
+// provable broadcast, as specified in `lib/ProvableBroadcast.lean` and exported by `lib/PBProtocol.lean`.
+// this is the lean counterpart of the native `crate::protocol`, with concrete types:
+// addresses, values and proofs are strings, rounds are `usize`, and partial signatures are strings
+// too (e.g. hex), since lean never looks inside them.
+
+// the messages and packets of `ProvableBroadcast.lean`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    InitMsg { r: usize, v: String, proof: String },
+    EchoMsg { r: usize, partial_signature: String },
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::InitMsg { r, v, proof } => {
+                write!(f, "InitMsg @ round {}: {} (proof: {})", r, v, proof)
+            }
+            Message::EchoMsg {
+                r,
+                partial_signature,
+            } => write!(f, "EchoMsg @ round {}: {}", r, partial_signature),
+        }
+    }
+}
+
+pub type Packet = super::network_protocol::Packet<String, Message>;
+
+/// What a node running the lean PB needs from the application.
+/// The same as `crate::protocol::PbParams`, for one node, with the lean binding's types.
+pub trait PbCallbacks {
+    /// The value and proof this node broadcasts in `round`.
+    /// Lean asks for them once per broadcast, so they always belong together.
+    fn value_bft(&self, round: usize) -> (String, String);
+
+    fn externally_validate(&self, sender: &str, round: usize, value: &str, proof: &str) -> bool;
+
+    /// This node's partial signature on `sender`'s value.
+    fn partially_sign(&self, sender: &str, round: usize, value: &str) -> String;
+
+    /// Whether `signature` is `signer`'s partial signature on this node's value.
+    fn partially_validate(
+        &self,
+        signer: &str,
+        sender: &str,
+        round: usize,
+        value: &str,
+        signature: &str,
+    ) -> bool;
+}
+
+#[cfg(feature = "lean")]
+pub mod lean {
+    use crate::ffitest::lean_helpers::{self, lean_string_to_rust, rust_string_to_lean, Mode};
+    use lean_sys::*;
+    use std::cell::Cell;
+
+    pub use super::{Message, Packet, PbCallbacks};
+
+    // PB is linked into the same fat library as RB, and initialized along with it.
+    pub use crate::networktest::rb_protocol::lean::initialize;
+
+    #[link(name = "ProtocolFat", kind = "static")]
+    extern "C" {
+        fn pb_create_protocol(
+            node_arr: lean_sys::lean_obj_arg,
+            me: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+        fn pb_create_init(
+            r: usize,
+            v: lean_sys::lean_obj_arg,
+            p: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+        fn pb_create_echo(r: usize, ps: lean_sys::lean_obj_arg) -> lean_sys::lean_obj_res;
+        fn pb_create_packet(
+            src: lean_sys::lean_obj_arg,
+            dst: lean_sys::lean_obj_arg,
+            msg: lean_sys::lean_obj_arg,
+            consumed: u8,
+        ) -> lean_sys::lean_obj_res;
+        fn pb_init_node_state(
+            p: lean_sys::lean_obj_arg,
+            node_address: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+        fn pb_send_message(
+            p: lean_sys::lean_obj_arg,
+            node_state: lean_sys::lean_obj_arg,
+            round: usize,
+        ) -> lean_sys::lean_obj_res;
+        fn pb_handle_message(
+            p: lean_sys::lean_obj_arg,
+            node_state: lean_sys::lean_obj_arg,
+            src: lean_sys::lean_obj_arg,
+            msg: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+        fn pb_check_output(
+            node_state: lean_sys::lean_obj_arg,
+            round: usize,
+        ) -> lean_sys::lean_obj_res;
+    }
+
+    // the callbacks of the node that's currently calling into lean on this thread.
+    // lean only calls back while we're inside one of its exports, so the externs below
+    // can just look here, instead of keeping a global table of every node's callbacks.
+    thread_local! {
+        static CURRENT: Cell<Option<*const dyn PbCallbacks>> = const { Cell::new(None) };
+    }
+
+    // sets `CURRENT` for as long as it's alive.
+    struct Calling(Option<*const dyn PbCallbacks>);
+
+    impl Calling {
+        // the caller keeps `callbacks` borrowed while the guard lives.
+        fn new(callbacks: &(dyn PbCallbacks + 'static)) -> Self {
+            let callbacks: *const dyn PbCallbacks = callbacks;
+            Calling(CURRENT.with(|current| current.replace(Some(callbacks))))
+        }
+    }
+
+    impl Drop for Calling {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.set(self.0));
+        }
+    }
+
+    fn with_current<T>(f: impl FnOnce(&dyn PbCallbacks) -> T) -> T {
+        let callbacks = CURRENT
+            .with(Cell::get)
+            .expect("lean should only call back from inside a PB export");
+        f(unsafe { &*callbacks })
+    }
+
+    // the callbacks for `pb_params` in `PBProtocol.lean`. every lean argument is borrowed (`@&`).
+    // `me` is always the node in `CURRENT`, so we don't need it.
+
+    // the value and the proof come from a single call, so a callback that picks a fresh value
+    // every time can't pair one broadcast's value with another's proof.
+    // a `PBValue × PBProof` is a `Prod.mk`: tag 0, with both strings as (boxed) fields.
+    #[no_mangle]
+    pub unsafe extern "C" fn pb_get_node_value_proof(
+        _me: *mut lean_object,
+        round: usize,
+    ) -> *mut lean_object {
+        let (value, proof) = with_current(|callbacks| callbacks.value_bft(round));
+        let pair = lean_alloc_ctor(0, 2, 0);
+        lean_ctor_set(pair, 0, rust_string_to_lean(value));
+        lean_ctor_set(pair, 1, rust_string_to_lean(proof));
+        pair
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn pb_externally_validate(
+        _me: *mut lean_object,
+        sender: *mut lean_object,
+        round: usize,
+        value: *mut lean_object,
+        proof: *mut lean_object,
+    ) -> u8 {
+        let sender = lean_string_to_rust(sender, Mode::Borrow);
+        let value = lean_string_to_rust(value, Mode::Borrow);
+        let proof = lean_string_to_rust(proof, Mode::Borrow);
+        with_current(|callbacks| callbacks.externally_validate(&sender, round, &value, &proof))
+            as u8
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn pb_partially_sign(
+        _me: *mut lean_object,
+        sender: *mut lean_object,
+        round: usize,
+        value: *mut lean_object,
+    ) -> *mut lean_object {
+        let sender = lean_string_to_rust(sender, Mode::Borrow);
+        let value = lean_string_to_rust(value, Mode::Borrow);
+        rust_string_to_lean(with_current(|callbacks| {
+            callbacks.partially_sign(&sender, round, &value)
+        }))
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn pb_partially_validate(
+        _me: *mut lean_object,
+        signer: *mut lean_object,
+        sender: *mut lean_object,
+        round: usize,
+        value: *mut lean_object,
+        signature: *mut lean_object,
+    ) -> u8 {
+        let signer = lean_string_to_rust(signer, Mode::Borrow);
+        let sender = lean_string_to_rust(sender, Mode::Borrow);
+        let value = lean_string_to_rust(value, Mode::Borrow);
+        let signature = lean_string_to_rust(signature, Mode::Borrow);
+        with_current(|callbacks| {
+            callbacks.partially_validate(&signer, &sender, round, &value, &signature)
+        }) as u8
+    }
+
+    // `PB.Message` is polymorphic in the round, so (unlike a plain `USize` field) the round is boxed,
+    // and the fields are in declaration order:
+    // | InitMsg { r, v, proof }
+    // | EchoMsg { r, partial_signature }
+    impl Message {
+        /// Converts a Lean message to its Rust representation. The message is borrowed.
+        pub unsafe fn from_lean(msg_lean: *mut lean_object) -> Self {
+            let r = lean_unbox_usize(lean_ctor_get(msg_lean, 0));
+            match lean_ptr_tag(msg_lean) {
+                0 => Message::InitMsg {
+                    r,
+                    v: lean_string_to_rust(lean_ctor_get(msg_lean, 1), Mode::Borrow),
+                    proof: lean_string_to_rust(lean_ctor_get(msg_lean, 2), Mode::Borrow),
+                },
+                1 => Message::EchoMsg {
+                    r,
+                    partial_signature: lean_string_to_rust(
+                        lean_ctor_get(msg_lean, 1),
+                        Mode::Borrow,
+                    ),
+                },
+                _ => panic!("unexpected tag"),
+            }
+        }
+
+        // Takes ownership of the Rust Message.
+        pub unsafe fn to_lean(self) -> *mut lean_object {
+            match self {
+                Self::InitMsg { r, v, proof } => {
+                    pb_create_init(r, rust_string_to_lean(v), rust_string_to_lean(proof))
+                }
+                Self::EchoMsg {
+                    r,
+                    partial_signature,
+                } => pb_create_echo(r, rust_string_to_lean(partial_signature)),
+            }
+        }
+    }
+
+    impl Packet {
+        /// Converts a Lean packet to its Rust representation. The packet is borrowed.
+        pub unsafe fn from_lean(packet_lean: *mut lean_object) -> Self {
+            let consumed_lean_offset: std::ffi::c_uint =
+                (3 * lean_helpers::VOID_PTR_SIZE).try_into().unwrap();
+            Packet {
+                src: lean_string_to_rust(lean_ctor_get(packet_lean, 0), Mode::Borrow),
+                dst: lean_string_to_rust(lean_ctor_get(packet_lean, 1), Mode::Borrow),
+                msg: Message::from_lean(lean_ctor_get(packet_lean, 2)),
+                consumed: lean_ctor_get_uint8(packet_lean, consumed_lean_offset) != 0,
+            }
+        }
+
+        // Takes ownership of the rust packet.
+        pub unsafe fn to_lean(self) -> *mut lean_object {
+            pb_create_packet(
+                rust_string_to_lean(self.src),
+                rust_string_to_lean(self.dst),
+                Message::to_lean(self.msg),
+                self.consumed as u8,
+            )
+        }
+    }
+
+    /// One node running the lean PB.
+    pub struct Protocol {
+        protocol: *mut lean_object,
+        node_state: *mut lean_object,
+        callbacks: Box<dyn PbCallbacks>,
+    }
+
+    impl Protocol {
+        pub unsafe fn create(
+            node_list: Vec<String>,
+            address: String,
+            callbacks: Box<dyn PbCallbacks>,
+        ) -> Self {
+            let node_array_lean = lean_helpers::rust_string_vec_to_lean_array(node_list);
+            let protocol =
+                pb_create_protocol(node_array_lean, rust_string_to_lean(address.clone()));
+
+            // RC: exports take ownership of their arguments, and we keep using `protocol`.
+            lean_inc(protocol);
+            let node_state = pb_init_node_state(protocol, rust_string_to_lean(address));
+
+            Protocol {
+                protocol,
+                node_state,
+                callbacks,
+            }
+        }
+
+        /// Deconstructs a Lean (new_state, packets_to_send) tuple into its Rust representation.
+        /// This function TAKES OWNERSHIP of `state_and_packets`, and returns ownership of the new state.
+        // (unlike RB's, this one borrows everything it reads, so it can free the whole tuple.)
+        unsafe fn deconstruct_state_and_packets(
+            state_and_packets: *mut lean_object,
+        ) -> (*mut lean_object, Vec<Packet>) {
+            assert!(lean_is_ctor(state_and_packets));
+            let new_state = lean_ctor_get(state_and_packets, 0);
+            let packets_arr_lean = lean_ctor_get(state_and_packets, 1);
+            assert!(lean_is_array(packets_arr_lean));
+
+            let packets = (0..lean_array_size(packets_arr_lean))
+                .map(|i| Packet::from_lean(lean_array_get_core(packets_arr_lean, i)))
+                .collect();
+
+            // RC: keep the state alive, and free the tuple along with the packets.
+            lean_inc(new_state);
+            lean_dec(state_and_packets);
+            (new_state, packets)
+        }
+
+        /// Broadcasts this node's value for `round`. Does nothing if it already has.
+        pub unsafe fn send_message(&mut self, round: usize) -> Vec<Packet> {
+            let _calling = Calling::new(self.callbacks.as_ref());
+            lean_inc(self.protocol);
+            let state_and_packets = pb_send_message(self.protocol, self.node_state, round);
+            let (new_state, packets) = Self::deconstruct_state_and_packets(state_and_packets);
+            self.node_state = new_state;
+            packets
+        }
+
+        pub unsafe fn handle_packet(&mut self, packet: Packet) -> Vec<Packet> {
+            let _calling = Calling::new(self.callbacks.as_ref());
+            let src_lean = rust_string_to_lean(packet.src);
+            let msg_lean = packet.msg.to_lean();
+            lean_inc(self.protocol);
+            let state_and_packets =
+                pb_handle_message(self.protocol, self.node_state, src_lean, msg_lean);
+            let (new_state, packets) = Self::deconstruct_state_and_packets(state_and_packets);
+            self.node_state = new_state;
+            packets
+        }
+
+        /// The (signer, partial signature) pairs this node combined for its broadcast in `round`,
+        /// if it got enough of them.
+        pub unsafe fn check_output(&self, round: usize) -> Option<Vec<(String, String)>> {
+            lean_inc(self.node_state);
+            let output = pb_check_output(self.node_state, round);
+            let signers = lean_helpers::lean_string_array_to_rust(lean_ctor_get(output, 0));
+            let signatures = lean_helpers::lean_string_array_to_rust(lean_ctor_get(output, 1));
+            lean_dec(output);
+
+            if signers.is_empty() {
+                None
+            } else {
+                Some(signers.into_iter().zip(signatures).collect())
+            }
+        }
+    }
+
+    impl Drop for Protocol {
+        fn drop(&mut self) {
+            unsafe {
+                lean_dec(self.protocol);
+                lean_dec(self.node_state);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::cell::Cell;
+        use std::collections::VecDeque;
+
+        // signatures anyone can forge, which is enough to check the bookkeeping.
+        struct Node {
+            address: String,
+        }
+
+        impl PbCallbacks for Node {
+            fn value_bft(&self, round: usize) -> (String, String) {
+                (format!("{}@{round}", self.address), String::from("proof"))
+            }
+
+            fn externally_validate(&self, _: &str, _: usize, _: &str, proof: &str) -> bool {
+                proof == "proof"
+            }
+
+            fn partially_sign(&self, sender: &str, round: usize, value: &str) -> String {
+                format!("{}:{sender}:{round}:{value}", self.address)
+            }
+
+            fn partially_validate(
+                &self,
+                signer: &str,
+                sender: &str,
+                round: usize,
+                value: &str,
+                signature: &str,
+            ) -> bool {
+                signature == format!("{signer}:{sender}:{round}:{value}")
+            }
+        }
+
+        #[test]
+        fn sender_outputs_threshold_signatures() {
+            initialize();
+            let addresses: Vec<String> = (0..4).map(|i| format!("node{i}")).collect();
+            let mut nodes: Vec<Protocol> = addresses
+                .iter()
+                .map(|address| unsafe {
+                    Protocol::create(
+                        addresses.clone(),
+                        address.clone(),
+                        Box::new(Node {
+                            address: address.clone(),
+                        }),
+                    )
+                })
+                .collect();
+
+            let mut in_flight: VecDeque<Packet> = unsafe { nodes[0].send_message(0) }.into();
+            while let Some(packet) = in_flight.pop_front() {
+                let dst = addresses.iter().position(|a| *a == packet.dst).unwrap();
+                // deliver everything twice: the duplicates must not count.
+                in_flight.extend(unsafe { nodes[dst].handle_packet(packet.clone()) });
+                in_flight.extend(unsafe { nodes[dst].handle_packet(packet) });
+            }
+
+            // N = 4, f = 1
+            let output = unsafe { nodes[0].check_output(0) }.expect("sender should output");
+            assert_eq!(output.len(), 3);
+            for (signer, signature) in &output {
+                assert_eq!(*signature, format!("{signer}:node0:0:node0@0"));
+            }
+            for node in &nodes[1..] {
+                assert_eq!(unsafe { node.check_output(0) }, None);
+            }
+        }
+
+        // picks a fresh value (with its own proof) every time it's asked for one.
+        struct Fresh {
+            address: String,
+            picked: Cell<usize>,
+        }
+
+        impl PbCallbacks for Fresh {
+            fn value_bft(&self, round: usize) -> (String, String) {
+                let picked = self.picked.get();
+                self.picked.set(picked + 1);
+                (
+                    format!("{}@{round}#{picked}", self.address),
+                    format!("proof#{picked}"),
+                )
+            }
+
+            // the proof has to be the one that came with the value.
+            fn externally_validate(&self, _: &str, _: usize, value: &str, proof: &str) -> bool {
+                value.rsplit_once('#').map(|(_, picked)| picked) == proof.strip_prefix("proof#")
+            }
+
+            fn partially_sign(&self, sender: &str, round: usize, value: &str) -> String {
+                format!("{}:{sender}:{round}:{value}", self.address)
+            }
+
+            fn partially_validate(
+                &self,
+                signer: &str,
+                sender: &str,
+                round: usize,
+                value: &str,
+                signature: &str,
+            ) -> bool {
+                signature == format!("{signer}:{sender}:{round}:{value}")
+            }
+        }
+
+        #[test]
+        fn value_and_proof_come_from_the_same_pick() {
+            initialize();
+            let addresses: Vec<String> = (0..4).map(|i| format!("fresh{i}")).collect();
+            let mut nodes: Vec<Protocol> = addresses
+                .iter()
+                .map(|address| unsafe {
+                    Protocol::create(
+                        addresses.clone(),
+                        address.clone(),
+                        Box::new(Fresh {
+                            address: address.clone(),
+                            picked: Cell::new(0),
+                        }),
+                    )
+                })
+                .collect();
+
+            let mut in_flight: VecDeque<Packet> = unsafe { nodes[0].send_message(0) }.into();
+            while let Some(packet) = in_flight.pop_front() {
+                let dst = addresses.iter().position(|a| *a == packet.dst).unwrap();
+                in_flight.extend(unsafe { nodes[dst].handle_packet(packet) });
+            }
+
+            // every member validated the init, so the sender got everyone's echo.
+            let output = unsafe { nodes[0].check_output(0) }.expect("sender should output");
+            assert_eq!(output.len(), 3);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use crate::networktest::network_protocol::{DrivenProtocol, NetworkProtocol, Packet};
+use crate::networktest::rb_protocol;
+use libp2p::identity::Keypair;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+// the protocol actor owns the lean runtime and the protocol on a single, dedicated OS thread.
+//
+// lean is not safe to call from arbitrary tokio worker threads (its runtime expects to be
+// initialized on the thread that calls into it), and a slow lean step would otherwise stall
+// the `select!` loop that polls the swarm.
+// so instead, the network task sends `Command`s to the actor, and the actor replies with `Event`s.
+// both directions go over bounded channels.
+//
+// the actor runs any `DrivenProtocol` (see `network_protocol.rs`), not just RB.
+
+type ProtocolPacket<P> = Packet<String, <P as NetworkProtocol>::Message>;
+
+pub enum Command<P: DrivenProtocol> {
+    Init {
+        node_list: Vec<String>,
+        address: String,
+        leader: String,
+        keypair: Keypair,
+    },
+    Broadcast {
+        message: String,
+    },
+    // a batch of packets received in a single request.
+    HandlePackets {
+        packets: Vec<ProtocolPacket<P>>,
+    },
+    Shutdown,
+}
+
+#[derive(Debug)]
+pub enum Event<P: DrivenProtocol> {
+    Initialized,
+    // `create` turned down the members or leader that `init` was given.
+    InitFailed { error: String },
+    // packets that have to be sent to OTHER nodes.
+    // packets addressed to this node are looped back inside the actor and never leave it.
+    Outbound { packets: Vec<ProtocolPacket<P>> },
+    // something new in the protocol's outputs, e.g. RB delivering a round.
+    // every output is emitted once.
+    Output { output: P::Output },
+}
+
+// backpressure metrics, shared between the actor thread and the network task.
+#[derive(Default, Debug)]
+struct ActorMetrics {
+    commands_submitted: AtomicU64,
+    commands_processed: AtomicU64,
+    // number of times the network task found the command channel full
+    command_backpressure: AtomicU64,
+    // number of times the actor found the event channel full
+    event_backpressure: AtomicU64,
+    // slowest single lean step observed so far
+    max_step_micros: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct MetricsSnapshot {
+    pub commands_submitted: u64,
+    pub commands_processed: u64,
+    pub channel_depth: usize,
+    pub backlog_depth: usize,
+    pub command_backpressure: u64,
+    pub event_backpressure: u64,
+    pub max_step_micros: u64,
+}
+
+impl Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "submitted: {}, processed: {}, in channel: {}, in backlog: {}, command backpressure: {}, event backpressure: {}, slowest step: {}us",
+            self.commands_submitted,
+            self.commands_processed,
+            self.channel_depth,
+            self.backlog_depth,
+            self.command_backpressure,
+            self.event_backpressure,
+            self.max_step_micros
+        )
+    }
+}
+
+/// Network-side handle to the protocol actor.
+///
+/// `submit` never blocks: if the command channel is full, the command is parked in a local
+/// backlog, which the network task drains by awaiting `flush`.
+/// Callers should stop reading from the network while `is_saturated` is true.
+pub struct ProtocolHandle<P: DrivenProtocol> {
+    commands: mpsc::Sender<Command<P>>,
+    backlog: VecDeque<Command<P>>,
+    backlog_capacity: usize,
+    metrics: Arc<ActorMetrics>,
+}
+
+impl<P: DrivenProtocol> ProtocolHandle<P> {
+    pub fn submit(&mut self, command: Command<P>) {
+        self.metrics
+            .commands_submitted
+            .fetch_add(1, Ordering::Relaxed);
+
+        // preserve ordering: once anything is in the backlog, everything goes through it.
+        if !self.backlog.is_empty() {
+            self.backlog.push_back(command);
+            return;
+        }
+
+        match self.commands.try_send(command) {
+            Ok(()) => (),
+            Err(TrySendError::Full(command)) => {
+                self.metrics
+                    .command_backpressure
+                    .fetch_add(1, Ordering::Relaxed);
+                self.backlog.push_back(command);
+            }
+            Err(TrySendError::Closed(_)) => panic!("protocol actor has stopped"),
+        }
+    }
+
+    pub fn has_backlog(&self) -> bool {
+        !self.backlog.is_empty()
+    }
+
+    pub fn is_saturated(&self) -> bool {
+        self.backlog.len() >= self.backlog_capacity
+    }
+
+    /// Waits for space in the command channel, then moves one command out of the backlog.
+    /// This is cancel-safe, so it can be used as a `select!` branch.
+    pub async fn flush(&mut self) {
+        if self.backlog.is_empty() {
+            return;
+        }
+        let permit = self
+            .commands
+            .reserve()
+            .await
+            .expect("protocol actor has stopped");
+        permit.send(self.backlog.pop_front().unwrap());
+    }
+
+    pub fn metrics(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            commands_submitted: self.metrics.commands_submitted.load(Ordering::Relaxed),
+            commands_processed: self.metrics.commands_processed.load(Ordering::Relaxed),
+            channel_depth: self.commands.max_capacity() - self.commands.capacity(),
+            backlog_depth: self.backlog.len(),
+            command_backpressure: self.metrics.command_backpressure.load(Ordering::Relaxed),
+            event_backpressure: self.metrics.event_backpressure.load(Ordering::Relaxed),
+            max_step_micros: self.metrics.max_step_micros.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Spawns the protocol actor on its own thread.
+/// `capacity` bounds both channels, and the backlog on the network side.
+pub fn spawn<P: DrivenProtocol>(
+    capacity: usize,
+    config: P::Config,
+) -> (ProtocolHandle<P>, mpsc::Receiver<Event<P>>) {
+    let (command_tx, command_rx) = mpsc::channel(capacity);
+    let (event_tx, event_rx) = mpsc::channel(capacity);
+    let metrics = Arc::new(ActorMetrics::default());
+
+    let actor_metrics = metrics.clone();
+    thread::Builder::new()
+        .name(String::from("rb-protocol"))
+        .spawn(move || run(command_rx, event_tx, actor_metrics, config))
+        .expect("should be able to spawn the protocol thread");
+
+    let handle = ProtocolHandle {
+        commands: command_tx,
+        backlog: VecDeque::new(),
+        backlog_capacity: capacity,
+        metrics,
+    };
+    (handle, event_rx)
+}
+
+struct Running<P: NetworkProtocol> {
+    protocol: P,
+    state: P::State,
+    // how many of the protocol's outputs we've emitted
+    reported: usize,
+}
+
+struct Actor<P: DrivenProtocol> {
+    config: P::Config,
+    running: Option<Running<P>>,
+    address: String,
+    events: mpsc::Sender<Event<P>>,
+    metrics: Arc<ActorMetrics>,
+}
+
+impl<P: DrivenProtocol> Actor<P> {
+    fn emit(&self, event: Event<P>) {
+        match self.events.try_send(event) {
+            Ok(()) => (),
+            Err(TrySendError::Full(event)) => {
+                self.metrics
+                    .event_backpressure
+                    .fetch_add(1, Ordering::Relaxed);
+                // the network task always drains events, so blocking here cannot deadlock.
+                let _ = self.events.blocking_send(event);
+            }
+            // the network task has gone away, nobody is listening anymore.
+            Err(TrySendError::Closed(_)) => (),
+        }
+    }
+
+    // runs the protocol on `packets` until none of them are addressed to this node,
+    // then emits the ones that have to go out over the network, along with any new outputs.
+    fn step(&mut self, packets: Vec<ProtocolPacket<P>>) {
+        let running = self
+            .running
+            .as_mut()
+            .expect("protocol should be initialized");
+        let mut outbound = Vec::new();
+        let mut local = VecDeque::from(packets);
+
+        while let Some(packet) = local.pop_front() {
+            if packet.dst != self.address {
+                outbound.push(packet);
+                continue;
+            }
+
+            let packets_to_send =
+                running
+                    .protocol
+                    .proc_message(&mut running.state, packet.src, packet.msg);
+            local.extend(packets_to_send);
+        }
+
+        let outputs = running.protocol.outputs(&running.state);
+        let new_outputs = outputs.get(running.reported..).unwrap_or(&[]).to_vec();
+        running.reported = outputs.len();
+
+        self.emit(Event::Outbound { packets: outbound });
+        for output in new_outputs {
+            self.emit(Event::Output { output });
+        }
+    }
+
+    fn handle(&mut self, command: Command<P>) {
+        match command {
+            Command::Init {
+                node_list,
+                address,
+                leader,
+                keypair,
+            } => {
+                let protocol = match P::create(self.config.clone(), node_list, leader, &keypair) {
+                    Ok(protocol) => protocol,
+                    Err(error) => {
+                        self.emit(Event::InitFailed { error });
+                        return;
+                    }
+                };
+                let state = protocol.local_init(&address);
+                self.running = Some(Running {
+                    protocol,
+                    state,
+                    reported: 0,
+                });
+                self.address = address;
+                self.emit(Event::Initialized);
+            }
+            Command::Broadcast { message } => {
+                let Some(running) = self.running.as_mut() else {
+                    println!("[rb_actor] dropping broadcast: protocol not yet initialized");
+                    return;
+                };
+                let transition = running.protocol.broadcast(&mut running.state, message);
+                let packets = running
+                    .protocol
+                    .proc_internal(&mut running.state, transition);
+                self.step(packets);
+            }
+            Command::HandlePackets { packets } => {
+                if self.running.is_none() {
+                    println!("[rb_actor] dropping packets: protocol not yet initialized");
+                    return;
+                }
+                self.step(packets);
+            }
+            Command::Shutdown => unreachable!("handled by the actor loop"),
+        }
+    }
+}
+
+fn run<P: DrivenProtocol>(
+    mut commands: mpsc::Receiver<Command<P>>,
+    events: mpsc::Sender<Event<P>>,
+    metrics: Arc<ActorMetrics>,
+    config: P::Config,
+) {
+    // lean is initialized on this thread, and only ever called from this thread.
+    rb_protocol::initialize();
+
+    let mut actor = Actor {
+        config,
+        running: None,
+        address: String::new(),
+        events,
+        metrics,
+    };
+
+    while let Some(command) = commands.blocking_recv() {
+        if let Command::Shutdown = command {
+            break;
+        }
+
+        let started = Instant::now();
+        actor.handle(command);
+        let elapsed = started.elapsed().as_micros() as u64;
+
+        actor
+            .metrics
+            .max_step_micros
+            .fetch_max(elapsed, Ordering::Relaxed);
+        actor
+            .metrics
+            .commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // any protocol will do, since the actor never runs one here.
+    type P = rb_protocol::ReliableBroadcast;
+
+    fn handle(capacity: usize) -> (ProtocolHandle<P>, mpsc::Receiver<Command<P>>) {
+        let (commands, command_rx) = mpsc::channel(capacity);
+        let handle = ProtocolHandle {
+            commands,
+            backlog: VecDeque::new(),
+            backlog_capacity: capacity,
+            metrics: Arc::new(ActorMetrics::default()),
+        };
+        (handle, command_rx)
+    }
+
+    fn broadcast(message: usize) -> Command<P> {
+        Command::Broadcast {
+            message: message.to_string(),
+        }
+    }
+
+    fn message(command: Command<P>) -> String {
+        match command {
+            Command::Broadcast { message } => message,
+            _ => panic!("expected a broadcast"),
+        }
+    }
+
+    #[tokio::test]
+    async fn submit_parks_commands_in_order_once_the_channel_is_full() {
+        let (mut handle, mut command_rx) = handle(2);
+        for i in 0..4 {
+            handle.submit(broadcast(i));
+        }
+
+        // two in the channel, two in the backlog, which is as much as it takes.
+        let metrics = handle.metrics();
+        assert_eq!(metrics.commands_submitted, 4);
+        assert_eq!(metrics.channel_depth, 2);
+        assert_eq!(metrics.backlog_depth, 2);
+        // only the first command that didn't fit ran into the full channel,
+        // the one after it went straight to the backlog.
+        assert_eq!(metrics.command_backpressure, 1);
+        assert!(handle.has_backlog());
+        assert!(handle.is_saturated());
+
+        // once the actor takes a command, `flush` moves the next one over.
+        assert_eq!(message(command_rx.recv().await.unwrap()), "0");
+        handle.flush().await;
+        assert_eq!(handle.metrics().backlog_depth, 1);
+        assert!(!handle.is_saturated());
+
+        // even with room in the channel, new commands queue up behind the backlog.
+        assert_eq!(message(command_rx.recv().await.unwrap()), "1");
+        handle.submit(broadcast(4));
+        assert_eq!(handle.metrics().backlog_depth, 2);
+        while handle.has_backlog() {
+            handle.flush().await;
+            let _ = command_rx.recv().await.unwrap();
+        }
+        assert_eq!(handle.metrics().channel_depth, 1);
+        assert_eq!(message(command_rx.recv().await.unwrap()), "4");
+    }
+
+    #[test]
+    fn emit_blocks_once_the_event_channel_is_full() {
+        let (events, mut event_rx) = mpsc::channel(1);
+        let metrics = Arc::new(ActorMetrics::default());
+        let actor: Actor<P> = Actor {
+            config: None,
+            running: None,
+            address: String::new(),
+            events,
+            metrics: metrics.clone(),
+        };
+
+        // the first event fills the channel, so the second one has to wait for the network task.
+        actor.emit(Event::Initialized);
+        let actor = thread::spawn(move || {
+            actor.emit(Event::Outbound {
+                packets: Vec::new(),
+            });
+            actor
+        });
+        while metrics.event_backpressure.load(Ordering::Relaxed) == 0 {
+            thread::yield_now();
+        }
+        assert!(!actor.is_finished());
+
+        assert!(matches!(event_rx.blocking_recv(), Some(Event::Initialized)));
+        assert!(matches!(
+            event_rx.blocking_recv(),
+            Some(Event::Outbound { .. })
+        ));
+        let actor = actor.join().unwrap();
+        assert_eq!(metrics.event_backpressure.load(Ordering::Relaxed), 1);
+
+        // with nobody listening anymore, events are dropped instead.
+        drop(event_rx);
+        actor.emit(Event::Initialized);
+    }
+}
@@ -0,0 +1,99 @@
+use libp2p::PeerId;
+use std::collections::HashMap;
+
+// coalesces outbound packets to the same peer, so that they go out as one `Request`
+// (one substream, one ack) instead of one request per packet.
+//
+// a batch is flushed once it reaches `max_batch_size` packets, or when the driver's flush interval
+// comes around, whichever happens first.
+pub struct Outbox<P> {
+    max_batch_size: usize,
+    pending: HashMap<PeerId, Vec<P>>,
+}
+
+impl<P> Outbox<P> {
+    pub fn new(max_batch_size: usize) -> Self {
+        Outbox {
+            max_batch_size: max_batch_size.max(1),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Queues a packet for `peer_id`, and returns the peer's batch if this filled it up.
+    pub fn push(&mut self, peer_id: PeerId, packet: P) -> Option<Vec<P>> {
+        let batch = self.pending.entry(peer_id).or_default();
+        batch.push(packet);
+        if batch.len() >= self.max_batch_size {
+            self.pending.remove(&peer_id)
+        } else {
+            None
+        }
+    }
+
+    /// Takes every non-empty batch.
+    pub fn drain(&mut self) -> Vec<(PeerId, Vec<P>)> {
+        self.pending.drain().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_full_batch_goes_out_right_away() {
+        let peer = PeerId::random();
+        let mut outbox = Outbox::new(3);
+        assert_eq!(outbox.push(peer, 1), None);
+        assert_eq!(outbox.push(peer, 2), None);
+        assert_eq!(outbox.push(peer, 3), Some(vec![1, 2, 3]));
+        assert!(outbox.is_empty());
+
+        // the next batch starts from scratch
+        assert_eq!(outbox.push(peer, 4), None);
+        assert!(!outbox.is_empty());
+    }
+
+    #[test]
+    fn drain_takes_every_partial_batch() {
+        // (the driver drains on every batch interval.)
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut outbox = Outbox::new(64);
+        outbox.push(a, 1);
+        outbox.push(b, 2);
+        outbox.push(a, 3);
+
+        let mut batches = outbox.drain();
+        batches.sort_by_key(|(_, packets)| packets.len());
+        assert_eq!(batches, vec![(b, vec![2]), (a, vec![1, 3])]);
+        assert!(outbox.is_empty());
+        assert!(outbox.drain().is_empty());
+    }
+
+    #[test]
+    fn peers_are_batched_separately() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut outbox = Outbox::new(2);
+        assert_eq!(outbox.push(a, "a1"), None);
+        assert_eq!(outbox.push(b, "b1"), None);
+        // filling `a`'s batch doesn't take `b`'s packets with it
+        assert_eq!(outbox.push(a, "a2"), Some(vec!["a1", "a2"]));
+        assert_eq!(outbox.drain(), vec![(b, vec!["b1"])]);
+    }
+
+    #[test]
+    fn a_batch_size_of_one_never_holds_anything() {
+        let peer = PeerId::random();
+        // a size of 0 would never fill up, so it's taken as 1
+        for size in [1, 0] {
+            let mut outbox = Outbox::new(size);
+            assert_eq!(outbox.push(peer, 1), Some(vec![1]));
+            assert_eq!(outbox.push(peer, 2), Some(vec![2]));
+            assert!(outbox.is_empty());
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use libp2p::PeerId;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// fault injection for `libp2p_rb`'s outbound requests, so that a cluster on one machine
+// sees the kind of network a real deployment would.
+//
+// a chaos profile (`rb --chaos profile.json`) says how likely it is for a request to each peer
+// to be dropped, duplicated, delayed or reordered, e.g.
+//   {
+//     "seed": 42,
+//     "default": { "drop": 0.05, "delay_ms": [0, 50] },
+//     "peers": { "<peer id>": { "drop": 0.3, "duplicate": 0.1, "reorder": 0.2, "reorder_window_ms": 200 } }
+//   }
+//
+// it's generic over the request type `R`, so that it works for whatever protocol the driver runs.
+//
+// separately, the node can be cut off from some peers for a while (see `Chaos::partition`).
+// a partition is a link going down, not packet loss: requests to the other side are held until
+// it heals, and requests from the other side go unanswered (so their senders retry them later).
+// dropped requests, on the other hand, are gone for good.
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    // chance that a request is never sent
+    pub drop: f64,
+    // chance that a request is sent twice
+    pub duplicate: f64,
+    // every request is delayed by a uniformly random [min, max] milliseconds
+    pub delay_ms: (u64, u64),
+    // chance that a request is held back by up to `reorder_window_ms` more,
+    // so that requests sent after it overtake it
+    pub reorder: f64,
+    pub reorder_window_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ChaosProfile {
+    // for reproducible runs. without it, every run is different.
+    pub seed: Option<u64>,
+    pub default: Faults,
+    // peer id -> faults on the link to that peer, instead of the default ones
+    pub peers: HashMap<String, Faults>,
+}
+
+impl ChaosProfile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("can't read chaos profile {}: {e}", path.display()))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("invalid chaos profile {}: {e}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct ChaosStats {
+    pub dropped: usize,
+    pub duplicated: usize,
+    pub delayed: usize,
+    pub reordered: usize,
+    // requests held back because of a partition
+    pub partitioned: usize,
+}
+
+// how many requests a partition holds for each peer. past that, they're dropped, so that
+// a long partition doesn't hold on to everything we send across it.
+const MAX_HELD_PER_PEER: usize = 1024;
+
+struct Partition<R> {
+    peers: HashSet<PeerId>,
+    // heals by itself at this point, if set
+    until: Option<Instant>,
+    held: Vec<(PeerId, R)>,
+    held_per_peer: HashMap<PeerId, usize>,
+}
+
+impl<R> Partition<R> {
+    // holds a request until the partition heals. returns false if there's no room left for it.
+    fn hold(&mut self, peer_id: PeerId, request: R) -> bool {
+        let held = self.held_per_peer.entry(peer_id).or_default();
+        if *held >= MAX_HELD_PER_PEER {
+            return false;
+        }
+        *held += 1;
+        self.held.push((peer_id, request));
+        true
+    }
+}
+
+pub struct Chaos<R> {
+    default: Faults,
+    peers: HashMap<PeerId, Faults>,
+    rng: StdRng,
+    delayed: Vec<(Instant, PeerId, R)>,
+    partition: Option<Partition<R>>,
+    stats: ChaosStats,
+}
+
+impl<R: Clone> Default for Chaos<R> {
+    // no faults, and no partition until someone asks for one.
+    fn default() -> Self {
+        Chaos::new(ChaosProfile::default()).expect("the default profile is valid")
+    }
+}
+
+impl<R: Clone> Chaos<R> {
+    pub fn new(profile: ChaosProfile) -> Result<Self, String> {
+        let mut peers = HashMap::new();
+        for (peer, faults) in profile.peers {
+            let peer_id = PeerId::from_str(&peer)
+                .map_err(|e| format!("invalid peer id {peer} in chaos profile: {e}"))?;
+            peers.insert(peer_id, faults);
+        }
+        let rng = match profile.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Ok(Chaos {
+            default: profile.default,
+            peers,
+            rng,
+            delayed: Vec::new(),
+            partition: None,
+            stats: ChaosStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> ChaosStats {
+        self.stats
+    }
+
+    pub fn is_partitioned(&self, peer_id: &PeerId) -> bool {
+        self.partition
+            .as_ref()
+            .is_some_and(|partition| partition.peers.contains(peer_id))
+    }
+
+    /// Whether there's anything for `due` or `expire` to do later.
+    pub fn is_busy(&self) -> bool {
+        !self.delayed.is_empty()
+            || self
+                .partition
+                .as_ref()
+                .is_some_and(|partition| partition.until.is_some())
+    }
+
+    /// Runs a request to `peer_id` through the faults for its link.
+    /// Returns the copies to send right away. Delayed copies come out of `due` later.
+    pub fn outbound(&mut self, peer_id: PeerId, request: R, now: Instant) -> Vec<R> {
+        if let Some(partition) = self.partition.as_mut() {
+            if partition.peers.contains(&peer_id) {
+                if partition.hold(peer_id, request) {
+                    self.stats.partitioned += 1;
+                } else {
+                    self.stats.dropped += 1;
+                }
+                return Vec::new();
+            }
+        }
+
+        let faults = self.peers.get(&peer_id).unwrap_or(&self.default).clone();
+        if self.rng.gen_bool(faults.drop.clamp(0.0, 1.0)) {
+            self.stats.dropped += 1;
+            return Vec::new();
+        }
+        let copies = if self.rng.gen_bool(faults.duplicate.clamp(0.0, 1.0)) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        let mut now_requests = Vec::new();
+        for _ in 0..copies {
+            let (min, max) = faults.delay_ms;
+            let mut delay = self.rng.gen_range(min.min(max)..=max.max(min));
+            if faults.reorder_window_ms > 0 && self.rng.gen_bool(faults.reorder.clamp(0.0, 1.0)) {
+                self.stats.reordered += 1;
+                delay += self.rng.gen_range(1..=faults.reorder_window_ms);
+            }
+
+            if delay == 0 {
+                now_requests.push(request.clone());
+            } else {
+                self.stats.delayed += 1;
+                let deliver_at = now + Duration::from_millis(delay);
+                self.delayed.push((deliver_at, peer_id, request.clone()));
+            }
+        }
+        now_requests
+    }
+
+    /// Delayed requests that are due by `now`, in the order they became due.
+    pub fn due(&mut self, now: Instant) -> Vec<(PeerId, R)> {
+        let (mut due, later): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(deliver_at, _, _)| *deliver_at <= now);
+        self.delayed = later;
+        due.sort_by_key(|(deliver_at, _, _)| *deliver_at);
+
+        let mut ready = Vec::new();
+        for (_, peer_id, request) in due {
+            // the link went down while the request was on its way
+            match self.partition.as_mut() {
+                Some(partition) if partition.peers.contains(&peer_id) => {
+                    if partition.hold(peer_id, request) {
+                        self.stats.partitioned += 1;
+                    } else {
+                        self.stats.dropped += 1;
+                    }
+                }
+                _ => ready.push((peer_id, request)),
+            }
+        }
+        ready
+    }
+
+    /// Cuts this node off from `peers`, for `duration` if given, or until `heal` otherwise.
+    /// Replaces any partition that's already in place, and returns what that one was holding.
+    pub fn partition(
+        &mut self,
+        peers: impl IntoIterator<Item = PeerId>,
+        duration: Option<Duration>,
+        now: Instant,
+    ) -> Vec<(PeerId, R)> {
+        let released = self.heal();
+        self.partition = Some(Partition {
+            peers: peers.into_iter().collect(),
+            until: duration.map(|duration| now + duration),
+            held: Vec::new(),
+            held_per_peer: HashMap::new(),
+        });
+        released
+    }
+
+    /// Ends the partition. Returns the requests it held, to be sent again.
+    pub fn heal(&mut self) -> Vec<(PeerId, R)> {
+        self.partition
+            .take()
+            .map(|partition| partition.held)
+            .unwrap_or_default()
+    }
+
+    /// Heals the partition if its time is up.
+    pub fn expire(&mut self, now: Instant) -> Vec<(PeerId, R)> {
+        let expired = self
+            .partition
+            .as_ref()
+            .and_then(|partition| partition.until)
+            .is_some_and(|until| until <= now);
+        if expired {
+            self.heal()
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn partitioned_peers(&self) -> Vec<String> {
+        self.partition
+            .as_ref()
+            .map(|partition| partition.peers.iter().map(PeerId::to_string).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networktest::rb_protocol::RBRequest;
+
+    fn request() -> RBRequest {
+        RBRequest {
+            packets: Vec::new(),
+        }
+    }
+
+    fn chaos(faults: Faults) -> Chaos<RBRequest> {
+        Chaos::new(ChaosProfile {
+            seed: Some(0),
+            default: faults,
+            peers: HashMap::new(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn no_faults_sends_everything_right_away() {
+        let mut chaos = Chaos::default();
+        let now = Instant::now();
+        assert_eq!(chaos.outbound(PeerId::random(), request(), now).len(), 1);
+        assert!(!chaos.is_busy());
+    }
+
+    #[test]
+    fn certain_faults_always_happen() {
+        let now = Instant::now();
+        let mut dropping = chaos(Faults {
+            drop: 1.0,
+            ..Faults::default()
+        });
+        assert!(dropping
+            .outbound(PeerId::random(), request(), now)
+            .is_empty());
+        assert_eq!(dropping.stats().dropped, 1);
+
+        let mut duplicating = chaos(Faults {
+            duplicate: 1.0,
+            ..Faults::default()
+        });
+        assert_eq!(
+            duplicating.outbound(PeerId::random(), request(), now).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn delayed_requests_come_out_once_due() {
+        let now = Instant::now();
+        let peer_id = PeerId::random();
+        let mut chaos = chaos(Faults {
+            delay_ms: (10, 10),
+            ..Faults::default()
+        });
+        assert!(chaos.outbound(peer_id, request(), now).is_empty());
+        assert!(chaos.due(now + Duration::from_millis(9)).is_empty());
+        assert_eq!(chaos.due(now + Duration::from_millis(10)).len(), 1);
+        assert!(!chaos.is_busy());
+    }
+
+    #[test]
+    fn partitions_hold_requests_until_they_heal() {
+        let now = Instant::now();
+        let (cut_off, reachable) = (PeerId::random(), PeerId::random());
+        let mut chaos = Chaos::default();
+        chaos.partition([cut_off], Some(Duration::from_secs(1)), now);
+
+        assert!(chaos.outbound(cut_off, request(), now).is_empty());
+        assert_eq!(chaos.outbound(reachable, request(), now).len(), 1);
+        assert!(chaos.expire(now + Duration::from_millis(999)).is_empty());
+
+        let released = chaos.expire(now + Duration::from_secs(1));
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].0, cut_off);
+        assert!(!chaos.is_partitioned(&cut_off));
+    }
+
+    #[test]
+    fn partitions_hold_a_bounded_number_of_requests_per_peer() {
+        let now = Instant::now();
+        let (flooded, other) = (PeerId::random(), PeerId::random());
+        let mut chaos = Chaos::default();
+        chaos.partition([flooded, other], None, now);
+
+        for _ in 0..MAX_HELD_PER_PEER + 10 {
+            assert!(chaos.outbound(flooded, request(), now).is_empty());
+        }
+        assert!(chaos.outbound(other, request(), now).is_empty());
+        assert_eq!(chaos.stats().partitioned, MAX_HELD_PER_PEER + 1);
+        assert_eq!(chaos.stats().dropped, 10);
+
+        let released = chaos.heal();
+        assert_eq!(released.len(), MAX_HELD_PER_PEER + 1);
+        assert_eq!(released.last().unwrap().0, other);
+    }
+}
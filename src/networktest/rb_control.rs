@@ -0,0 +1,509 @@
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+
+// local control API for `libp2p_rb`.
+//
+// the API speaks (line-delimited) JSON-RPC 2.0 over a unix socket: each line the client writes
+// is one request object, and each line the server writes back is the matching response.
+// e.g.
+//   -> {"jsonrpc": "2.0", "id": 1, "method": "broadcast", "params": {"value": "hello"}}
+//   <- {"jsonrpc": "2.0", "id": 1, "result": null}
+//
+// the server itself holds no protocol state. it translates requests into `ControlRequest`s
+// and hands them to the driver's event loop, which replies through a oneshot channel.
+
+pub enum ControlRequest {
+    // without `members`, the members are whoever we're connected to at the time.
+    Init {
+        leader: String,
+        members: Option<Vec<String>>,
+    },
+    Broadcast {
+        value: String,
+    },
+    Status,
+    Peers,
+    Deliveries {
+        since: usize,
+    },
+    Disconnect {
+        peer: String,
+    },
+    // without `duration_ms`, the partition lasts until `Heal`.
+    Partition {
+        peers: Vec<String>,
+        duration_ms: Option<u64>,
+    },
+    Heal,
+    Shutdown,
+}
+
+pub type ControlResult = Result<Value, String>;
+
+pub struct ControlMessage {
+    pub request: ControlRequest,
+    pub reply: oneshot::Sender<ControlResult>,
+}
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(serde::Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(serde::Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(serde::Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcResponse {
+    fn ok(id: Value, result: Value) -> Self {
+        RpcResponse {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn error(id: Value, code: i64, message: String) -> Self {
+        RpcResponse {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(RpcError { code, message }),
+        }
+    }
+}
+
+// params may be given either by name ({"value": "hi"}) or by position (["hi"]).
+fn param<'a>(params: &'a Value, name: &str, position: usize) -> Option<&'a Value> {
+    params.get(name).or_else(|| params.get(position))
+}
+
+fn string_param(params: &Value, name: &str, position: usize) -> Result<String, (i64, String)> {
+    param(params, name, position)
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or((INVALID_PARAMS, format!("missing string parameter '{name}'")))
+}
+
+impl ControlRequest {
+    fn from_rpc(method: &str, params: &Value) -> Result<Self, (i64, String)> {
+        match method {
+            "init" => {
+                let members = match param(params, "members", 1) {
+                    None => None,
+                    Some(members) => {
+                        Some(serde_json::from_value(members.clone()).map_err(|_| {
+                            (
+                                INVALID_PARAMS,
+                                String::from("'members' should be a list of peer ids"),
+                            )
+                        })?)
+                    }
+                };
+                Ok(ControlRequest::Init {
+                    leader: string_param(params, "leader", 0)?,
+                    members,
+                })
+            }
+            "broadcast" => Ok(ControlRequest::Broadcast {
+                value: string_param(params, "value", 0)?,
+            }),
+            "status" => Ok(ControlRequest::Status),
+            "peers" => Ok(ControlRequest::Peers),
+            "deliveries" => {
+                let since = match param(params, "since", 0) {
+                    None => 0,
+                    Some(since) => since.as_u64().ok_or((
+                        INVALID_PARAMS,
+                        String::from("'since' should be a non-negative integer"),
+                    ))? as usize,
+                };
+                Ok(ControlRequest::Deliveries { since })
+            }
+            "disconnect" => Ok(ControlRequest::Disconnect {
+                peer: string_param(params, "peer", 0)?,
+            }),
+            "partition" => {
+                let peers = param(params, "peers", 0)
+                    .and_then(|peers| serde_json::from_value(peers.clone()).ok())
+                    .ok_or((
+                        INVALID_PARAMS,
+                        String::from("'peers' should be a list of peer ids"),
+                    ))?;
+                let duration_ms = match param(params, "duration_ms", 1) {
+                    None | Some(Value::Null) => None,
+                    Some(duration) => Some(duration.as_u64().ok_or((
+                        INVALID_PARAMS,
+                        String::from("'duration_ms' should be a non-negative integer"),
+                    ))?),
+                };
+                Ok(ControlRequest::Partition { peers, duration_ms })
+            }
+            "heal" => Ok(ControlRequest::Heal),
+            "shutdown" => Ok(ControlRequest::Shutdown),
+            m => Err((METHOD_NOT_FOUND, format!("unknown method: {m}"))),
+        }
+    }
+}
+
+// the driver stops as soon as it handles `shutdown`, which can take the response down with it.
+// so `shutdown` is answered right away, and only handed over once the response is out.
+// (the driver can't refuse it anyway.)
+enum Dispatched {
+    Answered(RpcResponse),
+    Deferred(RpcResponse, ControlRequest),
+}
+
+async fn dispatch(line: &str, requests: &mpsc::Sender<ControlMessage>) -> Dispatched {
+    let rpc: RpcRequest = match serde_json::from_str(line) {
+        Ok(rpc) => rpc,
+        Err(e) => {
+            let response = RpcResponse::error(Value::Null, PARSE_ERROR, e.to_string());
+            return Dispatched::Answered(response);
+        }
+    };
+
+    let request = match ControlRequest::from_rpc(&rpc.method, &rpc.params) {
+        Ok(ControlRequest::Shutdown) => {
+            let response = RpcResponse::ok(rpc.id, Value::Null);
+            return Dispatched::Deferred(response, ControlRequest::Shutdown);
+        }
+        Ok(request) => request,
+        Err((code, message)) => {
+            return Dispatched::Answered(RpcResponse::error(rpc.id, code, message))
+        }
+    };
+    Dispatched::Answered(forward(rpc.id, request, requests).await)
+}
+
+async fn forward(
+    id: Value,
+    request: ControlRequest,
+    requests: &mpsc::Sender<ControlMessage>,
+) -> RpcResponse {
+    let shutting_down = || String::from("node is shutting down");
+    let (reply, result) = oneshot::channel();
+    if requests
+        .send(ControlMessage { request, reply })
+        .await
+        .is_err()
+    {
+        return RpcResponse::error(id, SERVER_ERROR, shutting_down());
+    }
+
+    match result.await {
+        Ok(Ok(value)) => RpcResponse::ok(id, value),
+        Ok(Err(message)) => RpcResponse::error(id, SERVER_ERROR, message),
+        Err(_) => RpcResponse::error(id, SERVER_ERROR, shutting_down()),
+    }
+}
+
+async fn handle_connection(stream: UnixStream, requests: mpsc::Sender<ControlMessage>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, deferred) = match dispatch(&line, &requests).await {
+            Dispatched::Answered(response) => (response, None),
+            Dispatched::Deferred(response, request) => (response, Some(request)),
+        };
+        let mut encoded = serde_json::to_string(&response).expect("responses should serialize");
+        encoded.push('\n');
+        let written = writer.write_all(encoded.as_bytes()).await;
+        if let Some(request) = deferred {
+            let (reply, _) = oneshot::channel();
+            let _ = requests.send(ControlMessage { request, reply }).await;
+        }
+        if written.is_err() {
+            break;
+        }
+    }
+}
+
+/// Serves the control API on a unix socket at `path`, forwarding requests to `requests`.
+/// Any stale socket left behind at `path` by a previous run is removed first.
+pub async fn serve(path: PathBuf, requests: mpsc::Sender<ControlMessage>) -> std::io::Result<()> {
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    println!("control api listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_connection(stream, requests.clone()));
+    }
+}
+
+/// Makes a single JSON-RPC call against the control socket at `path`.
+pub async fn call(path: &Path, method: &str, params: Value) -> Result<Value, Box<dyn Error>> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    writer.write_all(format!("{request}\n").as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    let line = lines
+        .next_line()
+        .await?
+        .ok_or("control socket closed without a response")?;
+
+    let mut response: Value = serde_json::from_str(&line)?;
+    match response.get("error") {
+        Some(error) => Err(format!("rpc error: {error}").into()),
+        None => Ok(response["result"].take()),
+    }
+}
+
+// turns `rbctl`'s arguments after the method into its params.
+fn client_params(method: &str, rest: &[String]) -> Result<Value, Box<dyn Error>> {
+    Ok(match (method, rest) {
+        ("init", [leader]) => json!({ "leader": leader }),
+        ("init", [leader, members @ ..]) => json!({ "leader": leader, "members": members }),
+        ("broadcast", value) if !value.is_empty() => json!({ "value": value.join(" ") }),
+        ("deliveries", [since]) => json!({ "since": since.parse::<u64>()? }),
+        ("disconnect", [peer]) => json!({ "peer": peer }),
+        // a duration of 0 lasts until `heal`
+        ("partition", [duration_ms, peers @ ..]) => {
+            let duration_ms = duration_ms.parse::<u64>()?;
+            json!({ "peers": peers, "duration_ms": (duration_ms > 0).then_some(duration_ms) })
+        }
+        _ => json!({}),
+    })
+}
+
+// command line client for the control API:
+// rbctl <socket> <method> [argument]
+//
+// e.g.
+//   cargo run -- rbctl /tmp/rb0.sock init <peer id of leader node> [<peer id of member> ...]
+//   cargo run -- rbctl /tmp/rb0.sock broadcast hello world
+//   cargo run -- rbctl /tmp/rb0.sock deliveries 0
+//   cargo run -- rbctl /tmp/rb0.sock partition 5000 <peer id> [<peer id> ...]
+#[tokio::main]
+pub async fn client_main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let [socket, method, rest @ ..] = args.as_slice() else {
+        return Err(
+            "usage: rbctl <socket> <init|broadcast|status|peers|deliveries|disconnect|partition|heal|shutdown> [argument]"
+                .into(),
+        );
+    };
+
+    let params = client_params(method, rest)?;
+    let result = call(Path::new(socket), method, params).await?;
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static NEXT_SOCKET: AtomicU64 = AtomicU64::new(0);
+
+    fn socket_path() -> PathBuf {
+        let id = NEXT_SOCKET.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(format!("pb-rust-control-{}-{id}.sock", std::process::id()))
+    }
+
+    // what `dispatch` answers to `line`, with the driver answering every request with `reply`.
+    async fn answer(line: &str, reply: ControlResult) -> Value {
+        let (requests, mut driver) = mpsc::channel::<ControlMessage>(1);
+        tokio::spawn(async move {
+            if let Some(control) = driver.recv().await {
+                let _ = control.reply.send(reply);
+            }
+        });
+        let response = match dispatch(line, &requests).await {
+            Dispatched::Answered(response) | Dispatched::Deferred(response, _) => response,
+        };
+        serde_json::to_value(response).unwrap()
+    }
+
+    fn error_code(response: &Value) -> i64 {
+        response["error"]["code"]
+            .as_i64()
+            .expect("should be an error")
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_a_parse_error() {
+        for line in ["{", "not json", r#"{"id": 1}"#] {
+            let response = answer(line, Ok(Value::Null)).await;
+            assert_eq!(error_code(&response), PARSE_ERROR, "{line}");
+            assert_eq!(response["id"], Value::Null);
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_methods_are_reported() {
+        let response = answer(r#"{"id": 7, "method": "explode"}"#, Ok(Value::Null)).await;
+        assert_eq!(error_code(&response), METHOD_NOT_FOUND);
+        assert_eq!(response["id"], 7);
+        assert!(response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("explode"));
+    }
+
+    #[test]
+    fn bad_params_are_rejected() {
+        let bad = [
+            ("broadcast", json!({})),
+            ("broadcast", json!({ "value": 3 })),
+            ("init", json!({})),
+            ("init", json!({ "leader": "a", "members": "b" })),
+            ("deliveries", json!({ "since": -1 })),
+            ("deliveries", json!({ "since": "0" })),
+            ("disconnect", json!([])),
+            ("partition", json!({ "peers": "a" })),
+            (
+                "partition",
+                json!({ "peers": ["a"], "duration_ms": "soon" }),
+            ),
+        ];
+        for (method, params) in bad {
+            match ControlRequest::from_rpc(method, &params) {
+                Err((code, _)) => assert_eq!(code, INVALID_PARAMS, "{method} {params}"),
+                Ok(_) => panic!("{method} {params} should be rejected"),
+            }
+        }
+    }
+
+    #[test]
+    fn params_are_taken_by_name_or_position() {
+        let by_name = ControlRequest::from_rpc("broadcast", &json!({ "value": "hi" }));
+        let by_position = ControlRequest::from_rpc("broadcast", &json!(["hi"]));
+        for request in [by_name, by_position] {
+            assert!(matches!(request, Ok(ControlRequest::Broadcast { value }) if value == "hi"));
+        }
+        let partition = ControlRequest::from_rpc("partition", &json!([["a", "b"], null]));
+        assert!(matches!(
+            partition,
+            Ok(ControlRequest::Partition { peers, duration_ms: None }) if peers == ["a", "b"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn driver_errors_become_server_errors() {
+        let line = r#"{"id": "x", "method": "broadcast", "params": ["hi"]}"#;
+        let response = answer(line, Err(String::from("not yet initialized"))).await;
+        assert_eq!(error_code(&response), SERVER_ERROR);
+        assert_eq!(response["id"], "x");
+        assert_eq!(response["error"]["message"], "not yet initialized");
+
+        let response = answer(
+            r#"{"id": 2, "method": "status"}"#,
+            Ok(json!({ "up": true })),
+        )
+        .await;
+        assert_eq!(response["result"], json!({ "up": true }));
+        assert!(response.get("error").is_none());
+    }
+
+    #[test]
+    fn rbctl_arguments_become_params() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            client_params("broadcast", &args(&["hello", "world"])).unwrap(),
+            json!({ "value": "hello world" })
+        );
+        assert_eq!(
+            client_params("init", &args(&["l", "a", "b"])).unwrap(),
+            json!({ "leader": "l", "members": ["a", "b"] })
+        );
+        assert_eq!(
+            client_params("partition", &args(&["0", "a"])).unwrap(),
+            json!({ "peers": ["a"], "duration_ms": null })
+        );
+        assert!(client_params("deliveries", &args(&["soon"])).is_err());
+    }
+
+    // a client talking to `serve` over a real socket, with a stand-in for the driver
+    // that exits as soon as it handles `shutdown`, like the real one.
+    #[tokio::test]
+    async fn rbctl_round_trip() {
+        let path = socket_path();
+        let (requests, mut driver) = mpsc::channel::<ControlMessage>(8);
+        tokio::spawn(serve(path.clone(), requests));
+        let driver = tokio::spawn(async move {
+            let mut broadcasts = Vec::new();
+            while let Some(control) = driver.recv().await {
+                let result = match control.request {
+                    ControlRequest::Broadcast { value } => {
+                        broadcasts.push(value);
+                        Ok(Value::Null)
+                    }
+                    ControlRequest::Status => Ok(json!({ "broadcasts": broadcasts })),
+                    ControlRequest::Shutdown => break,
+                    _ => Err(String::from("unsupported")),
+                };
+                let _ = control.reply.send(result);
+            }
+            broadcasts
+        });
+
+        // the socket shows up once `serve` is running.
+        let call_eventually = |method: &'static str, params: Value| {
+            let path = path.clone();
+            async move {
+                for _ in 0..100 {
+                    match call(&path, method, params.clone()).await {
+                        Err(e) if e.to_string().contains("rpc error") => return Err(e.to_string()),
+                        Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                        Ok(result) => return Ok(result),
+                    }
+                }
+                panic!("the control socket never came up");
+            }
+        };
+
+        let params = client_params("broadcast", &[String::from("hello")]).unwrap();
+        assert_eq!(call_eventually("broadcast", params).await, Ok(Value::Null));
+        assert_eq!(
+            call_eventually("status", json!({})).await,
+            Ok(json!({ "broadcasts": ["hello"] }))
+        );
+        assert!(call_eventually("heal", json!({}))
+            .await
+            .unwrap_err()
+            .contains("unsupported"));
+        assert_eq!(
+            call_eventually("shutdown", json!({})).await,
+            Ok(Value::Null)
+        );
+        assert_eq!(driver.await.unwrap(), ["hello"]);
+        let _ = std::fs::remove_file(path);
+    }
+}
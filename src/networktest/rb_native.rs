@@ -0,0 +1,229 @@
+use crate::networktest::rb_protocol::{Message, Packet};
+use std::collections::{HashMap, HashSet};
+
+// a native rust port of the reliable broadcast transition system in `ReliableBroadcast.lean`.
+//
+// it follows the lean code definition by definition (same messages, same thresholds,
+// same `procMsg`), down to the order of the packets it returns, so that the two can be run
+// side by side and compared packet for packet (see `differential.rs`).
+// any difference between them is either a bug in this port, or in the FFI glue in `rb_protocol`.
+//
+// it also lets the crate run without a lean toolchain: built without the `lean` feature,
+// `rb_protocol::Protocol` is this one.
+
+#[derive(Debug, Clone)]
+struct NodeState {
+    id: String,
+    all_nodes: Vec<String>,
+    sent: HashSet<usize>,
+    // (originator, round) -> value
+    echoed: HashMap<(String, usize), String>,
+    voted: HashMap<(String, usize), String>,
+    // newest sender first, like the lean list
+    msg_received_from: HashMap<Message, Vec<String>>,
+    // (originator, round) -> values, newest first
+    output: HashMap<(String, usize), Vec<String>>,
+}
+
+impl NodeState {
+    // initLocalState
+    fn new(id: String, all_nodes: Vec<String>) -> Self {
+        NodeState {
+            id,
+            all_nodes,
+            sent: HashSet::new(),
+            echoed: HashMap::new(),
+            voted: HashMap::new(),
+            msg_received_from: HashMap::new(),
+            output: HashMap::new(),
+        }
+    }
+
+    // Packet.broadcast
+    fn broadcast(&self, msg: Message) -> Vec<Packet> {
+        self.all_nodes
+            .iter()
+            .map(|dst| Packet {
+                src: self.id.clone(),
+                dst: dst.clone(),
+                msg: msg.clone(),
+                consumed: false,
+            })
+            .collect()
+    }
+
+    fn received_from(&self, msg: &Message) -> usize {
+        self.msg_received_from.get(msg).map_or(0, Vec::len)
+    }
+
+    // the thresholds are natural numbers in lean, so subtraction saturates at 0.
+    fn num_nodes(&self) -> usize {
+        self.all_nodes.len()
+    }
+
+    fn byz_thres(&self) -> usize {
+        self.num_nodes().saturating_sub(1) / 3
+    }
+
+    fn thres_echo_4_vote(&self) -> usize {
+        self.num_nodes().saturating_sub(self.byz_thres())
+    }
+
+    fn thres_vote_4_vote(&self) -> usize {
+        self.num_nodes()
+            .saturating_sub(self.byz_thres() + self.byz_thres())
+    }
+
+    fn thres_vote_4_output(&self) -> usize {
+        self.num_nodes().saturating_sub(self.byz_thres())
+    }
+
+    // procInt
+    fn proc_int(&mut self, r: usize, input_value: String) -> Vec<Packet> {
+        if self.sent.contains(&r) {
+            return Vec::new();
+        }
+        self.sent.insert(r);
+        self.broadcast(Message::InitialMsg { r, v: input_value })
+    }
+
+    // handleMessage. `None` if there's nothing to do.
+    fn handle_message(&mut self, src: &str, msg: &Message) -> Option<Vec<Packet>> {
+        match msg {
+            Message::InitialMsg { r, v } => {
+                let key = (src.to_string(), *r);
+                if self.echoed.contains_key(&key) {
+                    return None;
+                }
+                self.echoed.insert(key, v.clone());
+                Some(self.broadcast(Message::EchoMsg {
+                    originator: src.to_string(),
+                    r: *r,
+                    v: v.clone(),
+                }))
+            }
+            _ => {
+                let already_received = self.msg_received_from.entry(msg.clone()).or_default();
+                if already_received.iter().any(|s| s == src) {
+                    return None;
+                }
+                already_received.insert(0, src.to_string());
+                Some(Vec::new())
+            }
+        }
+    }
+
+    // checkVoteCondition
+    fn check_vote_condition(&self, msg: &Message) -> bool {
+        match msg {
+            Message::EchoMsg { originator, r, .. } => {
+                !self.voted.contains_key(&(originator.clone(), *r))
+                    && self.thres_echo_4_vote() <= self.received_from(msg)
+            }
+            Message::VoteMsg { originator, r, .. } => {
+                !self.voted.contains_key(&(originator.clone(), *r))
+                    && self.thres_vote_4_vote() <= self.received_from(msg)
+            }
+            Message::InitialMsg { .. } => false,
+        }
+    }
+
+    // updateVotedByMessage
+    fn update_voted_by_message(&mut self, msg: &Message) -> Vec<Packet> {
+        match msg {
+            Message::EchoMsg { originator, r, v } | Message::VoteMsg { originator, r, v } => {
+                self.voted.insert((originator.clone(), *r), v.clone());
+                self.broadcast(Message::VoteMsg {
+                    originator: originator.clone(),
+                    r: *r,
+                    v: v.clone(),
+                })
+            }
+            Message::InitialMsg { .. } => Vec::new(),
+        }
+    }
+
+    // tryUpdateOutputByMessage
+    fn try_update_output_by_message(&mut self, msg: &Message) {
+        if let Message::VoteMsg { originator, r, v } = msg {
+            if self.thres_vote_4_output() <= self.received_from(msg) {
+                // `List.insert` only adds values that aren't there yet, at the front.
+                let output = self.output.entry((originator.clone(), *r)).or_default();
+                if !output.contains(v) {
+                    output.insert(0, v.clone());
+                }
+            }
+        }
+    }
+
+    // routineCheck
+    fn routine_check(&mut self, msg: &Message) -> Vec<Packet> {
+        if self.check_vote_condition(msg) {
+            let packets = self.update_voted_by_message(msg);
+            self.try_update_output_by_message(msg);
+            packets
+        } else {
+            self.try_update_output_by_message(msg);
+            Vec::new()
+        }
+    }
+
+    // procMsg
+    fn proc_msg(&mut self, src: &str, msg: &Message) -> Vec<Packet> {
+        match self.handle_message(src, msg) {
+            Some(packets) => match msg {
+                Message::InitialMsg { .. } => packets,
+                _ => {
+                    let mut packets = packets;
+                    packets.extend(self.routine_check(msg));
+                    packets
+                }
+            },
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Same interface as `rb_protocol::lean::Protocol`, so either can be plugged into the driver.
+#[derive(Debug, Clone)]
+pub struct Protocol {
+    state: NodeState,
+    pub round: usize,
+    pub leader: String,
+}
+
+/// Nothing to initialize, but kept for parity with `rb_protocol::lean::initialize`.
+// (only used when this port stands in for the lean protocol.)
+#[cfg_attr(feature = "lean", allow(dead_code))]
+pub fn initialize() {}
+
+impl Protocol {
+    pub fn create(node_list: Vec<String>, address: String, leader: String) -> Self {
+        Protocol {
+            state: NodeState::new(address, node_list),
+            round: 0,
+            leader,
+        }
+    }
+
+    /// Broadcasts `message` in the next round.
+    /// (the lean version looks the value up by address, through `get_node_value`;
+    /// here, we just use it directly.)
+    pub fn send_message(&mut self, _address: String, message: String) -> Vec<Packet> {
+        let packets = self.state.proc_int(self.round, message);
+        self.round += 1;
+        packets
+    }
+
+    pub fn handle_packet(&mut self, packet: Packet) -> Vec<Packet> {
+        self.state.proc_msg(&packet.src, &packet.msg)
+    }
+
+    pub fn check_output(&mut self, round: usize) -> Option<String> {
+        self.state
+            .output
+            .get(&(self.leader.clone(), round))
+            .and_then(|values| values.first())
+            .cloned()
+    }
+}
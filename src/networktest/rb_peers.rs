@@ -0,0 +1,330 @@
+use libp2p::request_response::RequestId;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// keeps track of the protocol's members, independently of whether we can currently reach them.
+//
+// the protocol's member set is fixed at initialization, so losing a peer (its mDNS record expires,
+// or its connection drops) must not remove it from the protocol.
+// instead, we hold on to whatever we wanted to send it, keep redialing it with exponential backoff,
+// and flush the held packets once the connection comes back.
+//
+// a member that's still connected can refuse our packets too (it hasn't been initialized yet).
+// those are held the same way, and resent over the same connection once their backoff is up.
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+struct Backoff {
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+impl Backoff {
+    fn new(now: Instant) -> Self {
+        Backoff {
+            attempts: 0,
+            next_attempt: now,
+        }
+    }
+
+    // doubles the delay after every failed attempt, up to `MAX_BACKOFF`.
+    fn bump(&mut self, now: Instant) {
+        let delay = INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(self.attempts))
+            .min(MAX_BACKOFF);
+        self.attempts += 1;
+        self.next_attempt = now + delay;
+    }
+}
+
+// `R` is the request type of whatever protocol the driver runs.
+// `I` is only there so the tests can make up their own request ids.
+pub struct PeerTracker<R, I = RequestId> {
+    members: HashSet<PeerId>,
+    connected: HashSet<PeerId>,
+    // last known addresses, since mDNS forgets them once a peer expires.
+    addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    held: HashMap<PeerId, VecDeque<R>>,
+    redial: HashMap<PeerId, Backoff>,
+    // requests that haven't been acked yet, in case we have to send them again.
+    in_flight: HashMap<I, (PeerId, R)>,
+}
+
+impl<R, I> Default for PeerTracker<R, I> {
+    fn default() -> Self {
+        PeerTracker {
+            members: HashSet::new(),
+            connected: HashSet::new(),
+            addresses: HashMap::new(),
+            held: HashMap::new(),
+            redial: HashMap::new(),
+            in_flight: HashMap::new(),
+        }
+    }
+}
+
+impl<R, I: Eq + Hash> PeerTracker<R, I> {
+    pub fn set_members(&mut self, members: impl IntoIterator<Item = PeerId>) {
+        self.members = members.into_iter().collect();
+    }
+
+    pub fn is_member(&self, peer_id: &PeerId) -> bool {
+        self.members.contains(peer_id)
+    }
+
+    pub fn discovered(&mut self, peer_id: PeerId, address: Multiaddr) {
+        let addresses = self.addresses.entry(peer_id).or_default();
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    /// Marks the peer as reachable, and returns any requests that were held for it.
+    pub fn connected(&mut self, peer_id: PeerId) -> VecDeque<R> {
+        self.connected.insert(peer_id);
+        self.redial.remove(&peer_id);
+        self.held.remove(&peer_id).unwrap_or_default()
+    }
+
+    /// The last connection to the peer has closed.
+    pub fn disconnected(&mut self, peer_id: PeerId) {
+        self.connected.remove(&peer_id);
+        self.schedule_redial(peer_id);
+    }
+
+    /// The peer's mDNS record has expired.
+    /// As long as we still have a connection to it, this changes nothing.
+    pub fn expired(&mut self, peer_id: PeerId) {
+        if !self.connected.contains(&peer_id) {
+            self.schedule_redial(peer_id);
+        }
+    }
+
+    pub fn sent(&mut self, request_id: I, peer_id: PeerId, request: R) {
+        self.in_flight.insert(request_id, (peer_id, request));
+    }
+
+    /// The peer accepted a request.
+    /// If it had refused earlier ones, it's ready for them now, so they're returned to be sent.
+    pub fn acked(&mut self, request_id: &I) -> Option<(PeerId, VecDeque<R>)> {
+        let (peer_id, _) = self.in_flight.remove(request_id)?;
+        if self.connected.contains(&peer_id) && self.redial.contains_key(&peer_id) {
+            return Some((peer_id, self.connected(peer_id)));
+        }
+        None
+    }
+
+    /// The peer refused a request, because it hasn't been initialized yet.
+    /// Like a failed request, it's held and retried later, unless the peer isn't a member.
+    pub fn refused(&mut self, request_id: &I) {
+        self.failed(request_id);
+    }
+
+    /// A request failed, so it goes back in the queue until we reconnect to its destination.
+    /// Requests to non-members are dropped.
+    pub fn failed(&mut self, request_id: &I) {
+        if let Some((peer_id, request)) = self.in_flight.remove(request_id) {
+            if self.is_member(&peer_id) {
+                self.hold(peer_id, request);
+                self.schedule_redial(peer_id);
+            }
+        }
+    }
+
+    fn schedule_redial(&mut self, peer_id: PeerId) {
+        if self.is_member(&peer_id) {
+            self.redial
+                .entry(peer_id)
+                .or_insert_with(|| Backoff::new(Instant::now()));
+        }
+    }
+
+    // we only hold packets for members we've lost.
+    // anything else goes straight to `send_request`, which dials on demand.
+    pub fn should_hold(&self, peer_id: &PeerId) -> bool {
+        self.is_member(peer_id) && self.redial.contains_key(peer_id)
+    }
+
+    pub fn hold(&mut self, peer_id: PeerId, request: R) {
+        self.held.entry(peer_id).or_default().push_back(request);
+    }
+
+    pub fn held_count(&self) -> usize {
+        self.held.values().map(VecDeque::len).sum()
+    }
+
+    /// Returns the peers that are due for a redial (along with their last known addresses),
+    /// and pushes their next attempt back.
+    pub fn due_redials(&mut self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (peer_id, backoff) in self.redial.iter_mut() {
+            // there's nothing to dial while we're connected, see `due_resends`.
+            if self.connected.contains(peer_id) {
+                continue;
+            }
+            if backoff.next_attempt <= now {
+                backoff.bump(now);
+                let addresses = self.addresses.get(peer_id).cloned().unwrap_or_default();
+                due.push((*peer_id, addresses));
+            }
+        }
+        due
+    }
+
+    /// Returns the requests held for members we're still connected to (because they refused
+    /// or failed them), for those that are due for another try, and pushes their next try back.
+    pub fn due_resends(&mut self) -> Vec<(PeerId, VecDeque<R>)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (peer_id, backoff) in self.redial.iter_mut() {
+            if !self.connected.contains(peer_id) || backoff.next_attempt > now {
+                continue;
+            }
+            backoff.bump(now);
+            if let Some(held) = self.held.remove(peer_id) {
+                due.push((*peer_id, held));
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(members: &[PeerId]) -> PeerTracker<&'static str, u64> {
+        let mut peers = PeerTracker::default();
+        peers.set_members(members.iter().copied());
+        peers
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        let now = Instant::now();
+        let mut backoff = Backoff::new(now);
+        assert_eq!(backoff.next_attempt, now);
+        let mut delays = Vec::new();
+        for _ in 0..8 {
+            backoff.bump(now);
+            delays.push((backoff.next_attempt - now).as_secs());
+        }
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+    }
+
+    #[test]
+    fn lost_members_are_redialed_once_per_backoff() {
+        let member = PeerId::random();
+        let address: Multiaddr = "/memory/1".parse().unwrap();
+        let mut peers = tracker(&[member]);
+        peers.discovered(member, address.clone());
+        peers.connected(member);
+        assert!(peers.due_redials().is_empty());
+
+        peers.disconnected(member);
+        assert_eq!(peers.due_redials(), vec![(member, vec![address])]);
+        // the next attempt is a second away
+        assert!(peers.due_redials().is_empty());
+
+        peers.connected(member);
+        assert!(peers.due_redials().is_empty());
+    }
+
+    #[test]
+    fn held_requests_are_flushed_on_reconnect() {
+        let member = PeerId::random();
+        let mut peers = tracker(&[member]);
+        peers.connected(member);
+        assert!(!peers.should_hold(&member));
+
+        peers.disconnected(member);
+        assert!(peers.should_hold(&member));
+        peers.hold(member, "a");
+        peers.hold(member, "b");
+        assert_eq!(peers.held_count(), 2);
+
+        assert_eq!(peers.connected(member), VecDeque::from(["a", "b"]));
+        assert_eq!(peers.held_count(), 0);
+        assert!(!peers.should_hold(&member));
+    }
+
+    #[test]
+    fn only_lost_members_are_held_for() {
+        let member = PeerId::random();
+        let stranger = PeerId::random();
+        let mut peers = tracker(&[member]);
+        // a member we've never lost
+        assert!(!peers.should_hold(&member));
+        // non-members are dialed on demand instead
+        peers.disconnected(stranger);
+        peers.expired(stranger);
+        assert!(!peers.should_hold(&stranger));
+        assert!(peers.due_redials().is_empty());
+        // an expired record means nothing while we're still connected
+        peers.connected(member);
+        peers.expired(member);
+        assert!(!peers.should_hold(&member));
+    }
+
+    #[test]
+    fn failed_requests_are_held_for_members_only() {
+        let member = PeerId::random();
+        let stranger = PeerId::random();
+        let mut peers = tracker(&[member]);
+        peers.sent(1, member, "to member");
+        peers.sent(2, stranger, "to stranger");
+        peers.failed(&1);
+        peers.failed(&2);
+        assert_eq!(peers.held_count(), 1);
+        assert!(peers.should_hold(&member));
+        assert!(!peers.should_hold(&stranger));
+        assert_eq!(peers.connected(member), VecDeque::from(["to member"]));
+        // failing twice, or failing something that was acked, changes nothing
+        peers.sent(3, member, "acked");
+        assert_eq!(peers.acked(&3), None);
+        peers.failed(&1);
+        peers.failed(&3);
+        assert_eq!(peers.held_count(), 0);
+    }
+
+    #[test]
+    fn refused_requests_are_resent_over_the_same_connection() {
+        let member = PeerId::random();
+        let mut peers = tracker(&[member]);
+        peers.connected(member);
+        peers.sent(1, member, "refused");
+        peers.refused(&1);
+        assert!(peers.should_hold(&member));
+
+        // we're still connected, so there's nothing to dial: the request is just sent again.
+        assert!(peers.due_redials().is_empty());
+        assert_eq!(
+            peers.due_resends(),
+            vec![(member, VecDeque::from(["refused"]))]
+        );
+        assert!(peers.due_resends().is_empty());
+
+        // until the member takes a request, everything else for it is held too.
+        peers.hold(member, "later");
+        peers.sent(2, member, "refused");
+        assert_eq!(peers.acked(&2), Some((member, VecDeque::from(["later"]))));
+        assert!(!peers.should_hold(&member));
+        assert_eq!(peers.held_count(), 0);
+    }
+
+    #[test]
+    fn refused_requests_to_non_members_are_dropped() {
+        let stranger = PeerId::random();
+        let mut peers = tracker(&[]);
+        peers.connected(stranger);
+        peers.sent(1, stranger, "refused");
+        peers.refused(&1);
+        assert!(!peers.should_hold(&stranger));
+        assert_eq!(peers.held_count(), 0);
+        assert!(peers.due_resends().is_empty());
+    }
+}
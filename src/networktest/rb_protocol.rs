@@ -1,7 +1,15 @@
+use super::byzantine::{ByzantineKind, ByzantineStrategy, NodeContext};
+use super::network_protocol::{self, DrivenProtocol, NetworkProtocol, Request, Response};
+#[cfg(feature = "lean")]
 use crate::ffitest::lean_helpers::{self, rust_string_to_lean, Mode};
+#[cfg(feature = "lean")]
 use lean_sys::*;
+use libp2p::identity::Keypair;
+#[cfg(feature = "lean")]
 use once_cell::sync::OnceCell;
-use std::{collections::HashMap, error::Error, fmt::Display, sync::Mutex};
+use std::fmt::Display;
+#[cfg(feature = "lean")]
+use std::{collections::HashMap, sync::Mutex};
 
 // TODO: an alternative implementation for the global message hashtable would be to store global state
 // either in the IO Monad or some form of State monad (StateM).
@@ -10,8 +18,10 @@ use std::{collections::HashMap, error::Error, fmt::Display, sync::Mutex};
 // i should investigate this in the future.
 //
 // maps from Address (String) -> Message (String)
+#[cfg(feature = "lean")]
 static GLOBAL_MESSAGE_HASHTBL: OnceCell<Mutex<HashMap<String, String>>> = OnceCell::new();
 
+#[cfg(feature = "lean")]
 #[no_mangle]
 pub unsafe extern "C" fn get_node_value(node_address: *mut lean_object) -> *mut lean_object {
     // println!("[rb_protocol::get_node_value] (extern) called");
@@ -33,13 +43,103 @@ pub unsafe extern "C" fn get_node_value(node_address: *mut lean_object) -> *mut
     rust_string_to_lean(message_rust)
 }
 
+// called by `dbg_print'` in `ReliableBroadcast.lean`.
+// (it lives next to `get_node_value`, so that anything linking the protocol also links this.)
+#[cfg(feature = "lean")]
+#[no_mangle]
+pub unsafe extern "C" fn dbg_print_rust(s: *mut lean_object) -> usize {
+    let ss = lean_helpers::lean_string_to_rust(s, Mode::Owned);
+    println!("[from lean]: {ss}");
+
+    return 0;
+}
+
+// the messages and packets of `ReliableBroadcast.lean`, shared by the lean binding (`lean`)
+// and the native port (`rb_native`).
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    InitialMsg {
+        r: usize,
+        v: String,
+    },
+    EchoMsg {
+        originator: String,
+        r: usize,
+        v: String,
+    },
+    VoteMsg {
+        originator: String,
+        r: usize,
+        v: String,
+    },
+}
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::InitialMsg { r, v } => write!(f, "InitialMsg @ round {}: {}", r, v),
+            Message::EchoMsg { originator, r, v } => {
+                write!(f, "EchoMsg from {} @ round {}: {}", originator, r, v)
+            }
+            Message::VoteMsg { originator, r, v } => {
+                write!(f, "VoteMsg from {} @ round {}: {}", originator, r, v)
+            }
+        }
+    }
+}
+
+impl Message {
+    pub fn get_round(&self) -> usize {
+        match &self {
+            Self::InitialMsg { r, .. } | Self::EchoMsg { r, .. } | Self::VoteMsg { r, .. } => *r,
+        }
+    }
+}
+
+pub type Packet = network_protocol::Packet<String, Message>;
+
+// the protocol the rest of the crate runs: the compiled lean one,
+// or the native port of it when the crate is built without a lean toolchain.
+#[cfg(not(feature = "lean"))]
+pub use super::rb_native::{initialize, Protocol};
+#[cfg(feature = "lean")]
+pub use lean::{initialize, Protocol};
+
+#[cfg(feature = "lean")]
 pub mod lean {
 
     use crate::ffitest::lean_helpers::{self, lean_string_to_rust, rust_string_to_lean, Mode};
     use lean_sys::*;
-    use std::{collections::HashMap, sync::Mutex};
+    use std::{cell::Cell, collections::HashMap, sync::Mutex, sync::Once};
 
     use super::GLOBAL_MESSAGE_HASHTBL;
+    pub use super::{Message, Packet};
+
+    static LEAN_INITIALIZED: Once = Once::new();
+    static SENDING: Mutex<()> = Mutex::new(());
+
+    thread_local! {
+        static THREAD_INITIALIZED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// Initializes the lean runtime and the `Protocol` module.
+    /// Only the first call does anything, so every user of `Protocol` can call this up front.
+    /// Any other thread that wants to call into lean has to call this too, before its first call.
+    /// (e.g. test threads, which each get their own.)
+    pub fn initialize() {
+        let mut first = false;
+        LEAN_INITIALIZED.call_once(|| unsafe {
+            lean_helpers::initialize_lean_environment(initialize_Protocol);
+            first = true;
+        });
+
+        // the thread that initialized the runtime is already set up,
+        // but every other thread needs its own lean heap.
+        THREAD_INITIALIZED.with(|initialized| {
+            if !initialized.replace(true) && !first {
+                unsafe { lean_initialize_thread() };
+            }
+        });
+    }
 
     // note: we link with `ProtocolFat`, not `Protocol`.
     // this is because `Protocol.lean` has several additional dependencies that we need to link with,
@@ -54,8 +154,6 @@ pub mod lean {
         ) -> lean_sys::lean_obj_res;
 
         fn create_protocol(node_arr: lean_sys::lean_obj_arg) -> lean_sys::lean_obj_res;
-        // TODO: remove if unused
-        #[allow(dead_code)]
         fn create_packet(
             src: lean_sys::lean_obj_arg,
             dst: lean_sys::lean_obj_arg,
@@ -88,6 +186,61 @@ pub mod lean {
             leader: lean_sys::lean_obj_arg,
             round: usize,
         ) -> lean_sys::lean_obj_res;
+
+        fn node_sent(node_state: lean_sys::lean_obj_arg, round: usize) -> u8;
+        fn node_echoed(
+            node_state: lean_sys::lean_obj_arg,
+            originator: lean_sys::lean_obj_arg,
+            round: usize,
+        ) -> lean_sys::lean_obj_res;
+        fn node_voted(
+            node_state: lean_sys::lean_obj_arg,
+            originator: lean_sys::lean_obj_arg,
+            round: usize,
+        ) -> lean_sys::lean_obj_res;
+        fn node_output(
+            node_state: lean_sys::lean_obj_arg,
+            originator: lean_sys::lean_obj_arg,
+            round: usize,
+        ) -> lean_sys::lean_obj_res;
+        fn node_received_from(
+            node_state: lean_sys::lean_obj_arg,
+            msg: lean_sys::lean_obj_arg,
+        ) -> lean_sys::lean_obj_res;
+    }
+
+    // takes ownership of a lean `ConcreteValueOption`.
+    unsafe fn value_option_to_rust(option: *mut lean_object) -> Option<String> {
+        // `ConcreteValueOption.None` has no fields, so lean represents it as a boxed scalar
+        // instead of a pointer to a constructor object.
+        // this is why poking at the result (e.g. with `what_is_this`) used to segfault:
+        // it was dereferencing a scalar.
+        if lean_is_scalar(option) {
+            return None;
+        }
+
+        let value = lean_string_to_rust(lean_ctor_get(option, 0), Mode::Borrow);
+        // RC: we own the option, and have copied everything we need out of it.
+        lean_dec(option);
+        Some(value)
+    }
+
+    /// Everything a node knows about a bounded set of broadcasts, pulled out of its lean state.
+    /// Two nodes with the same snapshot behave the same on these broadcasts.
+    ///
+    /// Lists of senders are sorted, since the protocol only ever checks them for membership
+    /// and length, so the order messages arrived in doesn't matter.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub struct NodeSnapshot {
+        pub address: String,
+        pub sent: Vec<usize>,
+        // (originator, round, value)
+        pub echoed: Vec<(String, usize, String)>,
+        pub voted: Vec<(String, usize, String)>,
+        // (originator, round, values)
+        pub output: Vec<(String, usize, Vec<String>)>,
+        // (message, senders), only for messages received from someone
+        pub received_from: Vec<(String, Vec<String>)>,
     }
 
     // note: even though the lean representation looks identical to this enum type,
@@ -95,45 +248,7 @@ pub mod lean {
     // since `USize` fields are ordered AFTER `lean_object` fields, each constructor would look like:
     // | EchoMsg { originator: String, v: String, r: usize }
     // and we'll have to deconstruct it in that order.
-    #[derive(serde::Serialize, serde::Deserialize, Debug)]
-    pub enum Message {
-        InitialMsg {
-            r: usize,
-            v: String,
-        },
-        EchoMsg {
-            originator: String,
-            r: usize,
-            v: String,
-        },
-        VoteMsg {
-            originator: String,
-            r: usize,
-            v: String,
-        },
-    }
-    impl std::fmt::Display for Message {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match self {
-                Message::InitialMsg { r, v } => write!(f, "InitialMsg @ round {}: {}", r, v),
-                Message::EchoMsg { originator, r, v } => {
-                    write!(f, "EchoMsg from {} @ round {}: {}", originator, r, v)
-                }
-                Message::VoteMsg { originator, r, v } => {
-                    write!(f, "VoteMsg from {} @ round {}: {}", originator, r, v)
-                }
-            }
-        }
-    }
-
     impl Message {
-        pub fn get_round(&self) -> usize {
-            match &self {
-                Self::InitialMsg { r, .. } | Self::EchoMsg { r, .. } | Self::VoteMsg { r, .. } => {
-                    *r
-                }
-            }
-        }
         pub unsafe fn from_lean(msg_lean: *mut lean_object) -> Self {
             // println!("[rb_protocol::lean::Message::from_lean] called");
             let tag = lean_ptr_tag(msg_lean);
@@ -186,6 +301,8 @@ pub mod lean {
         }
 
         // Takes ownership of the Rust Message.
+        // `create_message` ends in `sorry` for unknown tags, but we only ever pass it
+        // the tags of this enum, even for messages decoded from untrusted peers.
         pub unsafe fn to_lean(self) -> *mut lean_object {
             let tag: usize;
             let originator_r: String;
@@ -224,24 +341,6 @@ pub mod lean {
         }
     }
 
-    #[derive(serde::Serialize, serde::Deserialize, Debug)]
-    pub struct Packet {
-        pub src: String,
-        pub dst: String,
-        pub msg: Message,
-        pub consumed: bool,
-    }
-
-    impl std::fmt::Display for Packet {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(
-                f,
-                "packet from {} to {} with message '{}' (consumed: {})",
-                self.src, self.dst, self.msg, self.consumed
-            )
-        }
-    }
-
     impl Packet {
         // TODO: check if the convention should be `from_lean` or `of_lean`.
         /// Converts a Lean packet to its Rust representation.
@@ -276,10 +375,8 @@ pub mod lean {
         }
 
         // Takes ownership of the rust packet.
-        // note: doesn't seem like this is needed, since we can just convert the
-        // message directly?
-        // TODO: remove if unused
-        #[allow(dead_code)]
+        // note: the driver doesn't need this, since it converts the message directly.
+        // it's used to round-trip packets through lean (see `fuzz/` and the tests below).
         pub unsafe fn to_lean(self) -> *mut lean_object {
             create_packet(
                 rust_string_to_lean(self.src),
@@ -317,10 +414,9 @@ pub mod lean {
             lean_inc(protocol);
             let node_state = init_node_state(protocol, node_address_lean);
 
-            // initialize the global message hashtbl
-            GLOBAL_MESSAGE_HASHTBL
-                .set(Mutex::new(HashMap::new()))
-                .expect("should be able to init global hashtbl");
+            // initialize the global message hashtbl.
+            // it is keyed by address, so several protocols (e.g. in the simulator) can share it.
+            GLOBAL_MESSAGE_HASHTBL.get_or_init(|| Mutex::new(HashMap::new()));
 
             // initialize round to 0
             let round = 0;
@@ -404,6 +500,11 @@ pub mod lean {
 
         pub unsafe fn send_message(&mut self, address: String, message: String) -> Vec<Packet> {
             // println!("[rb_protocol::lean::send_message] {address} : {message} ");
+            // the message db is keyed by address, and addresses are only unique within one protocol.
+            // hold this across the whole call, so that protocols running side by side
+            // (e.g. simulations on different test threads) can't read each other's messages.
+            let _sending = SENDING.lock().unwrap_or_else(|e| e.into_inner());
+
             // update the message db with the current message
             let mut ht = GLOBAL_MESSAGE_HASHTBL
                 .get()
@@ -465,59 +566,365 @@ pub mod lean {
             packets_to_send
         }
 
-        pub unsafe fn check_output(&mut self, round: usize) {
+        /// Returns the value delivered by this node for the leader's broadcast in `round`, if any.
+        pub unsafe fn check_output(&mut self, round: usize) -> Option<String> {
             let leader = rust_string_to_lean(self.leader.clone());
 
             lean_inc(self.node_state);
-            let _output_opt_lean = check_output(self.node_state, leader, round);
-
-            // TODO: there's currently something very wrong with this, where
-            // the result of the `check_output` call doesn't even seem to be a valid Lean object.
-            // trying to do anything wiht it just segfaults.
-            // currently, we just debug print the output from lean directly as a band-aid solution.
-            // what_is_this("my option", output_opt_lean);
-
-            // let cast = |lean_str| lean_string_to_rust(lean_str, Mode::Borrow);
-            // let output_opt = lean_option_to_rust(output_opt_lean, cast);
-
-            // // we would normally return [output_opt] here to pass back to the application code,
-            // // but for now we just display it.
-            // match output_opt {
-            //     Some(v) => {
-            //         println!("\n============ CONSENSUS OBTAINED FOR ROUND {round} =============");
-            //         println!("\nValue: {v}\n");
-            //         println!("===============================================================\n");
-            //     }
-            //     None => (),
-            // }
+            let output_opt_lean = check_output(self.node_state, leader, round);
+            value_option_to_rust(output_opt_lean)
+        }
+
+        /// Makes an independent copy of this node, e.g. to explore two different futures from here.
+        /// Lean values are immutable (lean only updates in place when nobody else holds a
+        /// reference), so the copy just takes another reference to the same lean objects.
+        pub unsafe fn fork(&self) -> Self {
+            lean_inc(self.protocol);
+            lean_inc(self.node_state);
+            Protocol {
+                protocol: self.protocol,
+                node_state: self.node_state,
+                round: self.round,
+                leader: self.leader.clone(),
+            }
+        }
+
+        /// Drops this node's references to its lean objects.
+        pub unsafe fn release(self) {
+            lean_dec(self.protocol);
+            lean_dec(self.node_state);
+        }
+
+        /// Reads this node's state for every broadcast by one of `originators` in `0..rounds`,
+        /// and for every message in `messages`.
+        pub unsafe fn snapshot(
+            &self,
+            address: &str,
+            originators: &[String],
+            rounds: usize,
+            messages: &[Message],
+        ) -> NodeSnapshot {
+            let mut snapshot = NodeSnapshot {
+                address: address.to_string(),
+                sent: Vec::new(),
+                echoed: Vec::new(),
+                voted: Vec::new(),
+                output: Vec::new(),
+                received_from: Vec::new(),
+            };
+
+            // RC: every export takes ownership of the node state, so we bump it before each call.
+            for round in 0..rounds {
+                lean_inc(self.node_state);
+                if node_sent(self.node_state, round) != 0 {
+                    snapshot.sent.push(round);
+                }
+
+                for originator in originators {
+                    let originator_lean = || rust_string_to_lean(originator.clone());
+
+                    lean_inc(self.node_state);
+                    let echoed = node_echoed(self.node_state, originator_lean(), round);
+                    if let Some(v) = value_option_to_rust(echoed) {
+                        snapshot.echoed.push((originator.clone(), round, v));
+                    }
+
+                    lean_inc(self.node_state);
+                    let voted = node_voted(self.node_state, originator_lean(), round);
+                    if let Some(v) = value_option_to_rust(voted) {
+                        snapshot.voted.push((originator.clone(), round, v));
+                    }
+
+                    lean_inc(self.node_state);
+                    let output = node_output(self.node_state, originator_lean(), round);
+                    let values = lean_helpers::lean_string_array_to_rust(output);
+                    lean_dec(output);
+                    if !values.is_empty() {
+                        snapshot.output.push((originator.clone(), round, values));
+                    }
+                }
+            }
+
+            for msg in messages {
+                lean_inc(self.node_state);
+                let senders_lean = node_received_from(self.node_state, msg.clone().to_lean());
+                let mut senders = lean_helpers::lean_string_array_to_rust(senders_lean);
+                lean_dec(senders_lean);
+                if !senders.is_empty() {
+                    senders.sort();
+                    snapshot.received_from.push((msg.to_string(), senders));
+                }
+            }
+
+            snapshot
         }
     }
 }
 
-// for RB, we send all packets via `Request`s, and acknowledge receiving a packet
-// via a `Response`.`
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct RBRequest {
-    pub packet: lean::Packet,
-}
+// the requests and responses `libp2p_rb` sends when it runs RB.
+pub type RBRequest = Request<Message>;
+pub type RBResponse = Response;
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub enum RBResponse {
-    Ack,
+/// A value this node delivered: RB's output.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Delivery {
+    pub leader: String,
+    pub round: usize,
+    pub value: String,
 }
 
-// may want to consider using a crate like `derive_more` to help us derive
-// `Display` here.
-impl Display for RBRequest {
+impl Display for Delivery {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "request: {}", self.packet)
+        write!(
+            f,
+            "delivered '{}' from {} in round {}",
+            self.value, self.leader, self.round
+        )
     }
 }
 
-impl Display for RBResponse {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "response: ack")
+/// RB as a `NetworkProtocol`, for the driver to run.
+/// Every node runs `Protocol`, unless it's byzantine, in which case it runs its strategy instead.
+pub struct ReliableBroadcast {
+    pub node_list: Vec<String>,
+    pub leader: String,
+    pub byzantine: Option<ByzantineKind>,
+}
+
+pub enum RBState {
+    Honest {
+        address: String,
+        protocol: Protocol,
+        deliveries: Vec<Delivery>,
+    },
+    // byzantine nodes don't run the protocol, so they never deliver anything.
+    Byzantine {
+        address: String,
+        strategy: Box<dyn ByzantineStrategy>,
+        round: usize,
+    },
+}
+
+impl ReliableBroadcast {
+    // byzantine nodes never loop packets back to themselves.
+    // everything they craft for other nodes goes straight out.
+    fn without_self(address: &str, packets: Vec<Packet>) -> Vec<Packet> {
+        packets
+            .into_iter()
+            .filter(|packet| packet.dst != address)
+            .collect()
+    }
+}
+
+impl NetworkProtocol for ReliableBroadcast {
+    type Address = String;
+    type Message = Message;
+    // the value to broadcast, in the leader's next round
+    type InternalTransition = String;
+    type State = RBState;
+    type Output = Delivery;
+
+    // without lean, `Protocol` is the native port, whose methods are safe to call.
+    #[cfg_attr(not(feature = "lean"), allow(unused_unsafe))]
+    fn local_init(&self, address: &String) -> RBState {
+        match self.byzantine {
+            Some(kind) => {
+                println!("[rb_protocol] running as a byzantine node ({kind})");
+                RBState::Byzantine {
+                    address: address.clone(),
+                    strategy: kind.build(),
+                    round: 0,
+                }
+            }
+            None => RBState::Honest {
+                address: address.clone(),
+                protocol: unsafe {
+                    Protocol::create(self.node_list.clone(), address.clone(), self.leader.clone())
+                },
+                deliveries: Vec::new(),
+            },
+        }
+    }
+
+    #[cfg_attr(not(feature = "lean"), allow(unused_unsafe))]
+    fn proc_internal(&self, state: &mut RBState, value: String) -> Vec<Packet> {
+        match state {
+            RBState::Honest {
+                address, protocol, ..
+            } => unsafe { protocol.send_message(address.clone(), value) },
+            RBState::Byzantine {
+                address,
+                strategy,
+                round,
+            } => {
+                let ctx = NodeContext {
+                    address: address.as_str(),
+                    nodes: &self.node_list,
+                };
+                let packets = strategy.on_broadcast(&ctx, *round, &value);
+                *round += 1;
+                Self::without_self(address, packets)
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "lean"), allow(unused_unsafe))]
+    fn proc_message(&self, state: &mut RBState, src: String, msg: Message) -> Vec<Packet> {
+        match state {
+            RBState::Honest {
+                address,
+                protocol,
+                deliveries,
+            } => {
+                let round = msg.get_round();
+                let packet = Packet {
+                    src,
+                    dst: address.clone(),
+                    msg,
+                    consumed: false,
+                };
+                let packets = unsafe { protocol.handle_packet(packet) };
+
+                // a round is delivered at most once.
+                let delivered = deliveries.iter().any(|delivery| delivery.round == round);
+                if !delivered {
+                    if let Some(value) = unsafe { protocol.check_output(round) } {
+                        deliveries.push(Delivery {
+                            leader: self.leader.clone(),
+                            round,
+                            value,
+                        });
+                    }
+                }
+                packets
+            }
+            RBState::Byzantine {
+                address, strategy, ..
+            } => {
+                let ctx = NodeContext {
+                    address: address.as_str(),
+                    nodes: &self.node_list,
+                };
+                let packet = Packet {
+                    src,
+                    dst: address.clone(),
+                    msg,
+                    consumed: false,
+                };
+                let packets = strategy.on_packet(&ctx, &packet);
+                Self::without_self(address, packets)
+            }
+        }
+    }
+
+    fn outputs(&self, state: &RBState) -> Vec<Delivery> {
+        match state {
+            RBState::Honest { deliveries, .. } => deliveries.clone(),
+            RBState::Byzantine { .. } => Vec::new(),
+        }
+    }
+}
+
+impl DrivenProtocol for ReliableBroadcast {
+    type Config = Option<ByzantineKind>;
+
+    fn create(
+        byzantine: Option<ByzantineKind>,
+        node_list: Vec<String>,
+        leader: String,
+        _keypair: &Keypair,
+    ) -> Result<Self, String> {
+        Ok(ReliableBroadcast {
+            node_list,
+            leader,
+            byzantine,
+        })
+    }
+
+    fn broadcast(&self, _state: &mut RBState, value: String) -> String {
+        value
+    }
+
+    fn traced_packet(packet: &Packet) -> Option<Packet> {
+        Some(packet.clone())
+    }
+
+    fn traced_output(delivery: &Delivery) -> Option<Delivery> {
+        Some(delivery.clone())
     }
 }
 
-impl Error for RBResponse {}
+// regression tests for crashes found by the fuzz targets in `fuzz/`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "lean")]
+    fn round_trip(packet: Packet) -> Packet {
+        lean::initialize();
+        unsafe { Packet::from_lean(packet.clone().to_lean()) }
+    }
+
+    fn packet(src: &str, dst: &str, msg: Message) -> Packet {
+        Packet {
+            src: src.to_string(),
+            dst: dst.to_string(),
+            msg,
+            consumed: false,
+        }
+    }
+
+    // used to panic in `rust_string_to_lean`, which went through a C string.
+    #[cfg(feature = "lean")]
+    #[test]
+    fn strings_with_nul_bytes_round_trip_through_lean() {
+        let packet = packet(
+            "a\0b",
+            "\0",
+            Message::EchoMsg {
+                originator: String::from("\0\0"),
+                r: 3,
+                v: String::from("before\0after"),
+            },
+        );
+        assert_eq!(round_trip(packet.clone()), packet);
+    }
+
+    #[cfg(feature = "lean")]
+    #[test]
+    fn empty_and_multibyte_strings_round_trip_through_lean() {
+        let packet = packet(
+            "",
+            "노드",
+            Message::VoteMsg {
+                originator: String::from("🦀"),
+                r: usize::MAX,
+                v: String::new(),
+            },
+        );
+        assert_eq!(round_trip(packet.clone()), packet);
+    }
+
+    // a valid request, cut short anywhere, must fail to decode rather than panic.
+    #[test]
+    fn truncated_requests_fail_to_decode() {
+        let request = RBRequest {
+            packets: vec![packet(
+                "src",
+                "dst",
+                Message::InitialMsg {
+                    r: 0,
+                    v: String::from("value"),
+                },
+            )],
+        };
+        let bytes = cbor4ii::serde::to_vec(Vec::new(), &request).unwrap();
+        assert_eq!(
+            cbor4ii::serde::from_slice::<RBRequest>(&bytes).unwrap(),
+            request
+        );
+        for end in 0..bytes.len() {
+            assert!(cbor4ii::serde::from_slice::<RBRequest>(&bytes[..end]).is_err());
+        }
+    }
+}
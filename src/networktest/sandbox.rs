@@ -1,14 +1,6 @@
 use crate::ffitest::lean_helpers::*;
 use crate::networktest::rb_protocol;
-use lean_sys::*;
 
-#[no_mangle]
-pub unsafe extern "C" fn dbg_print_rust(s: *mut lean_object) -> usize {
-    let ss = lean_string_to_rust(s, Mode::Owned);
-    println!("[from lean]: {ss}");
-
-    return 0;
-}
 pub fn main() {
     unsafe {
         initialize_lean_environment(rb_protocol::lean::initialize_Protocol);
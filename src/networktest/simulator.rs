@@ -0,0 +1,849 @@
+use crate::networktest::byzantine::{
+    parse_assignments, ByzantineKind, ByzantineStrategy, NodeContext,
+};
+use crate::networktest::network_protocol::{self, NetworkProtocol};
+use crate::networktest::rb_protocol::{self, Packet, Protocol};
+use crate::networktest::trace::{self, TraceEvent};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+// a deterministic, in-process network simulator for the lean RB protocol.
+//
+// every node is a real `rb_protocol::Protocol` (the compiled lean code, unless the crate is built
+// without it), but instead of going over libp2p, packets go through a seeded scheduler
+// that delays, reorders and duplicates them.
+// the same seed always produces the same schedule, so any failing run can be replayed exactly.
+// alternatively, the delivery order can be scripted step by step (see `SimConfig::schedule`),
+// which is what the property tests below generate and shrink.
+//
+// once the network is quiet, we check the reliable broadcast properties:
+// - agreement: no two honest nodes deliver different values for the same round
+// - validity: if the leader is honest, every honest node delivers the leader's value
+// - integrity: a node delivers at most one value per round, and never changes its mind
+// - totality: if some honest node delivers in a round, every honest node does
+//
+// some nodes can be made byzantine (see `byzantine.rs`). the properties are only checked for
+// the honest ones, and they're only guaranteed to hold as long as fewer than a third are byzantine.
+//
+// other protocols (anything that's a `NetworkProtocol`) run on the same network with
+// `ProtocolSimulator`, which leaves checking their properties to the caller.
+
+// a config (with the seed, or the script) is all it takes to replay a run,
+// so it round-trips through JSON: `cargo run -- sim --replay '<json>'`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    pub nodes: usize,
+    pub rounds: usize,
+    // the values the leader broadcasts, one per round.
+    // rounds without one get a generated value.
+    pub values: Vec<String>,
+    pub seed: u64,
+    // every packet is delayed by a random number of ticks in [0, max_delay].
+    // packets that become due in the same tick are delivered in random order.
+    pub max_delay: u64,
+    // chance that a packet is delivered twice
+    pub duplicate_probability: f64,
+    // safety net, in case the protocol never quiesces
+    pub max_steps: usize,
+    // node index -> strategy, for the nodes that don't run the protocol honestly
+    pub byzantine: BTreeMap<usize, ByzantineKind>,
+    // if set, step `i` delivers the `schedule[i]`-th in-flight packet (modulo the number in flight,
+    // oldest first) instead of following the random delays. once the script runs out,
+    // packets are delivered oldest first.
+    pub schedule: Option<Vec<usize>>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            nodes: 4,
+            rounds: 1,
+            values: Vec::new(),
+            seed: 0,
+            max_delay: 10,
+            duplicate_probability: 0.1,
+            max_steps: 100_000,
+            byzantine: BTreeMap::new(),
+            schedule: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    Agreement {
+        round: usize,
+        node_a: String,
+        value_a: String,
+        node_b: String,
+        value_b: String,
+    },
+    Validity {
+        round: usize,
+        node: String,
+        expected: String,
+        delivered: Option<String>,
+    },
+    Integrity {
+        round: usize,
+        node: String,
+        first: String,
+        second: String,
+    },
+    Totality {
+        round: usize,
+        delivered: String,
+        missing: String,
+    },
+    // the network never went quiet within `max_steps`
+    NoQuiescence {
+        steps: usize,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::Agreement {
+                round,
+                node_a,
+                value_a,
+                node_b,
+                value_b,
+            } => write!(
+                f,
+                "agreement @ round {round}: {node_a} delivered '{value_a}' but {node_b} delivered '{value_b}'"
+            ),
+            Violation::Validity {
+                round,
+                node,
+                expected,
+                delivered,
+            } => write!(
+                f,
+                "validity @ round {round}: {node} delivered {delivered:?} instead of '{expected}'"
+            ),
+            Violation::Integrity {
+                round,
+                node,
+                first,
+                second,
+            } => write!(
+                f,
+                "integrity @ round {round}: {node} delivered '{first}' and then '{second}'"
+            ),
+            Violation::Totality {
+                round,
+                delivered,
+                missing,
+            } => write!(
+                f,
+                "totality @ round {round}: {delivered} delivered but {missing} never did"
+            ),
+            Violation::NoQuiescence { steps } => {
+                write!(f, "network still busy after {steps} steps")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SimReport {
+    pub seed: u64,
+    pub steps: usize,
+    // (round, node) -> delivered value
+    pub deliveries: BTreeMap<(usize, String), String>,
+    pub violations: Vec<Violation>,
+    pub byzantine: Vec<String>,
+    // packets dropped because a byzantine node tried to send them under someone else's name
+    pub forgeries: usize,
+    // every packet, in the order it was delivered
+    pub trace: Vec<String>,
+    // everything that happened, for `trace.rs` to render
+    pub events: Vec<TraceEvent>,
+}
+
+impl SimReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl Display for SimReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "seed {}: {} steps, {} deliveries, {} violations",
+            self.seed,
+            self.steps,
+            self.deliveries.len(),
+            self.violations.len()
+        )?;
+        if !self.byzantine.is_empty() {
+            writeln!(
+                f,
+                "  byzantine: {} ({} forged packets dropped)",
+                self.byzantine.join(", "),
+                self.forgeries
+            )?;
+        }
+        for ((round, node), value) in self.deliveries.iter() {
+            writeln!(f, "  {node} delivered '{value}' for round {round}")?;
+        }
+        for violation in self.violations.iter() {
+            writeln!(f, "  VIOLATION: {violation}")?;
+        }
+        if !self.violations.is_empty() {
+            writeln!(f, "  trace:")?;
+            for (step, packet) in self.trace.iter().enumerate() {
+                writeln!(f, "    {step}: {packet}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Scheduled<T> {
+    deliver_at: u64,
+    // breaks ties between packets that are due in the same tick
+    tiebreak: u64,
+    packet: T,
+}
+
+// the network every simulation runs on: whatever goes in comes out delayed, reordered,
+// and sometimes duplicated, as picked by the seed (or the script). see `SimConfig`.
+struct Network<T> {
+    rng: StdRng,
+    max_delay: u64,
+    duplicate_probability: f64,
+    schedule: Option<Vec<usize>>,
+    // oldest first
+    in_flight: Vec<Scheduled<T>>,
+    now: u64,
+    steps: usize,
+}
+
+impl<T: Clone> Network<T> {
+    fn new(config: &SimConfig) -> Self {
+        Network {
+            rng: StdRng::seed_from_u64(config.seed),
+            max_delay: config.max_delay,
+            duplicate_probability: config.duplicate_probability,
+            schedule: config.schedule.clone(),
+            in_flight: Vec::new(),
+            now: 0,
+            steps: 0,
+        }
+    }
+
+    fn send(&mut self, packet: T) {
+        let copies = if self.rng.gen_bool(self.duplicate_probability) {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let scheduled = Scheduled {
+                deliver_at: self.now + self.rng.gen_range(0..=self.max_delay),
+                tiebreak: self.rng.gen(),
+                packet: packet.clone(),
+            };
+            self.in_flight.push(scheduled);
+        }
+    }
+
+    // picks the in-flight packet to deliver next, either from the script or by due time.
+    fn next_index(&self) -> usize {
+        match &self.schedule {
+            Some(script) => script
+                .get(self.steps)
+                .map_or(0, |choice| choice % self.in_flight.len()),
+            None => self
+                .in_flight
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, scheduled)| (scheduled.deliver_at, scheduled.tiebreak))
+                .map(|(i, _)| i)
+                .expect("there should be a packet in flight"),
+        }
+    }
+
+    // takes the next packet off the network, and moves the clock up to when it's due.
+    fn next(&mut self) -> Option<T> {
+        if self.in_flight.is_empty() {
+            return None;
+        }
+        let scheduled = self.in_flight.remove(self.next_index());
+        self.now = self.now.max(scheduled.deliver_at);
+        self.steps += 1;
+        Some(scheduled.packet)
+    }
+
+    fn is_quiet(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
+
+enum SimNode {
+    Honest(Protocol),
+    Byzantine(Box<dyn ByzantineStrategy>),
+}
+
+pub struct Simulator {
+    config: SimConfig,
+    network: Network<Packet>,
+    addresses: Vec<String>,
+    nodes: Vec<SimNode>,
+    // round -> value the leader broadcast
+    inputs: BTreeMap<usize, String>,
+    deliveries: BTreeMap<(usize, String), String>,
+    violations: Vec<Violation>,
+    forgeries: usize,
+    trace: Vec<String>,
+    events: Vec<TraceEvent>,
+}
+
+pub fn node_address(i: usize) -> String {
+    format!("node-{i}")
+}
+
+impl Simulator {
+    /// Sets up `config.nodes` nodes, with `node-0` as the leader.
+    /// Lean is initialized on the calling thread, so the simulator must stay on that thread.
+    // (without lean, `Protocol`'s methods are safe, hence the `unused_unsafe`s.)
+    #[cfg_attr(not(feature = "lean"), allow(unused_unsafe))]
+    pub fn new(config: SimConfig) -> Self {
+        rb_protocol::initialize();
+
+        let addresses: Vec<String> = (0..config.nodes).map(node_address).collect();
+        let leader = addresses[0].clone();
+        let nodes = addresses
+            .iter()
+            .enumerate()
+            .map(|(i, address)| match config.byzantine.get(&i) {
+                Some(kind) => SimNode::Byzantine(kind.build()),
+                None => SimNode::Honest(unsafe {
+                    Protocol::create(addresses.clone(), address.clone(), leader.clone())
+                }),
+            })
+            .collect();
+
+        Simulator {
+            network: Network::new(&config),
+            config,
+            addresses,
+            nodes,
+            inputs: BTreeMap::new(),
+            deliveries: BTreeMap::new(),
+            violations: Vec::new(),
+            forgeries: 0,
+            trace: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    fn is_honest(&self, node: usize) -> bool {
+        matches!(self.nodes[node], SimNode::Honest(_))
+    }
+
+    // unforgeable channels: whatever `sender` sends goes out under its own address, or not at all.
+    fn send(&mut self, sender: usize, packets: Vec<Packet>) {
+        for packet in packets {
+            if packet.src == self.addresses[sender] {
+                self.events
+                    .push(TraceEvent::send(self.network.now, &packet));
+                self.network.send(packet);
+            } else {
+                self.forgeries += 1;
+            }
+        }
+    }
+
+    fn node_index(&self, address: &str) -> usize {
+        self.addresses
+            .iter()
+            .position(|a| a == address)
+            .expect("packets should only be addressed to known nodes")
+    }
+
+    /// The leader broadcasts `value` in the next round.
+    #[cfg_attr(not(feature = "lean"), allow(unused_unsafe))]
+    pub fn broadcast(&mut self, value: String) {
+        let round = self.inputs.len();
+        let packets = match &mut self.nodes[0] {
+            SimNode::Honest(leader) => unsafe {
+                leader.send_message(self.addresses[0].clone(), value.clone())
+            },
+            SimNode::Byzantine(strategy) => {
+                let ctx = NodeContext {
+                    address: &self.addresses[0],
+                    nodes: &self.addresses,
+                };
+                strategy.on_broadcast(&ctx, round, &value)
+            }
+        };
+        self.inputs.insert(round, value);
+        if self.is_honest(0) {
+            let transitions = trace::transitions(self.network.now, &self.addresses[0], &packets);
+            self.events.extend(transitions);
+        }
+        self.send(0, packets);
+    }
+
+    /// Delivers the next packet. Returns false once the network is quiet.
+    #[cfg_attr(not(feature = "lean"), allow(unused_unsafe))]
+    pub fn step(&mut self) -> bool {
+        let Some(packet) = self.network.next() else {
+            return false;
+        };
+        let dst = self.node_index(&packet.dst);
+        let round = packet.msg.get_round();
+        self.trace.push(packet.to_string());
+        self.events
+            .push(TraceEvent::receive(self.network.now, &packet));
+
+        match &mut self.nodes[dst] {
+            SimNode::Honest(node) => {
+                let packets = unsafe { node.handle_packet(packet) };
+                let output = unsafe { node.check_output(round) };
+                let transitions =
+                    trace::transitions(self.network.now, &self.addresses[dst], &packets);
+                self.events.extend(transitions);
+                self.send(dst, packets);
+
+                if let Some(value) = output {
+                    self.record_delivery(round, dst, value);
+                }
+            }
+            SimNode::Byzantine(strategy) => {
+                let ctx = NodeContext {
+                    address: &self.addresses[dst],
+                    nodes: &self.addresses,
+                };
+                let packets = strategy.on_packet(&ctx, &packet);
+                self.send(dst, packets);
+            }
+        }
+        true
+    }
+
+    fn record_delivery(&mut self, round: usize, node: usize, value: String) {
+        let key = (round, self.addresses[node].clone());
+        match self.deliveries.get(&key) {
+            None => {
+                self.events.push(TraceEvent::Deliver {
+                    time: self.network.now,
+                    node: key.1.clone(),
+                    leader: self.addresses[0].clone(),
+                    round,
+                    value: value.clone(),
+                });
+                self.deliveries.insert(key, value);
+            }
+            Some(first) if *first != value => {
+                let violation = Violation::Integrity {
+                    round,
+                    node: key.1.clone(),
+                    first: first.clone(),
+                    second: value,
+                };
+                if !self.violations.contains(&violation) {
+                    self.violations.push(violation);
+                }
+            }
+            Some(_) => (),
+        }
+    }
+
+    // agreement, validity and totality only make sense once the network is quiet.
+    // they're only checked for honest nodes (byzantine nodes never deliver anything anyway).
+    fn check_quiescent_properties(&mut self) {
+        let honest: Vec<&String> = self
+            .addresses
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_honest(*i))
+            .map(|(_, address)| address)
+            .collect();
+        let honest_leader = self.is_honest(0);
+
+        for (&round, input) in self.inputs.iter() {
+            let delivered: Vec<(&String, Option<&String>)> = honest
+                .iter()
+                .map(|&node| (node, self.deliveries.get(&(round, node.clone()))))
+                .collect();
+
+            // agreement: compare everybody against the first node that delivered.
+            let first = delivered.iter().find_map(|(n, v)| v.map(|v| (*n, v)));
+            if let Some((node_a, value_a)) = first {
+                for (node_b, value_b) in delivered.iter() {
+                    if let Some(value_b) = value_b {
+                        if value_a != *value_b {
+                            self.violations.push(Violation::Agreement {
+                                round,
+                                node_a: node_a.clone(),
+                                value_a: value_a.clone(),
+                                node_b: (*node_b).clone(),
+                                value_b: (*value_b).clone(),
+                            });
+                        }
+                    }
+                }
+            }
+
+            // validity: if the leader is honest, nobody should deliver anything but its input.
+            // (nodes that never deliver are caught by totality, or below if nobody delivered.)
+            // a byzantine leader can make honest nodes deliver anything, or nothing,
+            // as long as they agree.
+            for (node, value) in delivered.iter().filter(|_| honest_leader) {
+                if let Some(value) = value {
+                    if *value != input {
+                        self.violations.push(Violation::Validity {
+                            round,
+                            node: (*node).clone(),
+                            expected: input.clone(),
+                            delivered: Some((*value).clone()),
+                        });
+                    }
+                }
+            }
+
+            // totality: one delivery means everybody delivers.
+            if let Some((node_a, _)) = first {
+                for (node_b, value_b) in delivered.iter() {
+                    if value_b.is_none() {
+                        self.violations.push(Violation::Totality {
+                            round,
+                            delivered: node_a.clone(),
+                            missing: (*node_b).clone(),
+                        });
+                    }
+                }
+            } else if honest_leader {
+                // nobody delivered an honest leader's value at all.
+                for (node, _) in delivered.iter() {
+                    self.violations.push(Violation::Validity {
+                        round,
+                        node: (*node).clone(),
+                        expected: input.clone(),
+                        delivered: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Runs until the network is quiet (or `max_steps` is hit), then checks the RB properties.
+    pub fn run(mut self) -> SimReport {
+        while self.network.steps < self.config.max_steps {
+            if !self.step() {
+                break;
+            }
+        }
+
+        if !self.network.is_quiet() {
+            self.violations.push(Violation::NoQuiescence {
+                steps: self.network.steps,
+            });
+        } else {
+            self.check_quiescent_properties();
+        }
+
+        SimReport {
+            seed: self.config.seed,
+            steps: self.network.steps,
+            deliveries: self.deliveries,
+            violations: self.violations,
+            byzantine: self
+                .config
+                .byzantine
+                .iter()
+                .map(|(node, kind)| format!("{} ({kind})", node_address(*node)))
+                .collect(),
+            forgeries: self.forgeries,
+            trace: self.trace,
+            events: self.events,
+        }
+    }
+}
+
+/// Runs one simulation: the leader broadcasts one value per round, then the network runs dry.
+pub fn simulate(config: SimConfig) -> SimReport {
+    let values: Vec<String> = (0..config.rounds)
+        .map(|round| match config.values.get(round) {
+            Some(value) => value.clone(),
+            None => format!("value {round} (seed {})", config.seed),
+        })
+        .collect();
+    let mut simulator = Simulator::new(config);
+    for value in values {
+        simulator.broadcast(value);
+    }
+    simulator.run()
+}
+
+/// Runs any `NetworkProtocol` on the simulated network, with the same delays, reordering and
+/// duplication as RB's simulator (`seed`, `max_delay`, `duplicate_probability` and `schedule`
+/// from the config). Every node is honest.
+pub struct ProtocolSimulator<P: NetworkProtocol<Address = String>> {
+    protocol: P,
+    network: Network<network_protocol::Packet<String, P::Message>>,
+    addresses: Vec<String>,
+    states: Vec<P::State>,
+    max_steps: usize,
+}
+
+impl<P: NetworkProtocol<Address = String>> ProtocolSimulator<P> {
+    /// Sets up `config.nodes` nodes, named like RB's (`node_address`).
+    pub fn new(protocol: P, config: &SimConfig) -> Self {
+        let addresses: Vec<String> = (0..config.nodes).map(node_address).collect();
+        let states = addresses
+            .iter()
+            .map(|address| protocol.local_init(address))
+            .collect();
+        ProtocolSimulator {
+            protocol,
+            network: Network::new(config),
+            addresses,
+            states,
+            max_steps: config.max_steps,
+        }
+    }
+
+    fn send(&mut self, packets: Vec<network_protocol::Packet<String, P::Message>>) {
+        for packet in packets {
+            self.network.send(packet);
+        }
+    }
+
+    /// Node `node` takes an internal transition, e.g. to start a broadcast.
+    pub fn input(&mut self, node: usize, transition: P::InternalTransition) {
+        let packets = self
+            .protocol
+            .proc_internal(&mut self.states[node], transition);
+        self.send(packets);
+    }
+
+    /// Runs until the network is quiet, calling `on_delivery` with every packet before its
+    /// destination gets it. Returns false if it was still busy after `max_steps`.
+    pub fn run_with(
+        &mut self,
+        mut on_delivery: impl FnMut(&network_protocol::Packet<String, P::Message>),
+    ) -> bool {
+        while self.network.steps < self.max_steps {
+            let Some(packet) = self.network.next() else {
+                return true;
+            };
+            on_delivery(&packet);
+            let dst = self
+                .addresses
+                .iter()
+                .position(|address| *address == packet.dst)
+                .expect("packets should only be addressed to known nodes");
+            let packets = self
+                .protocol
+                .proc_message(&mut self.states[dst], packet.src, packet.msg);
+            self.send(packets);
+        }
+        self.network.is_quiet()
+    }
+
+    pub fn run(&mut self) -> bool {
+        self.run_with(|_| ())
+    }
+
+    /// Everything node `node` has output so far.
+    pub fn outputs(&self, node: usize) -> Vec<P::Output> {
+        self.protocol.outputs(&self.states[node])
+    }
+
+    pub fn steps(&self) -> usize {
+        self.network.steps
+    }
+}
+
+fn write_trace(path: &str, report: &SimReport) {
+    trace::write_trace(std::path::Path::new(path), &report.events).expect("failed to write trace");
+    println!("wrote the trace of seed {} to {path}", report.seed);
+}
+
+// cargo run -- sim [nodes] [rounds] [first seed] [number of seeds] [--byzantine <node>=<strategy>,...]
+// cargo run -- sim --replay '<config json>'
+//
+// e.g. `cargo run -- sim 7 1 0 100 --byzantine 0=equivocate,3=spam`
+//
+// with `--trace <path>`, the events of the first run that violated a property
+// (or of the last run, if none did) are written to `path`, for `cargo run -- trace` to render.
+pub fn main() {
+    let mut args: Vec<String> = std::env::args().skip(2).collect();
+    let mut trace_path = None;
+    if let Some(i) = args.iter().position(|a| a == "--trace") {
+        let path = args.get(i + 1).expect("--trace expects a path").clone();
+        trace_path = Some(path);
+        args.drain(i..i + 2);
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--replay") {
+        let json = args.get(i + 1).expect("--replay expects a config as JSON");
+        let config: SimConfig = serde_json::from_str(json).expect("invalid config");
+        let report = simulate(config);
+        print!("{report}");
+        if let Some(path) = &trace_path {
+            write_trace(path, &report);
+        }
+        // (the report only includes the trace if something went wrong)
+        if report.is_ok() {
+            for (step, packet) in report.trace.iter().enumerate() {
+                println!("  {step}: {packet}");
+            }
+        }
+        return;
+    }
+
+    let mut byzantine = BTreeMap::new();
+    if let Some(i) = args.iter().position(|a| a == "--byzantine") {
+        let spec = args
+            .get(i + 1)
+            .expect("--byzantine expects a list of <node>=<strategy>");
+        byzantine = parse_assignments(spec)
+            .expect("invalid --byzantine")
+            .into_iter()
+            .collect();
+        args.drain(i..i + 2);
+    }
+
+    let arg = |i: usize, default: u64| -> u64 {
+        args.get(i)
+            .map(|a| a.parse().expect("simulator arguments should be numbers"))
+            .unwrap_or(default)
+    };
+    let nodes = arg(0, 4) as usize;
+    let rounds = arg(1, 1) as usize;
+    let first_seed = arg(2, 0);
+    let seeds = arg(3, 1);
+
+    if let Some(node) = byzantine.keys().find(|&&node| node >= nodes) {
+        panic!("byzantine node {node} doesn't exist, there are only {nodes} nodes");
+    }
+    if 3 * byzantine.len() >= nodes {
+        println!(
+            "warning: {} of {nodes} nodes are byzantine, so RB's properties are not guaranteed",
+            byzantine.len()
+        );
+    }
+
+    let mut failures = 0;
+    let mut traced = false;
+    for seed in first_seed..first_seed + seeds {
+        let report = simulate(SimConfig {
+            nodes,
+            rounds,
+            seed,
+            byzantine: byzantine.clone(),
+            ..SimConfig::default()
+        });
+        print!("{report}");
+        if let Some(path) = &trace_path {
+            let last = seed + 1 == first_seed + seeds;
+            if !traced && (!report.is_ok() || last) {
+                write_trace(path, &report);
+                traced = true;
+            }
+        }
+        if !report.is_ok() {
+            failures += 1;
+        }
+    }
+    println!("{failures} of {seeds} runs violated an RB property");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn byzantine_kind() -> impl Strategy<Value = ByzantineKind> {
+        prop_oneof![
+            Just(ByzantineKind::Silent),
+            Just(ByzantineKind::EquivocatingLeader),
+            Just(ByzantineKind::EchoVoteSpam),
+            Just(ByzantineKind::Replay),
+        ]
+    }
+
+    // 1 to 7 nodes, fewer than a third of which are faulty. the leader may be one of them.
+    fn cluster() -> impl Strategy<Value = (usize, BTreeMap<usize, ByzantineKind>)> {
+        (1usize..=7).prop_flat_map(|nodes| {
+            let max_faulty = (nodes - 1) / 3;
+            let faulty = prop::collection::btree_map(0..nodes, byzantine_kind(), 0..=max_faulty);
+            (Just(nodes), faulty)
+        })
+    }
+
+    // scripted schedules shrink well: proptest drops and lowers choices
+    // until it finds the shortest script that still breaks something.
+    fn scripted_config() -> impl Strategy<Value = SimConfig> {
+        (
+            cluster(),
+            prop::collection::vec("[a-z ]{0,8}", 1..=2),
+            prop::collection::vec(any::<usize>(), 0..400),
+        )
+            .prop_map(|((nodes, byzantine), values, schedule)| SimConfig {
+                nodes,
+                rounds: values.len(),
+                values,
+                byzantine,
+                schedule: Some(schedule),
+                duplicate_probability: 0.0,
+                ..SimConfig::default()
+            })
+    }
+
+    fn seeded_config() -> impl Strategy<Value = SimConfig> {
+        (cluster(), 1usize..=2, any::<u64>(), 0.0..0.5).prop_map(
+            |((nodes, byzantine), rounds, seed, duplicate_probability)| SimConfig {
+                nodes,
+                rounds,
+                seed,
+                byzantine,
+                duplicate_probability,
+                ..SimConfig::default()
+            },
+        )
+    }
+
+    fn check(config: SimConfig) -> Result<(), TestCaseError> {
+        let replay = serde_json::to_string(&config).unwrap();
+        let report = simulate(config);
+        prop_assert!(
+            report.is_ok(),
+            "{report}replay with: cargo run -- sim --replay '{replay}'"
+        );
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn scripted_schedules_satisfy_rb(config in scripted_config()) {
+            check(config)?;
+        }
+
+        #[test]
+        fn delayed_and_duplicated_packets_satisfy_rb(config in seeded_config()) {
+            check(config)?;
+        }
+
+        #[test]
+        fn runs_are_deterministic(config in seeded_config()) {
+            let first = simulate(config.clone());
+            let second = simulate(config);
+            prop_assert_eq!(first.trace, second.trace);
+            prop_assert_eq!(first.deliveries, second.deliveries);
+        }
+    }
+}
@@ -0,0 +1,592 @@
+use crate::networktest::rb_protocol::{Message, Packet};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// execution traces of RB runs, and renderers that turn them into message sequence charts.
+//
+// a trace is a list of `TraceEvent`s, stored as JSON lines. the simulator records one trace
+// for the whole run (`sim --trace <path>`), while each driver node records its own
+// (`rb --trace <path>`), which the renderer merges by time.
+//
+// e.g. `cargo run -- trace mermaid node0.jsonl node1.jsonl node2.jsonl node3.jsonl`
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    // `time` is in simulator ticks for simulated runs,
+    // and in microseconds since the unix epoch for live nodes.
+    Send {
+        time: u64,
+        src: String,
+        dst: String,
+        msg: Message,
+    },
+    Receive {
+        time: u64,
+        src: String,
+        dst: String,
+        msg: Message,
+    },
+    // the node changed state, e.g. it echoed or voted for a value.
+    Transition {
+        time: u64,
+        node: String,
+        state: String,
+    },
+    Deliver {
+        time: u64,
+        node: String,
+        leader: String,
+        round: usize,
+        value: String,
+    },
+}
+
+impl TraceEvent {
+    pub fn time(&self) -> u64 {
+        match self {
+            TraceEvent::Send { time, .. }
+            | TraceEvent::Receive { time, .. }
+            | TraceEvent::Transition { time, .. }
+            | TraceEvent::Deliver { time, .. } => *time,
+        }
+    }
+
+    // the node that recorded the event
+    pub fn node(&self) -> &str {
+        match self {
+            TraceEvent::Send { src, .. } => src,
+            TraceEvent::Receive { dst, .. } => dst,
+            TraceEvent::Transition { node, .. } | TraceEvent::Deliver { node, .. } => node,
+        }
+    }
+
+    pub fn send(time: u64, packet: &Packet) -> Self {
+        TraceEvent::Send {
+            time,
+            src: packet.src.clone(),
+            dst: packet.dst.clone(),
+            msg: packet.msg.clone(),
+        }
+    }
+
+    pub fn receive(time: u64, packet: &Packet) -> Self {
+        TraceEvent::Receive {
+            time,
+            src: packet.src.clone(),
+            dst: packet.dst.clone(),
+            msg: packet.msg.clone(),
+        }
+    }
+}
+
+/// The state changes a node went through in one step, judging by the packets it sent out.
+/// (the protocol state itself lives in lean, but every transition is announced with a broadcast.)
+pub fn transitions(time: u64, node: &str, packets: &[Packet]) -> Vec<TraceEvent> {
+    let mut seen = HashSet::new();
+    packets
+        .iter()
+        .filter(|packet| seen.insert(&packet.msg))
+        .map(|packet| {
+            let state = match &packet.msg {
+                Message::InitialMsg { r, v } => format!("proposed '{v}' in round {r}"),
+                Message::EchoMsg { originator, r, v } => {
+                    format!("echoed '{v}' from {originator} in round {r}")
+                }
+                Message::VoteMsg { originator, r, v } => {
+                    format!("voted for '{v}' from {originator} in round {r}")
+                }
+            };
+            TraceEvent::Transition {
+                time,
+                node: node.to_string(),
+                state,
+            }
+        })
+        .collect()
+}
+
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+/// Appends events to a JSON lines file as they happen.
+/// Every line is flushed right away, so the trace survives the node being killed.
+pub struct TraceWriter {
+    out: LineWriter<File>,
+}
+
+impl TraceWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(TraceWriter {
+            out: LineWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, event: &TraceEvent) {
+        let line = serde_json::to_string(event).expect("trace events should serialize");
+        // losing the trace shouldn't take the node down with it.
+        if let Err(e) = writeln!(self.out, "{line}") {
+            println!("failed to write trace event: {e}");
+        }
+    }
+}
+
+pub fn write_trace(path: &Path, events: &[TraceEvent]) -> io::Result<()> {
+    let mut writer = TraceWriter::create(path)?;
+    events.iter().for_each(|event| writer.record(event));
+    Ok(())
+}
+
+pub fn read_trace(path: &Path) -> io::Result<Vec<TraceEvent>> {
+    let mut events = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Merges the traces of several nodes into one, by time.
+/// Events with the same time stay in the order they were recorded in.
+pub fn merge(traces: Vec<Vec<TraceEvent>>) -> Vec<TraceEvent> {
+    let mut events: Vec<TraceEvent> = traces.into_iter().flatten().collect();
+    events.sort_by_key(TraceEvent::time);
+    events
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mermaid,
+    PlantUml,
+    Graphviz,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mermaid" => Ok(Format::Mermaid),
+            "plantuml" => Ok(Format::PlantUml),
+            "dot" | "graphviz" => Ok(Format::Graphviz),
+            "html" => Ok(Format::Html),
+            other => Err(format!(
+                "unknown trace format: {other} (expected mermaid, plantuml, dot or html)"
+            )),
+        }
+    }
+}
+
+pub fn render(format: Format, events: &[TraceEvent]) -> String {
+    match format {
+        Format::Mermaid => render_mermaid(events),
+        Format::PlantUml => render_plantuml(events),
+        Format::Graphviz => render_graphviz(events),
+        Format::Html => render_html(events),
+    }
+}
+
+// every node in the trace, in order of first appearance, with a short id for the diagrams.
+struct Participants {
+    names: Vec<String>,
+    ids: HashMap<String, String>,
+}
+
+impl Participants {
+    fn new(events: &[TraceEvent]) -> Self {
+        let mut participants = Participants {
+            names: Vec::new(),
+            ids: HashMap::new(),
+        };
+        for event in events {
+            match event {
+                TraceEvent::Send { src, dst, .. } | TraceEvent::Receive { src, dst, .. } => {
+                    participants.add(src);
+                    participants.add(dst);
+                }
+                _ => participants.add(event.node()),
+            }
+        }
+        participants
+    }
+
+    fn add(&mut self, name: &str) {
+        if !self.ids.contains_key(name) {
+            self.ids
+                .insert(name.to_string(), format!("p{}", self.names.len()));
+            self.names.push(name.to_string());
+        }
+    }
+
+    fn id(&self, name: &str) -> &str {
+        &self.ids[name]
+    }
+
+    // peer ids are long, so label them by their last few characters, like the driver's logs do.
+    fn label(name: &str) -> &str {
+        match name.char_indices().rev().nth(5) {
+            Some((i, _)) if name.len() > 12 => &name[i..],
+            _ => name,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.names
+            .iter()
+            .map(|name| (self.ids[name].as_str(), Self::label(name)))
+    }
+}
+
+// sends that were never received (dropped, or still in flight when the trace ended)
+fn lost_sends(events: &[TraceEvent]) -> Vec<&TraceEvent> {
+    let mut received: HashMap<(&str, &str, &Message), usize> = HashMap::new();
+    for event in events {
+        if let TraceEvent::Receive { src, dst, msg, .. } = event {
+            *received
+                .entry((src.as_str(), dst.as_str(), msg))
+                .or_default() += 1;
+        }
+    }
+    events
+        .iter()
+        .filter(|event| match event {
+            TraceEvent::Send { src, dst, msg, .. } => {
+                match received.get_mut(&(src.as_str(), dst.as_str(), msg)) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        false
+                    }
+                    _ => true,
+                }
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+// characters that would end a mermaid or plantuml message early
+fn diagram_text(s: &str) -> String {
+    s.replace(['\n', '\r'], " ")
+        .replace(';', ",")
+        .replace('#', "no. ")
+}
+
+fn event_text(event: &TraceEvent) -> String {
+    match event {
+        TraceEvent::Send { dst, msg, .. } => {
+            format!("sent {msg} to {}", Participants::label(dst))
+        }
+        TraceEvent::Receive { src, msg, .. } => {
+            format!("received {msg} from {}", Participants::label(src))
+        }
+        TraceEvent::Transition { state, .. } => state.clone(),
+        TraceEvent::Deliver {
+            round,
+            value,
+            leader,
+            ..
+        } => format!(
+            "DELIVERED '{value}' from {} in round {round}",
+            Participants::label(leader)
+        ),
+    }
+}
+
+// messages are drawn as arrows when they're received. sends only show up if they were lost.
+fn render_mermaid(events: &[TraceEvent]) -> String {
+    let participants = Participants::new(events);
+    let mut out = String::from("sequenceDiagram\n");
+    for (id, label) in participants.iter() {
+        let _ = writeln!(out, "    participant {id} as {}", diagram_text(label));
+    }
+    for event in events {
+        match event {
+            TraceEvent::Send { .. } => (),
+            TraceEvent::Receive { src, dst, msg, .. } => {
+                let _ = writeln!(
+                    out,
+                    "    {}->>{}: {}",
+                    participants.id(src),
+                    participants.id(dst),
+                    diagram_text(&msg.to_string())
+                );
+            }
+            _ => {
+                let _ = writeln!(
+                    out,
+                    "    Note over {}: {}",
+                    participants.id(event.node()),
+                    diagram_text(&event_text(event))
+                );
+            }
+        }
+    }
+    for event in lost_sends(events) {
+        if let TraceEvent::Send { src, dst, msg, .. } = event {
+            let _ = writeln!(
+                out,
+                "    {}-x{}: lost: {}",
+                participants.id(src),
+                participants.id(dst),
+                diagram_text(&msg.to_string())
+            );
+        }
+    }
+    out
+}
+
+fn render_plantuml(events: &[TraceEvent]) -> String {
+    let participants = Participants::new(events);
+    let mut out = String::from("@startuml\n");
+    for (id, label) in participants.iter() {
+        let _ = writeln!(out, "participant \"{}\" as {id}", label.replace('"', "'"));
+    }
+    for event in events {
+        match event {
+            TraceEvent::Send { .. } => (),
+            TraceEvent::Receive { src, dst, msg, .. } => {
+                let _ = writeln!(
+                    out,
+                    "{} -> {} : {}",
+                    participants.id(src),
+                    participants.id(dst),
+                    diagram_text(&msg.to_string())
+                );
+            }
+            TraceEvent::Deliver { .. } => {
+                let _ = writeln!(
+                    out,
+                    "hnote over {} #palegreen : {}",
+                    participants.id(event.node()),
+                    diagram_text(&event_text(event))
+                );
+            }
+            TraceEvent::Transition { .. } => {
+                let _ = writeln!(
+                    out,
+                    "note over {} : {}",
+                    participants.id(event.node()),
+                    diagram_text(&event_text(event))
+                );
+            }
+        }
+    }
+    for event in lost_sends(events) {
+        if let TraceEvent::Send { src, dst, msg, .. } = event {
+            let _ = writeln!(
+                out,
+                "{} ->x {} : lost: {}",
+                participants.id(src),
+                participants.id(dst),
+                diagram_text(&msg.to_string())
+            );
+        }
+    }
+    out.push_str("@enduml\n");
+    out
+}
+
+fn dot_text(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// a space-time diagram: one column of events per node, with an edge from every send
+// to the receive that matches it.
+fn render_graphviz(events: &[TraceEvent]) -> String {
+    let participants = Participants::new(events);
+    let mut out =
+        String::from("digraph trace {\n    rankdir=TB;\n    node [shape=box, fontsize=10];\n");
+
+    for (id, label) in participants.iter() {
+        let _ = writeln!(out, "    subgraph cluster_{id} {{");
+        let _ = writeln!(out, "        label=\"{}\";", dot_text(label));
+        let mut previous: Option<usize> = None;
+        for (i, event) in events.iter().enumerate() {
+            if participants.id(event.node()) != id {
+                continue;
+            }
+            let style = match event {
+                TraceEvent::Deliver { .. } => ", style=filled, fillcolor=palegreen",
+                TraceEvent::Transition { .. } => ", style=rounded",
+                _ => "",
+            };
+            let _ = writeln!(
+                out,
+                "        e{i} [label=\"{}\"{style}];",
+                dot_text(&event_text(event))
+            );
+            if let Some(previous) = previous {
+                let _ = writeln!(
+                    out,
+                    "        e{previous} -> e{i} [color=grey, arrowhead=none];"
+                );
+            }
+            previous = Some(i);
+        }
+        out.push_str("    }\n");
+    }
+
+    // match every receive with the earliest send of the same packet
+    let mut unmatched: HashMap<(&str, &str, &Message), Vec<usize>> = HashMap::new();
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            TraceEvent::Send { src, dst, msg, .. } => unmatched
+                .entry((src.as_str(), dst.as_str(), msg))
+                .or_default()
+                .push(i),
+            TraceEvent::Receive { src, dst, msg, .. } => {
+                let send = unmatched
+                    .get_mut(&(src.as_str(), dst.as_str(), msg))
+                    .filter(|sends| !sends.is_empty())
+                    .map(|sends| sends.remove(0));
+                if let Some(send) = send {
+                    let _ = writeln!(out, "    e{send} -> e{i} [constraint=false, color=blue];");
+                }
+            }
+            _ => (),
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn html_text(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// a self-contained page (no scripts, no external resources): one column per node,
+// one row per event, in time order.
+fn render_html(events: &[TraceEvent]) -> String {
+    let participants = Participants::new(events);
+    let start = events.first().map_or(0, TraceEvent::time);
+
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>RB trace</title>\n<style>\n\
+         body { font-family: sans-serif; font-size: 13px; }\n\
+         table { border-collapse: collapse; }\n\
+         th, td { border: 1px solid #ddd; padding: 2px 6px; vertical-align: top; }\n\
+         th { position: sticky; top: 0; background: #f4f4f4; }\n\
+         td.time { color: #888; text-align: right; }\n\
+         .send { color: #555; }\n\
+         .receive { color: #1a4fa0; }\n\
+         .transition { font-style: italic; }\n\
+         .deliver { background: #c8f0c8; font-weight: bold; }\n\
+         </style>\n</head>\n<body>\n<table>\n<tr><th>time</th>",
+    );
+    for (_, label) in participants.iter() {
+        let _ = write!(out, "<th>{}</th>", html_text(label));
+    }
+    out.push_str("</tr>\n");
+
+    for event in events {
+        let class = match event {
+            TraceEvent::Send { .. } => "send",
+            TraceEvent::Receive { .. } => "receive",
+            TraceEvent::Transition { .. } => "transition",
+            TraceEvent::Deliver { .. } => "deliver",
+        };
+        let column = participants.id(event.node());
+        let _ = write!(
+            out,
+            "<tr><td class=\"time\">+{}</td>",
+            event.time().saturating_sub(start)
+        );
+        for (id, _) in participants.iter() {
+            if id == column {
+                let _ = write!(
+                    out,
+                    "<td class=\"{class}\">{}</td>",
+                    html_text(&event_text(event))
+                );
+            } else {
+                out.push_str("<td></td>");
+            }
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+// cargo run -- trace <mermaid|plantuml|dot|html> <trace.jsonl> [<trace.jsonl> ...]
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let [format, paths @ ..] = args.as_slice() else {
+        panic!("usage: trace <mermaid|plantuml|dot|html> <trace.jsonl> [<trace.jsonl> ...]");
+    };
+    let format: Format = format.parse().unwrap();
+    if paths.is_empty() {
+        panic!("trace expects at least one trace file");
+    }
+
+    let traces = paths
+        .iter()
+        .map(|path| read_trace(Path::new(path)))
+        .collect::<io::Result<Vec<_>>>()
+        .expect("failed to read trace");
+    print!("{}", render(format, &merge(traces)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networktest::simulator::{simulate, SimConfig};
+
+    #[test]
+    fn simulated_traces_round_trip_through_jsonl() {
+        let report = simulate(SimConfig::default());
+        let path = std::env::temp_dir().join(format!("pb-rust-trace-{}.jsonl", std::process::id()));
+        write_trace(&path, &report.events).unwrap();
+        let events = read_trace(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(events, report.events);
+        let deliveries = events
+            .iter()
+            .filter(|event| matches!(event, TraceEvent::Deliver { .. }))
+            .count();
+        assert_eq!(deliveries, report.deliveries.len());
+    }
+
+    #[test]
+    fn every_format_shows_every_node_and_delivery() {
+        let report = simulate(SimConfig::default());
+        for format in [
+            Format::Mermaid,
+            Format::PlantUml,
+            Format::Graphviz,
+            Format::Html,
+        ] {
+            let rendered = render(format, &report.events);
+            for node in 0..4 {
+                assert!(rendered.contains(&format!("node-{node}")), "{format:?}");
+            }
+            assert_eq!(
+                rendered.matches("DELIVERED").count(),
+                report.deliveries.len(),
+                "{format:?}"
+            );
+        }
+    }
+}
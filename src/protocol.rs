@@ -1,12 +1,20 @@
-// TODO: delete this, since it's unused.
 use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
 
-type Address = i32;
+// provable broadcast (PB), as in the bythos paper.
+//
+// the sender broadcasts a value (with a proof that it's allowed to send it), every node that
+// externally validates it echoes back a partial signature, and once the sender has partial
+// signatures from N - f distinct nodes, it combines them into a signature that proves the value
+// was seen by enough honest nodes. that combined signature is PB's output.
+//
+// nodes sign (sender, round, value), so an echo for one sender can't be passed off as one for
+// another. how they sign is up to the `PbParams` (e.g. with a `signature::Keyring`).
 
-//  partial signature is the same as LightSignature in the actual code.
-//  TODO: will probably have to parameterize over partial and combined signature type too.
-type PartialSignature = ();
-type CombinedSignature = ();
+// for when nodes are just numbered, e.g. in tests and benchmarks.
+// (over libp2p, nodes are addressed by their peer ids instead.)
+pub type Address = i32;
 
 // this is a proof that a Value is sent by the sender.
 // specifically, we can use the external validy function EV(value, proof, sender)
@@ -14,12 +22,13 @@ type CombinedSignature = ();
 // (note: original defn on bythos paper doesn't include sender, but assumes
 // sender's public key is known. this is probably because they use address as PK.)
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum InternalEvent<R> {
     SendAction { round: R },
 }
 
-#[derive(Clone)]
-pub enum Message<R, V, P> {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message<R, V, P, S> {
     Init {
         round: R,
         value: V,
@@ -28,63 +37,259 @@ pub enum Message<R, V, P> {
     Echo {
         round: R,
         // PartialSignature (from the paper) or LightSignature (from the source code)?
-        partial_signature: PartialSignature,
+        partial_signature: S,
     },
 }
 
-pub struct Packet<R, V, P> {
-    src: Address,
-    dst: Address,
-    msg: Message<R, V, P>,
-    received: bool,
+// `S` is the partial signature type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Packet<A, R, V, P, S> {
+    pub src: A,
+    pub dst: A,
+    pub msg: Message<R, V, P, S>,
+    pub received: bool,
 }
 
-pub struct NodeState<R, V, P> {
-    address: Address,
+// the sender's proof that N - f nodes saw `value` in `round`.
+// anyone who can check combined signatures can check it (see `ProvableBroadcast::verify_certificate`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Certificate<A, R, V, C> {
+    pub sender: A,
+    pub round: R,
+    pub value: V,
+    pub signature: C,
+}
+
+// there used to be 2 possible designs for the PB interface here:
+// 1) implement PB parameters as a struct of `fn`s, and implement PB functions as an impl of that struct
+// 2) (current impl) implement PB parameters as a trait, and make PB generic over it.
+// 1) can't hold any state, like a node's private key or whatever an application needs to validate
+// values, so we went with 2). variables (like `node_addresses` or `num_byzantine`) stay in
+// `ProvableBroadcast`, since they're the same for every application.
+
+/// The parameters of provable broadcast: what's being broadcast, and how nodes pick, validate
+/// and sign values. Implementations are per node, so they can hold the node's keys and any other
+/// state they need.
+pub trait PbParams {
+    type Round: Clone + Eq + Hash + Debug;
+    type Value: Clone + Debug;
+    type Proof: Clone + Debug;
+    type Address: Clone + Eq + Hash + Debug;
+    type PartialSignature: Clone + Eq + Hash + Debug;
+    type CombinedSignature: Clone + Eq + Hash + Debug;
+
+    /// Generates the value (and its proof) that `sender` broadcasts in `round`.
+    fn value_bft(&self, sender: &Self::Address, round: &Self::Round) -> (Self::Value, Self::Proof);
+
+    /// Whether `proof` shows that `sender` may broadcast `value` in `round`.
+    fn externally_validate(
+        &self,
+        sender: &Self::Address,
+        round: &Self::Round,
+        value: &Self::Value,
+        proof: &Self::Proof,
+    ) -> bool;
+
+    /// Our partial signature on `sender`'s `value` in `round`.
+    fn partially_sign(
+        &self,
+        sender: &Self::Address,
+        round: &Self::Round,
+        value: &Self::Value,
+    ) -> Self::PartialSignature;
+
+    /// Whether `signature` is `signer`'s partial signature on `sender`'s `value` in `round`.
+    fn partially_validate(
+        &self,
+        signer: &Self::Address,
+        sender: &Self::Address,
+        round: &Self::Round,
+        value: &Self::Value,
+        signature: &Self::PartialSignature,
+    ) -> bool;
+
+    /// Combines validated partial signatures, one per signer.
+    fn combine_partial_signatures(
+        &self,
+        signatures: Vec<(Self::Address, Self::PartialSignature)>,
+    ) -> Self::CombinedSignature;
+
+    /// Whether `signature` shows that N - f nodes signed `sender`'s `value` in `round`.
+    fn verify_combined(
+        &self,
+        sender: &Self::Address,
+        round: &Self::Round,
+        value: &Self::Value,
+        signature: &Self::CombinedSignature,
+    ) -> bool;
+}
+
+pub type PbMessage<P> = Message<
+    <P as PbParams>::Round,
+    <P as PbParams>::Value,
+    <P as PbParams>::Proof,
+    <P as PbParams>::PartialSignature,
+>;
+pub type PbPacket<P> = Packet<
+    <P as PbParams>::Address,
+    <P as PbParams>::Round,
+    <P as PbParams>::Value,
+    <P as PbParams>::Proof,
+    <P as PbParams>::PartialSignature,
+>;
+pub type PbCertificate<P> = Certificate<
+    <P as PbParams>::Address,
+    <P as PbParams>::Round,
+    <P as PbParams>::Value,
+    <P as PbParams>::CombinedSignature,
+>;
+
+pub struct NodeState<P: PbParams> {
+    address: P::Address,
     // sender state
-    // if Round in Set, then this node is the sender for that round.
-    sent: HashSet<R>,
-    // TODO: might want to use a second hash map for this (HashMap<Address, PartialSignature>)
-    counter: HashMap<R, Vec<(Address, PartialSignature)>>,
-    output: HashMap<R, CombinedSignature>,
+    // if Round is a key, then this node is the sender for that round, and sent this value and proof.
+    sent: HashMap<P::Round, (P::Value, P::Proof)>,
+    // at most one partial signature per node, so that duplicate echoes don't count twice.
+    counter: HashMap<P::Round, HashMap<P::Address, P::PartialSignature>>,
+    output: HashMap<P::Round, P::CombinedSignature>,
     // receiver state
     // (Address, Round) means we echoed a message from Address at R.
-    echoed: HashMap<(Address, R), (V, P)>,
+    echoed: HashMap<(P::Address, P::Round), (P::Value, P::Proof)>,
+}
+
+// (not derived, since that would require the params themselves to be `Clone` and `Debug`.)
+impl<P: PbParams> Clone for NodeState<P> {
+    fn clone(&self) -> Self {
+        NodeState {
+            address: self.address.clone(),
+            sent: self.sent.clone(),
+            counter: self.counter.clone(),
+            output: self.output.clone(),
+            echoed: self.echoed.clone(),
+        }
+    }
+}
+
+impl<P: PbParams> Debug for NodeState<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeState")
+            .field("address", &self.address)
+            .field("sent", &self.sent)
+            .field("counter", &self.counter)
+            .field("output", &self.output)
+            .field("echoed", &self.echoed)
+            .finish()
+    }
+}
+
+impl<P: PbParams> NodeState<P> {
+    /// The initial state of the node at `address`: it hasn't sent or echoed anything yet.
+    pub fn new(address: P::Address) -> Self {
+        NodeState {
+            address,
+            sent: HashMap::new(),
+            counter: HashMap::new(),
+            output: HashMap::new(),
+            echoed: HashMap::new(),
+        }
+    }
+
+    pub fn address(&self) -> P::Address {
+        self.address.clone()
+    }
+
+    /// The combined signature for our broadcast in `round`, once we have one.
+    pub fn output(&self, round: &P::Round) -> Option<&P::CombinedSignature> {
+        self.output.get(round)
+    }
+
+    pub fn has_output(&self, round: &P::Round) -> bool {
+        self.output.contains_key(round)
+    }
+
+    /// Every round we've finished broadcasting in, with its combined signature.
+    pub fn outputs(&self) -> impl Iterator<Item = (&P::Round, &P::CombinedSignature)> {
+        self.output.iter()
+    }
+
+    /// Our output for `round`, along with what it certifies.
+    pub fn certificate(&self, round: &P::Round) -> Option<PbCertificate<P>> {
+        let signature = self.output.get(round)?;
+        let (value, _) = self.sent.get(round)?;
+        Some(Certificate {
+            sender: self.address.clone(),
+            round: round.clone(),
+            value: value.clone(),
+            signature: signature.clone(),
+        })
+    }
+
+    /// The value (and proof) we broadcast in `round`, if we're its sender.
+    pub fn sent(&self, round: &P::Round) -> Option<&(P::Value, P::Proof)> {
+        self.sent.get(round)
+    }
+
+    /// The value (and proof) we echoed for `sender` in `round`, if any.
+    pub fn echoed(&self, sender: P::Address, round: &P::Round) -> Option<&(P::Value, P::Proof)> {
+        self.echoed.get(&(sender, round.clone()))
+    }
+
+    /// The nodes whose partial signatures we've collected for our broadcast in `round`.
+    pub fn signers(&self, round: &P::Round) -> HashSet<P::Address> {
+        self.counter
+            .get(round)
+            .map(|signatures| signatures.keys().cloned().collect())
+            .unwrap_or_default()
+    }
 }
 
-// aside: there are 2 possible designs for the PB interface here.
-// 1) (current impl) implement PB parameters as a struct, and implement PB functions as an impl of that struct
-// 2) implement PB parameters as a trait, and implement PB functions as normal functions that take in some
-//    `ProvableBroadcast<R,V,P>` instead of `self`.
-//    note that this impl is slightly awkward because you can't have variables (like `node_addresses` or
-//    `num_byzantine`) in traits, so we would need to store those as getter functions.`
-// i haven't referenced the API design in other protocol crates yet, but i'll take a look soon.
-
-// The protocol is parameterized over the round type R, value type V, and proof type P.
-pub struct ProvableBroadcast<R, V, P> {
-    node_addresses: Vec<Address>,
+// The protocol is parameterized over the application's `PbParams`.
+pub struct ProvableBroadcast<P: PbParams> {
+    params: P,
+    node_addresses: Vec<P::Address>,
     num_byzantine: usize,
-    // generates some form of value and proof
-    value_bft: fn(&Address, &R) -> (V, P),
-    // validate a partial signature
-    externally_validate: fn(&R, &V, &P) -> bool,
-    // TODO: need to add in a parameter for the private key, probably.
-    partially_sign: fn(&Address, &R, &V) -> PartialSignature,
-    partially_validate: fn(&Address, &R, &V, &PartialSignature) -> bool,
-    combine_partial_signatures: fn(Vec<&PartialSignature>) -> CombinedSignature,
 }
 
-impl<R, V, P> ProvableBroadcast<R, V, P>
-where
-    R: Clone + Eq + std::hash::Hash,
-    V: Clone,
-    P: Clone,
-{
-    fn signature_threshold(&self) -> usize {
+impl<P: PbParams> ProvableBroadcast<P> {
+    /// PB among `node_addresses`, tolerating up to `num_byzantine` faulty nodes.
+    /// Panics unless `num_byzantine` is less than a third of the nodes.
+    pub fn new(params: P, node_addresses: Vec<P::Address>, num_byzantine: usize) -> Self {
+        assert!(
+            3 * num_byzantine < node_addresses.len(),
+            "PB needs N > 3f, but N = {} and f = {num_byzantine}",
+            node_addresses.len()
+        );
+        ProvableBroadcast {
+            params,
+            node_addresses,
+            num_byzantine,
+        }
+    }
+
+    pub fn params(&self) -> &P {
+        &self.params
+    }
+
+    pub fn node_addresses(&self) -> &[P::Address] {
+        &self.node_addresses
+    }
+
+    pub fn signature_threshold(&self) -> usize {
         self.node_addresses.len() - self.num_byzantine
     }
 
-    fn make_packet(src: &Address, dst: &Address, msg: &Message<R, V, P>) -> Packet<R, V, P> {
+    /// Checks a certificate with the params' `verify_combined`.
+    /// This doesn't need any node's state.
+    pub fn verify_certificate(&self, certificate: &PbCertificate<P>) -> bool {
+        self.params.verify_combined(
+            &certificate.sender,
+            &certificate.round,
+            &certificate.value,
+            &certificate.signature,
+        )
+    }
+
+    fn make_packet(src: &P::Address, dst: &P::Address, msg: &PbMessage<P>) -> PbPacket<P> {
         Packet {
             src: src.clone(),
             dst: dst.clone(),
@@ -94,59 +299,77 @@ where
     }
     // broadcasts a message to all nodes.
     // filbs: for simplicity, will just clone everything first.
-    fn broadcast(&self, src: &Address, msg: Message<R, V, P>) -> Vec<Packet<R, V, P>> {
+    fn broadcast(&self, src: &P::Address, msg: PbMessage<P>) -> Vec<PbPacket<P>> {
         self.node_addresses
             .iter()
-            .map(|dst| ProvableBroadcast::<R, V, P>::make_packet(src, dst, &msg))
+            .map(|dst| Self::make_packet(src, dst, &msg))
             .collect()
     }
 
+    /// Delivers a packet addressed to `st`'s node. Returns the packets it sends in response.
+    pub fn handle_packet(&self, st: &mut NodeState<P>, packet: PbPacket<P>) -> Vec<PbPacket<P>> {
+        assert_eq!(packet.dst, st.address, "packet delivered to the wrong node");
+        self.proc_msg(st, packet.src, packet.msg)
+    }
+
     // process internal events.
     // this function marks a node as the sender of a given message, and makes it
     // send the Init message to other nodes.
     // (i think the Coq implementation returns NodeState because its functional.
     // but since rust isn't, we can just update the hashmaps/sets directly.)
-    fn proc_int(
+    pub fn proc_int(
         &self,
-        st: &mut NodeState<R, V, P>,
-        internal_event: InternalEvent<R>,
-    ) -> Vec<Packet<R, V, P>> {
-        let NodeState {
-            address: id,
-            sent,
-            counter,
-            output,
-            echoed,
-        } = st;
+        st: &mut NodeState<P>,
+        internal_event: InternalEvent<P::Round>,
+    ) -> Vec<PbPacket<P>> {
         match internal_event {
-            InternalEvent::SendAction { round: r } => match sent.get(&r) {
-                // this node has already initiated this broadcast.
-                Some(_) => {
-                    // no further packets need to be sent.
-                    Vec::new()
+            InternalEvent::SendAction { round: r } => {
+                // this node has already initiated this broadcast: no further packets need to be sent.
+                if st.sent.contains_key(&r) {
+                    return Vec::new();
                 }
-                // this node has not yet initiated this broadcast.
-                None => {
-                    // mark this node as the sender for this round
-                    sent.insert(r.clone());
-                    let (v, p) = (self.value_bft)(id, &r);
-                    let init_msg = Message::Init {
-                        round: r,
-                        value: v,
-                        proof: p,
-                    };
-                    self.broadcast(id, init_msg)
-                }
-            },
+                let (v, p) = self.params.value_bft(&st.address, &r);
+                self.send_value(st, r, v, p)
+            }
         }
     }
 
-    fn proc_msg(
+    /// Starts our broadcast in `round` with a value (and proof) that the application already has,
+    /// instead of asking `value_bft` for one. Does nothing if we've already broadcast in `round`.
+    pub fn send_value(
         &self,
-        st: &mut NodeState<R, V, P>,
-        src: Address,
-        msg: Message<R, V, P>,
-    ) -> Vec<Packet<R, V, P>> {
+        st: &mut NodeState<P>,
+        round: P::Round,
+        value: P::Value,
+        proof: P::Proof,
+    ) -> Vec<PbPacket<P>> {
+        let NodeState {
+            address: id, sent, ..
+        } = st;
+        if sent.contains_key(&round) {
+            return Vec::new();
+        }
+        // mark this node as the sender for this round
+        sent.insert(round.clone(), (value.clone(), proof.clone()));
+        let init_msg = Message::Init {
+            round,
+            value,
+            proof,
+        };
+        self.broadcast(id, init_msg)
+    }
+
+    pub fn proc_msg(
+        &self,
+        st: &mut NodeState<P>,
+        src: P::Address,
+        msg: PbMessage<P>,
+    ) -> Vec<PbPacket<P>> {
+        // only members take part in the protocol.
+        if !self.node_addresses.contains(&src) {
+            return Vec::new();
+        }
+
         let NodeState {
             address,
             sent,
@@ -155,38 +378,42 @@ where
             echoed,
         } = st;
         match msg {
+            // the sender echoes its own Init too, like every other node.
             Message::Init {
                 round,
                 value,
                 proof,
-            } => match (sent.get(&round), echoed.get(&(*address, round.clone()))) {
-                (None, None) => {
-                    // we are not the sender, AND we haven't echoed this message before.
+            } => match echoed.get(&(src.clone(), round.clone())) {
+                None => {
+                    // we haven't echoed a message from this sender in this round before.
                     // so, we should echo this message.
 
                     // validate sender's signature
-                    match (self.externally_validate)(&round, &value, &proof) {
+                    match self
+                        .params
+                        .externally_validate(&src, &round, &value, &proof)
+                    {
                         true => {
                             // generate partial signature
-                            let partial_signature = (self.partially_sign)(&address, &round, &value);
+                            let partial_signature =
+                                self.params.partially_sign(&src, &round, &value);
 
                             // update `echoed` map
-                            echoed.insert((src, round.clone()), (value, proof));
+                            echoed.insert((src.clone(), round.clone()), (value, proof));
 
                             // construct message and convert to packets
-                            let msg = Message::Echo::<R, V, P> {
+                            let msg = Message::Echo {
                                 round,
                                 partial_signature,
                             };
-                            let packet =
-                                ProvableBroadcast::<R, V, P>::make_packet(&address, &src, &msg);
+                            let packet = Self::make_packet(address, &src, &msg);
                             vec![packet]
                         }
                         // could not validate sender's signature: don't echo.
                         false => Vec::new(),
                     }
                 }
-                _ => {
+                Some(_) => {
                     // do not echo this message
                     Vec::new()
                 }
@@ -197,50 +424,329 @@ where
                 round,
                 partial_signature,
             } => match (sent.get(&round), output.get(&round)) {
-                // is the sender AND we haven't made a partial signature yet.
-                (Some(_), None) => {
-                    let (value, _) = (self.value_bft)(&address, &round);
-                    match (self.partially_validate)(&address, &round, &value, &partial_signature) {
-                        true => {
-                            let partial_signatures =
-                                counter.entry(round.clone()).or_insert(Vec::new());
-                            match partial_signatures.contains(&(src, partial_signature)) {
-                                false => {
-                                    partial_signatures.push((src, partial_signature));
-
-                                    // (this section is an inlined version of `routine_check`.)
-                                    // check if we should combine signatures
-                                    let has_exactly_enough_signatures =
-                                        partial_signatures.len() == self.signature_threshold();
-                                    let has_combined_signature = output.contains_key(&round);
-
-                                    // combine signatures, if possible
-                                    if has_exactly_enough_signatures && !has_combined_signature {
-                                        let partial_signatures_only =
-                                            partial_signatures.iter().map(|(_, x)| x).collect();
-                                        let combined_signature = (self.combine_partial_signatures)(
-                                            partial_signatures_only,
-                                        );
-                                        output.insert(round, combined_signature);
-                                    }
-
-                                    // no packets to output
-                                    Vec::new()
-                                }
-
-                                // we've already recorded this signature. no further action needed
-                                true => Vec::new(),
-                            }
-                        }
+                // is the sender AND we haven't made a combined signature yet.
+                (Some((value, _)), None) => {
+                    // the echo has to be signed by whoever sent it.
+                    if !self.params.partially_validate(
+                        &src,
+                        address,
+                        &round,
+                        value,
+                        &partial_signature,
+                    ) {
                         // validation failed: don't update signatures.
-                        false => Vec::new(),
+                        return Vec::new();
+                    }
+
+                    let partial_signatures = counter.entry(round.clone()).or_default();
+                    if partial_signatures.contains_key(&src) {
+                        // we've already recorded a signature from this node. no further action needed
+                        return Vec::new();
+                    }
+                    partial_signatures.insert(src, partial_signature);
+
+                    // (this section is an inlined version of `routine_check`.)
+                    // combine signatures, once we have enough of them
+                    if partial_signatures.len() >= self.signature_threshold() {
+                        let combined_signature = self.params.combine_partial_signatures(
+                            partial_signatures
+                                .iter()
+                                .map(|(signer, signature)| (signer.clone(), signature.clone()))
+                                .collect(),
+                        );
+                        output.insert(round, combined_signature);
                     }
+
+                    // no packets to output
+                    Vec::new()
                 }
 
-                // either NOT the sender, or is the sender but we've already created the partial signature.
+                // either NOT the sender, or is the sender but we've already created the combined signature.
                 // do nothing.
                 _ => Vec::new(),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::{Committee, Ed25519Multisig, InsecureScheme, Keyring, SignatureScheme};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::cell::RefCell;
+
+    // a node's params: its keys, and the values it refuses to echo.
+    // the refused values can change between calls, like an application's validation context would.
+    struct TestParams<S: SignatureScheme> {
+        keyring: Keyring<S, Address>,
+        refused: RefCell<HashSet<String>>,
+    }
+
+    fn signing_bytes(sender: &Address, round: &u32, value: &str) -> Vec<u8> {
+        let mut bytes = sender.to_le_bytes().to_vec();
+        bytes.extend(round.to_le_bytes());
+        bytes.extend(value.as_bytes());
+        bytes
+    }
+
+    impl<S: SignatureScheme> PbParams for TestParams<S> {
+        type Round = u32;
+        type Value = String;
+        type Proof = ();
+        type Address = Address;
+        type PartialSignature = S::PartialSignature;
+        type CombinedSignature = S::CombinedSignature<Address>;
+
+        fn value_bft(&self, sender: &Address, round: &u32) -> (String, ()) {
+            (format!("{sender} in {round}"), ())
+        }
+
+        fn externally_validate(&self, _: &Address, _: &u32, value: &String, _: &()) -> bool {
+            !self.refused.borrow().contains(value)
+        }
+
+        fn partially_sign(
+            &self,
+            sender: &Address,
+            round: &u32,
+            value: &String,
+        ) -> S::PartialSignature {
+            self.keyring.sign(&signing_bytes(sender, round, value))
+        }
+
+        fn partially_validate(
+            &self,
+            signer: &Address,
+            sender: &Address,
+            round: &u32,
+            value: &String,
+            signature: &S::PartialSignature,
+        ) -> bool {
+            let message = signing_bytes(sender, round, value);
+            self.keyring
+                .committee()
+                .verify_partial(signer, &message, signature)
+        }
+
+        fn combine_partial_signatures(
+            &self,
+            signatures: Vec<(Address, S::PartialSignature)>,
+        ) -> S::CombinedSignature<Address> {
+            S::combine(signatures)
+        }
+
+        fn verify_combined(
+            &self,
+            sender: &Address,
+            round: &u32,
+            value: &String,
+            signature: &S::CombinedSignature<Address>,
+        ) -> bool {
+            let message = signing_bytes(sender, round, value);
+            self.keyring
+                .committee()
+                .verify_combined(&message, signature)
+        }
+    }
+
+    type Node<S> = (ProvableBroadcast<TestParams<S>>, NodeState<TestParams<S>>);
+
+    // 4 nodes, tolerating 1 byzantine one, so the sender needs 3 echoes.
+    fn setup<S: SignatureScheme>() -> (Committee<S, Address>, Vec<Node<S>>) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let keypairs: Vec<_> = (0..4).map(|_| S::generate_keypair(&mut rng)).collect();
+        let public_keys = keypairs
+            .iter()
+            .enumerate()
+            .map(|(address, (_, public_key))| (address as Address, public_key.clone()))
+            .collect();
+        let committee = Committee::new(public_keys, 3);
+        let nodes = keypairs
+            .into_iter()
+            .enumerate()
+            .map(|(address, (secret_key, _))| {
+                let params = TestParams {
+                    keyring: Keyring::new(secret_key, committee.clone()),
+                    refused: RefCell::new(HashSet::new()),
+                };
+                let pb = ProvableBroadcast::new(params, vec![0, 1, 2, 3], 1);
+                (pb, NodeState::new(address as Address))
+            })
+            .collect();
+        (committee, nodes)
+    }
+
+    fn deliver<S: SignatureScheme>(
+        nodes: &mut [Node<S>],
+        packet: PbPacket<TestParams<S>>,
+    ) -> Vec<PbPacket<TestParams<S>>> {
+        let (pb, st) = &mut nodes[packet.dst as usize];
+        pb.handle_packet(st, packet)
+    }
+
+    // starts node 0's broadcast in `round`, and returns every node's echo to it.
+    fn echoes<S: SignatureScheme>(
+        nodes: &mut [Node<S>],
+        round: u32,
+    ) -> Vec<PbPacket<TestParams<S>>> {
+        let (pb, st) = &mut nodes[0];
+        let inits = pb.proc_int(st, InternalEvent::SendAction { round });
+        inits
+            .into_iter()
+            .flat_map(|init| deliver(nodes, init))
+            .collect()
+    }
+
+    #[test]
+    fn sender_outputs_once_every_node_has_echoed() {
+        let (_, mut nodes) = setup::<InsecureScheme>();
+
+        let echoes = echoes(&mut nodes, 7);
+        assert_eq!(echoes.len(), 4);
+        assert!(echoes.iter().all(|echo| echo.dst == 0));
+        assert_eq!(
+            nodes[2].1.echoed(0, &7),
+            Some(&(String::from("0 in 7"), ()))
+        );
+
+        for echo in echoes {
+            assert!(deliver(&mut nodes, echo).is_empty());
+        }
+        assert!(nodes[0].1.has_output(&7));
+        assert_eq!(nodes[0].1.outputs().count(), 1);
+        assert!(!nodes[1].1.has_output(&7));
+    }
+
+    #[test]
+    fn output_needs_exactly_the_echo_threshold() {
+        let (_, mut nodes) = setup::<InsecureScheme>();
+        let mut echoes = echoes(&mut nodes, 0).into_iter().skip(1);
+
+        deliver(&mut nodes, echoes.next().unwrap());
+        deliver(&mut nodes, echoes.next().unwrap());
+        assert!(!nodes[0].1.has_output(&0));
+
+        deliver(&mut nodes, echoes.next().unwrap());
+        assert!(nodes[0].1.has_output(&0));
+        assert_eq!(nodes[0].1.signers(&0), HashSet::from([1, 2, 3]));
+    }
+
+    #[test]
+    fn duplicate_echoes_count_once() {
+        let (_, mut nodes) = setup::<InsecureScheme>();
+        let echoes = echoes(&mut nodes, 0);
+
+        for _ in 0..3 {
+            deliver(&mut nodes, echoes[1].clone());
+            deliver(&mut nodes, echoes[2].clone());
+        }
+        assert!(!nodes[0].1.has_output(&0));
+        assert_eq!(nodes[0].1.signers(&0), HashSet::from([1, 2]));
+    }
+
+    #[test]
+    fn application_values_are_sent_once_per_round() {
+        let (_, mut nodes) = setup::<InsecureScheme>();
+        let (pb, st) = &mut nodes[0];
+
+        let inits = pb.send_value(st, 0, String::from("mine"), ());
+        assert_eq!(inits.len(), 4);
+        assert_eq!(st.sent(&0), Some(&(String::from("mine"), ())));
+        assert!(pb.send_value(st, 0, String::from("other"), ()).is_empty());
+        assert!(pb
+            .proc_int(st, InternalEvent::SendAction { round: 0 })
+            .is_empty());
+    }
+
+    #[test]
+    fn duplicate_inits_are_echoed_once() {
+        let (_, mut nodes) = setup::<InsecureScheme>();
+        let init = Packet {
+            src: 0,
+            dst: 1,
+            msg: Message::Init {
+                round: 0,
+                value: String::from("v"),
+                proof: (),
+            },
+            received: false,
+        };
+
+        assert_eq!(deliver(&mut nodes, init.clone()).len(), 1);
+        assert!(deliver(&mut nodes, init).is_empty());
+    }
+
+    #[test]
+    fn nodes_only_echo_values_they_validate() {
+        let (_, mut nodes) = setup::<InsecureScheme>();
+        // nodes 1 and 2 refuse round 0's value, but not round 1's.
+        for (pb, _) in &nodes[1..3] {
+            pb.params()
+                .refused
+                .borrow_mut()
+                .insert(String::from("0 in 0"));
+        }
+
+        assert_eq!(echoes(&mut nodes, 0).len(), 2);
+        assert_eq!(echoes(&mut nodes, 1).len(), 4);
+
+        // and starting the same round again does nothing.
+        let (pb, st) = &mut nodes[0];
+        assert!(pb
+            .proc_int(st, InternalEvent::SendAction { round: 0 })
+            .is_empty());
+    }
+
+    #[test]
+    fn ignores_echoes_from_non_members_and_with_bad_signatures() {
+        let (_, mut nodes) = setup::<InsecureScheme>();
+        let echoes = echoes(&mut nodes, 0);
+
+        // node 1's echo, claiming to come from node 2 (which it didn't sign for),
+        // or from someone who isn't a member at all.
+        for src in [2, 4] {
+            let mut forged = echoes[1].clone();
+            forged.src = src;
+            deliver(&mut nodes, forged);
+        }
+        assert!(nodes[0].1.signers(&0).is_empty());
+    }
+
+    #[test]
+    fn third_parties_can_check_certificates() {
+        let (committee, mut nodes) = setup::<Ed25519Multisig>();
+        for echo in echoes(&mut nodes, 3) {
+            deliver(&mut nodes, echo);
+        }
+        let certificate = nodes[0]
+            .1
+            .certificate(&3)
+            .expect("node 0 should have an output");
+        assert!(nodes[0].0.verify_certificate(&certificate));
+
+        // someone who only knows the committee can check it too.
+        let check = |certificate: &PbCertificate<TestParams<Ed25519Multisig>>| {
+            let message =
+                signing_bytes(&certificate.sender, &certificate.round, &certificate.value);
+            committee.verify_combined(&message, &certificate.signature)
+        };
+        assert!(check(&certificate));
+
+        // the certificate is for this exact sender, round and value.
+        let mut other_value = certificate.clone();
+        other_value.value = String::from("something else");
+        assert!(!check(&other_value));
+        let mut other_sender = certificate.clone();
+        other_sender.sender = 1;
+        assert!(!check(&other_sender));
+
+        // and it needs N - f signatures.
+        let mut too_few = certificate;
+        while too_few.signature.len() >= committee.threshold() {
+            too_few.signature.pop_first();
+        }
+        assert!(!check(&too_few));
+    }
+}
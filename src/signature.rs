@@ -0,0 +1,355 @@
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+// signature schemes for provable broadcast.
+//
+// every node echoes a partial signature on the sender's value, and the sender combines N - f of
+// them into a combined signature. anyone who knows the members' public keys can then check the
+// combined signature, without having taken part in the protocol.
+//
+// the schemes here are stateless: keys are passed in explicitly.
+// they don't care how members are named, so signers are whatever the application addresses
+// its nodes with (e.g. `protocol::Address`, or libp2p peer ids).
+
+/// What a signer can be named by.
+pub trait SignerId: Ord + Hash + Clone + Debug {}
+
+impl<A: Ord + Hash + Clone + Debug> SignerId for A {}
+
+pub trait SignatureScheme {
+    type PublicKey: Clone + Debug;
+    type SecretKey: Clone + Debug;
+    type PartialSignature: Clone + Debug + Eq + Hash;
+    type CombinedSignature<A: SignerId>: Clone + Debug + Eq + Hash;
+
+    fn generate_keypair<G: RngCore + CryptoRng>(rng: &mut G) -> (Self::SecretKey, Self::PublicKey);
+
+    fn partially_sign(secret_key: &Self::SecretKey, message: &[u8]) -> Self::PartialSignature;
+
+    fn verify_partial(
+        public_key: &Self::PublicKey,
+        message: &[u8],
+        signature: &Self::PartialSignature,
+    ) -> bool;
+
+    /// Combines partial signatures that have already been verified, one per signer.
+    fn combine<A: SignerId>(
+        signatures: Vec<(A, Self::PartialSignature)>,
+    ) -> Self::CombinedSignature<A>;
+
+    /// Whether `signature` holds valid partial signatures on `message` from at least `threshold`
+    /// of the nodes in `public_keys`.
+    fn verify_combined<A: SignerId>(
+        public_keys: &HashMap<A, Self::PublicKey>,
+        threshold: usize,
+        message: &[u8],
+        signature: &Self::CombinedSignature<A>,
+    ) -> bool;
+}
+
+// the members' public keys, and how many of them a combined signature needs.
+// that's everything a third party needs to check a combined signature.
+pub struct Committee<S: SignatureScheme, A: SignerId> {
+    public_keys: HashMap<A, S::PublicKey>,
+    threshold: usize,
+}
+
+// (not derived, since that would require the scheme itself to be `Clone`.)
+impl<S: SignatureScheme, A: SignerId> Clone for Committee<S, A> {
+    fn clone(&self) -> Self {
+        Committee {
+            public_keys: self.public_keys.clone(),
+            threshold: self.threshold,
+        }
+    }
+}
+
+impl<S: SignatureScheme, A: SignerId> Committee<S, A> {
+    pub fn new(public_keys: HashMap<A, S::PublicKey>, threshold: usize) -> Self {
+        Committee {
+            public_keys,
+            threshold,
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn is_member(&self, address: &A) -> bool {
+        self.public_keys.contains_key(address)
+    }
+
+    /// Whether `signer` is a member, and `signature` is its signature on `message`.
+    pub fn verify_partial(
+        &self,
+        signer: &A,
+        message: &[u8],
+        signature: &S::PartialSignature,
+    ) -> bool {
+        self.public_keys
+            .get(signer)
+            .is_some_and(|public_key| S::verify_partial(public_key, message, signature))
+    }
+
+    pub fn verify_combined(&self, message: &[u8], signature: &S::CombinedSignature<A>) -> bool {
+        S::verify_combined(&self.public_keys, self.threshold, message, signature)
+    }
+}
+
+// a member's own secret key, along with the committee it signs for.
+pub struct Keyring<S: SignatureScheme, A: SignerId> {
+    secret_key: S::SecretKey,
+    committee: Committee<S, A>,
+}
+
+impl<S: SignatureScheme, A: SignerId> Keyring<S, A> {
+    pub fn new(secret_key: S::SecretKey, committee: Committee<S, A>) -> Self {
+        Keyring {
+            secret_key,
+            committee,
+        }
+    }
+
+    pub fn committee(&self) -> &Committee<S, A> {
+        &self.committee
+    }
+
+    pub fn sign(&self, message: &[u8]) -> S::PartialSignature {
+        S::partially_sign(&self.secret_key, message)
+    }
+}
+
+// ed25519 multi-signature, as a plain set of signatures: the combined signature is every partial
+// signature, keyed by its signer. it grows linearly with N, but needs no setup beyond each node
+// publishing its key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Ed25519Multisig;
+
+impl SignatureScheme for Ed25519Multisig {
+    type PublicKey = VerifyingKey;
+    type SecretKey = SigningKey;
+    // the raw signature bytes, which (unlike `ed25519_dalek::Signature`) we can hash.
+    type PartialSignature = [u8; 64];
+    type CombinedSignature<A: SignerId> = BTreeMap<A, [u8; 64]>;
+
+    fn generate_keypair<G: RngCore + CryptoRng>(rng: &mut G) -> (SigningKey, VerifyingKey) {
+        let secret_key = SigningKey::generate(rng);
+        let public_key = secret_key.verifying_key();
+        (secret_key, public_key)
+    }
+
+    fn partially_sign(secret_key: &SigningKey, message: &[u8]) -> [u8; 64] {
+        secret_key.sign(message).to_bytes()
+    }
+
+    fn verify_partial(public_key: &VerifyingKey, message: &[u8], signature: &[u8; 64]) -> bool {
+        let signature = ed25519_dalek::Signature::from_bytes(signature);
+        public_key.verify(message, &signature).is_ok()
+    }
+
+    fn combine<A: SignerId>(signatures: Vec<(A, [u8; 64])>) -> BTreeMap<A, [u8; 64]> {
+        signatures.into_iter().collect()
+    }
+
+    fn verify_combined<A: SignerId>(
+        public_keys: &HashMap<A, VerifyingKey>,
+        threshold: usize,
+        message: &[u8],
+        signature: &BTreeMap<A, [u8; 64]>,
+    ) -> bool {
+        // signatures from outside the membership don't make the combined signature invalid,
+        // they just don't count.
+        let valid = signature
+            .iter()
+            .filter(|(signer, partial)| {
+                public_keys
+                    .get(signer)
+                    .is_some_and(|public_key| Self::verify_partial(public_key, message, partial))
+            })
+            .count();
+        valid >= threshold
+    }
+}
+
+// for tests only: the "secret" key is the public key, so anyone can forge anyone's signature.
+// it's just cheap, and deterministic.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct InsecureScheme;
+
+#[cfg(test)]
+impl InsecureScheme {
+    fn digest(key: u64, message: &[u8]) -> u64 {
+        use std::hash::Hasher;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        key.hash(&mut hasher);
+        message.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+impl SignatureScheme for InsecureScheme {
+    type PublicKey = u64;
+    type SecretKey = u64;
+    type PartialSignature = u64;
+    type CombinedSignature<A: SignerId> = BTreeMap<A, u64>;
+
+    fn generate_keypair<G: RngCore + CryptoRng>(rng: &mut G) -> (u64, u64) {
+        let key = rng.next_u64();
+        (key, key)
+    }
+
+    fn partially_sign(secret_key: &u64, message: &[u8]) -> u64 {
+        Self::digest(*secret_key, message)
+    }
+
+    fn verify_partial(public_key: &u64, message: &[u8], signature: &u64) -> bool {
+        Self::digest(*public_key, message) == *signature
+    }
+
+    fn combine<A: SignerId>(signatures: Vec<(A, u64)>) -> BTreeMap<A, u64> {
+        signatures.into_iter().collect()
+    }
+
+    fn verify_combined<A: SignerId>(
+        public_keys: &HashMap<A, u64>,
+        threshold: usize,
+        message: &[u8],
+        signature: &BTreeMap<A, u64>,
+    ) -> bool {
+        let valid = signature
+            .iter()
+            .filter(|(signer, partial)| {
+                public_keys
+                    .get(signer)
+                    .is_some_and(|public_key| Self::verify_partial(public_key, message, partial))
+            })
+            .count();
+        valid >= threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn committee(n: usize) -> (Vec<SigningKey>, HashMap<usize, VerifyingKey>) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let keypairs: Vec<_> = (0..n)
+            .map(|_| Ed25519Multisig::generate_keypair(&mut rng))
+            .collect();
+        let public_keys = (0..n).zip(keypairs.iter().map(|(_, pk)| *pk)).collect();
+        let secret_keys = keypairs.into_iter().map(|(sk, _)| sk).collect();
+        (secret_keys, public_keys)
+    }
+
+    fn combined(
+        secret_keys: &[SigningKey],
+        signers: &[usize],
+        message: &[u8],
+    ) -> BTreeMap<usize, [u8; 64]> {
+        Ed25519Multisig::combine(
+            signers
+                .iter()
+                .map(|&signer| {
+                    let secret_key = &secret_keys[signer];
+                    (signer, Ed25519Multisig::partially_sign(secret_key, message))
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn ed25519_partial_signatures_only_verify_for_their_signer_and_message() {
+        let (secret_keys, public_keys) = committee(2);
+        let signature = Ed25519Multisig::partially_sign(&secret_keys[0], b"value");
+        assert!(Ed25519Multisig::verify_partial(
+            &public_keys[&0],
+            b"value",
+            &signature
+        ));
+        assert!(!Ed25519Multisig::verify_partial(
+            &public_keys[&1],
+            b"value",
+            &signature
+        ));
+        assert!(!Ed25519Multisig::verify_partial(
+            &public_keys[&0],
+            b"other",
+            &signature
+        ));
+    }
+
+    #[test]
+    fn ed25519_combined_signatures_need_threshold_valid_signers() {
+        let (secret_keys, public_keys) = committee(4);
+        let message = b"value";
+
+        let enough = combined(&secret_keys, &[0, 1, 3], message);
+        assert!(Ed25519Multisig::verify_combined(
+            &public_keys,
+            3,
+            message,
+            &enough
+        ));
+        assert!(!Ed25519Multisig::verify_combined(
+            &public_keys,
+            3,
+            b"other",
+            &enough
+        ));
+
+        let too_few = combined(&secret_keys, &[0, 1], message);
+        assert!(!Ed25519Multisig::verify_combined(
+            &public_keys,
+            3,
+            message,
+            &too_few
+        ));
+
+        // node 1's key signing in node 2's name doesn't count for node 2.
+        let mut forged = too_few.clone();
+        forged.insert(2, Ed25519Multisig::partially_sign(&secret_keys[1], message));
+        assert!(!Ed25519Multisig::verify_combined(
+            &public_keys,
+            3,
+            message,
+            &forged
+        ));
+    }
+
+    #[test]
+    fn signers_can_be_named_by_anything() {
+        let (secret_keys, public_keys) = committee(2);
+        let names = ["alice", "bob"].map(String::from);
+        let committee: Committee<Ed25519Multisig, String> = Committee::new(
+            names
+                .iter()
+                .cloned()
+                .zip((0..2).map(|i| public_keys[&i]))
+                .collect(),
+            2,
+        );
+        let signature = Ed25519Multisig::combine(
+            names
+                .iter()
+                .zip(secret_keys)
+                .map(|(name, secret_key)| {
+                    let keyring = Keyring::new(secret_key, committee.clone());
+                    (name.clone(), keyring.sign(b"value"))
+                })
+                .collect(),
+        );
+        assert!(committee.verify_combined(b"value", &signature));
+        assert!(committee.verify_partial(&names[0], b"value", &signature[&names[0]]));
+        assert!(!committee.verify_partial(&names[1], b"value", &signature[&names[0]]));
+        assert!(!committee.is_member(&String::from("carol")));
+    }
+}
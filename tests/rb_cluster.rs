@@ -0,0 +1,527 @@
+use libp2p::Multiaddr;
+use pb_rust::networktest::aba_protocol::Decision;
+use pb_rust::networktest::libp2p_pb::ExportedCertificate;
+use pb_rust::networktest::libp2p_rb::{self, Options, ProtocolKind, TransportKind};
+use pb_rust::networktest::rb_control;
+use pb_rust::networktest::rb_protocol::Delivery;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// integration tests for `libp2p_rb`: whole clusters of RB nodes, running inside the test process.
+//
+// every node is the real driver (`libp2p_rb::run`, with its own protocol actor), on its own thread
+// and tokio runtime, talking to the others over the in-process memory transport.
+// the tests only go through the crate's public API: they start nodes with `libp2p_rb::run`,
+// and drive them through the control API, like `rbctl` would.
+//
+// membership is static: node `i` always has the keypair derived from its seed, so we know every
+// peer id up front, and a node restarted with the same seed is the same member.
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// memory ports, key seeds and socket names must be unique across the whole test process,
+// since the tests run in parallel (and the lean protocol's global state is keyed by peer id).
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+struct Node {
+    protocol: ProtocolKind,
+    seed: u64,
+    peer_id: String,
+    address: Multiaddr,
+    socket: PathBuf,
+    // where PB's certificates go
+    exports: PathBuf,
+    thread: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl Node {
+    fn options(&self, peers: Vec<Multiaddr>) -> Options {
+        Options {
+            protocol: self.protocol,
+            daemon: Some(self.socket.clone()),
+            transport: TransportKind::Memory,
+            listen: vec![self.address.clone()],
+            peers,
+            mdns: false,
+            key_seed: Some(self.seed),
+            export: (self.protocol == ProtocolKind::Pb).then(|| self.exports.clone()),
+            ..Options::default()
+        }
+    }
+
+    // runs the node until it is shut down through the control API.
+    fn start(&mut self, peers: Vec<Multiaddr>) {
+        let options = self.options(peers);
+        self.thread = Some(std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+            runtime
+                .block_on(libp2p_rb::run(options))
+                .map_err(|e| e.to_string())
+        }));
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        rb_control::call(&self.socket, method, params)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    // the control socket only shows up once the node is running, so keep trying until it does.
+    async fn call_eventually(&self, method: &str, params: Value) -> Value {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            match self.call(method, params.clone()).await {
+                Ok(result) => return result,
+                Err(e) if Instant::now() > deadline => {
+                    panic!("{method} on node {} kept failing: {e}", self.peer_id)
+                }
+                Err(_) => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) {
+        self.call_eventually("shutdown", json!({})).await;
+        let thread = self.thread.take().expect("node should be running");
+        let result = tokio::task::spawn_blocking(move || thread.join())
+            .await
+            .expect("joining a node shouldn't fail")
+            .expect("node thread shouldn't panic");
+        assert_eq!(result, Ok(()), "node {} failed", self.peer_id);
+    }
+
+    async fn connected_peers(&self) -> u64 {
+        let status = self.call_eventually("status", json!({})).await;
+        status["connected_peers"].as_u64().unwrap_or(0)
+    }
+
+    // the protocol's outputs, whatever they are.
+    async fn outputs<T: serde::de::DeserializeOwned>(&self) -> Vec<T> {
+        let result = self
+            .call_eventually("deliveries", json!({ "since": 0 }))
+            .await;
+        result["deliveries"]
+            .as_array()
+            .expect("deliveries should be a list")
+            .iter()
+            .map(|delivery| {
+                serde_json::from_value(delivery.clone()).expect("deliveries should deserialize")
+            })
+            .collect()
+    }
+
+    async fn deliveries(&self) -> Vec<(String, usize, String)> {
+        let deliveries: Vec<Delivery> = self.outputs().await;
+        deliveries
+            .into_iter()
+            .map(|d| (d.leader, d.round, d.value))
+            .collect()
+    }
+}
+
+struct Cluster {
+    nodes: Vec<Node>,
+}
+
+impl Cluster {
+    async fn start(n: usize) -> Self {
+        Cluster::start_with(n, ProtocolKind::Rb).await
+    }
+
+    // starts `n` nodes. node `i` dials every node before it, which makes a full mesh.
+    async fn start_with(n: usize, protocol: ProtocolKind) -> Self {
+        let nodes: Vec<Node> = (0..n)
+            .map(|_| {
+                let id = next_id();
+                let seed = std::process::id() as u64 * 1_000_000 + id;
+                let peer_id = libp2p_rb::peer_id_for_seed(seed).to_string();
+                Node {
+                    protocol,
+                    seed,
+                    peer_id,
+                    address: format!("/memory/{seed}").parse().unwrap(),
+                    socket: std::env::temp_dir().join(format!("pb-rust-test-{seed}.sock")),
+                    exports: std::env::temp_dir().join(format!("pb-rust-test-{seed}-exports")),
+                    thread: None,
+                }
+            })
+            .collect();
+
+        // a node is listening by the time its control socket is up,
+        // so waiting for it means the next node's dials don't go nowhere.
+        let mut cluster = Cluster { nodes };
+        for i in 0..n {
+            let peers = cluster.addresses_before(i);
+            cluster.nodes[i].start(peers);
+            cluster.nodes[i].call_eventually("status", json!({})).await;
+        }
+        cluster.wait_for_mesh().await;
+        cluster
+    }
+
+    fn addresses_before(&self, i: usize) -> Vec<Multiaddr> {
+        self.nodes[..i]
+            .iter()
+            .map(|node| node.address.clone())
+            .collect()
+    }
+
+    fn members(&self) -> Vec<String> {
+        self.nodes.iter().map(|node| node.peer_id.clone()).collect()
+    }
+
+    async fn wait_for_mesh(&self) {
+        let others = self.nodes.len() as u64 - 1;
+        let deadline = Instant::now() + TIMEOUT;
+        for node in self.nodes.iter() {
+            while node.connected_peers().await < others {
+                assert!(
+                    Instant::now() < deadline,
+                    "node {} never connected to everyone",
+                    node.peer_id
+                );
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+
+    async fn init(&self, node: usize, leader: usize) {
+        let params = json!({
+            "leader": self.nodes[leader].peer_id,
+            "members": self.members(),
+        });
+        self.nodes[node]
+            .call("init", params)
+            .await
+            .expect("init should succeed");
+    }
+
+    async fn init_all(&self, leader: usize) {
+        for node in 0..self.nodes.len() {
+            self.init(node, leader).await;
+        }
+    }
+
+    async fn broadcast(&self, leader: usize, value: &str) {
+        self.nodes[leader]
+            .call("broadcast", json!({ "value": value }))
+            .await
+            .expect("broadcast should succeed");
+    }
+
+    // waits until every node in `nodes` has delivered `value` in `round`,
+    // and checks that nobody delivered anything else in that round.
+    async fn assert_delivered(&self, nodes: &[usize], leader: usize, round: usize, value: &str) {
+        let expected = (self.nodes[leader].peer_id.clone(), round, value.to_string());
+        let deadline = Instant::now() + TIMEOUT;
+        let mut waiting: HashSet<usize> = nodes.iter().copied().collect();
+
+        while !waiting.is_empty() {
+            for &node in nodes {
+                let deliveries = self.nodes[node].deliveries().await;
+                let in_round: Vec<_> = deliveries.iter().filter(|d| d.1 == round).collect();
+                assert!(
+                    in_round.iter().all(|d| **d == expected),
+                    "node {node} delivered {in_round:?} instead of {expected:?}"
+                );
+                if !in_round.is_empty() {
+                    waiting.remove(&node);
+                }
+            }
+            if waiting.is_empty() {
+                break;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "nodes {waiting:?} never delivered '{value}' in round {round}"
+            );
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    fn all(&self) -> Vec<usize> {
+        (0..self.nodes.len()).collect()
+    }
+
+    async fn shutdown(mut self) {
+        for node in self.nodes.iter_mut() {
+            if node.thread.is_some() {
+                node.shutdown().await;
+            }
+            let _ = std::fs::remove_dir_all(&node.exports);
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn every_node_delivers_from_each_leader() {
+    // a fresh cluster per leader, since a node only ever follows one leader.
+    for leader in 0..4 {
+        let cluster = Cluster::start(4).await;
+        cluster.init_all(leader).await;
+
+        for round in 0..2 {
+            let value = format!("from {leader} in round {round}");
+            cluster.broadcast(leader, &value).await;
+            cluster
+                .assert_delivered(&cluster.all(), leader, round, &value)
+                .await;
+        }
+        cluster.shutdown().await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn honest_nodes_deliver_while_one_node_is_down() {
+    let mut cluster = Cluster::start(4).await;
+    cluster.init_all(0).await;
+
+    // with n = 4, the protocol tolerates 1 faulty node, so the other 3 can go on without node 3.
+    cluster.nodes[3].shutdown().await;
+    cluster.broadcast(0, "without node 3").await;
+    cluster
+        .assert_delivered(&[0, 1, 2], 0, 0, "without node 3")
+        .await;
+
+    cluster.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn late_member_gets_the_packets_it_refused() {
+    let cluster = Cluster::start(4).await;
+    for node in 0..3 {
+        cluster.init(node, 0).await;
+    }
+
+    // node 3 refuses everything until it's initialized, so the others hold on to its packets.
+    cluster.broadcast(0, "before node 3").await;
+    cluster
+        .assert_delivered(&[0, 1, 2], 0, 0, "before node 3")
+        .await;
+
+    cluster.init(3, 0).await;
+    cluster
+        .assert_delivered(&cluster.all(), 0, 0, "before node 3")
+        .await;
+
+    cluster.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restarted_node_rejoins() {
+    let mut cluster = Cluster::start(4).await;
+    cluster.init_all(0).await;
+
+    cluster.broadcast(0, "before the restart").await;
+    cluster
+        .assert_delivered(&cluster.all(), 0, 0, "before the restart")
+        .await;
+
+    // same seed, same address: the same member comes back, with a fresh protocol state.
+    cluster.nodes[3].shutdown().await;
+    let peers = cluster.addresses_before(3);
+    cluster.nodes[3].start(peers);
+    cluster.nodes[3].call_eventually("status", json!({})).await;
+    cluster.wait_for_mesh().await;
+    cluster.init(3, 0).await;
+
+    cluster.broadcast(0, "after the restart").await;
+    cluster
+        .assert_delivered(&cluster.all(), 0, 1, "after the restart")
+        .await;
+
+    cluster.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropped_connections_are_redialed() {
+    let cluster = Cluster::start(4).await;
+    cluster.init_all(0).await;
+
+    // the leader drops its connection to node 2 right before broadcasting.
+    // whatever doesn't make it to node 2 is held, and sent once node 2 redials the leader.
+    cluster.nodes[0]
+        .call("disconnect", json!({ "peer": cluster.nodes[2].peer_id }))
+        .await
+        .expect("the leader should be connected to node 2");
+    cluster.broadcast(0, "through a dropped connection").await;
+    cluster
+        .assert_delivered(&cluster.all(), 0, 0, "through a dropped connection")
+        .await;
+
+    // and the network is whole again afterwards.
+    cluster.wait_for_mesh().await;
+    cluster.broadcast(0, "after reconnecting").await;
+    cluster
+        .assert_delivered(&cluster.all(), 0, 1, "after reconnecting")
+        .await;
+
+    cluster.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partitioned_node_catches_up_after_healing() {
+    let cluster = Cluster::start(4).await;
+    cluster.init_all(0).await;
+
+    // cut node 3 off from everyone else, in both directions.
+    let others: Vec<String> = cluster.members()[..3].to_vec();
+    cluster.nodes[3]
+        .call("partition", json!({ "peers": others }))
+        .await
+        .expect("partition should succeed");
+    for node in 0..3 {
+        let params = json!({ "peers": [cluster.nodes[3].peer_id] });
+        cluster.nodes[node]
+            .call("partition", params)
+            .await
+            .expect("partition should succeed");
+    }
+
+    // the majority side goes on without node 3...
+    cluster.broadcast(0, "during the partition").await;
+    cluster
+        .assert_delivered(&[0, 1, 2], 0, 0, "during the partition")
+        .await;
+    assert!(cluster.nodes[3].deliveries().await.is_empty());
+
+    // ...and node 3 gets everything it missed once the partition heals.
+    for node in cluster.nodes.iter() {
+        node.call("heal", json!({}))
+            .await
+            .expect("heal should succeed");
+    }
+    cluster
+        .assert_delivered(&cluster.all(), 0, 0, "during the partition")
+        .await;
+
+    cluster.shutdown().await;
+}
+
+// the driver isn't specific to RB: the native PB runs on it just the same.
+#[tokio::test(flavor = "multi_thread")]
+async fn provable_broadcast_runs_on_the_same_driver() {
+    let cluster = Cluster::start_with(4, ProtocolKind::Pb).await;
+    // PB has no leader, so the one we pass in doesn't matter.
+    cluster.init_all(0).await;
+
+    // a stale certificate from some earlier run doesn't keep the new one from being exported.
+    let path = cluster.nodes[1]
+        .exports
+        .join(format!("certificate-{}-0.json", cluster.nodes[1].peer_id));
+    std::fs::write(&path, "stale").unwrap();
+    cluster.broadcast(1, "certified").await;
+
+    let deadline = Instant::now() + TIMEOUT;
+    let certificate = loop {
+        let certificates: Vec<ExportedCertificate> = cluster.nodes[1].outputs().await;
+        if let Some(certificate) = certificates.into_iter().next() {
+            break certificate;
+        }
+        assert!(Instant::now() < deadline, "node 1 never got a certificate");
+        tokio::time::sleep(POLL_INTERVAL).await;
+    };
+    assert_eq!(certificate.sender, cluster.nodes[1].peer_id);
+    assert_eq!(
+        (certificate.round, certificate.value.as_str()),
+        (0, "certified")
+    );
+    let members: Vec<String> = cluster
+        .nodes
+        .iter()
+        .map(|node| node.peer_id.clone())
+        .collect();
+    assert!(certificate.verify(&members));
+
+    // the sender also exports it, and it checks out without the node.
+    while ExportedCertificate::load(&path).ok().as_ref() != Some(&certificate) {
+        assert!(
+            Instant::now() < deadline,
+            "node 1 never exported its certificate"
+        );
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    // a certificate for some other value doesn't check out, and neither does one with
+    // fewer signatures than the members call for, whatever threshold it claims.
+    let mut forged = certificate.clone();
+    forged.value = String::from("goodbye");
+    assert!(!forged.verify(&members));
+    let mut forged = certificate.clone();
+    let signer = forged.signatures.keys().next().unwrap().clone();
+    forged.signatures = BTreeMap::from([(signer.clone(), forged.signatures[&signer].clone())]);
+    forged.threshold = 1;
+    assert!(!forged.verify(&members));
+    // nor does one that names its own members: on its own, the sender's echo would be enough.
+    let mut forged = certificate.clone();
+    let sender = forged.sender.clone();
+    forged.members = vec![sender.clone()];
+    forged.signatures = BTreeMap::from([(sender.clone(), forged.signatures[&sender].clone())]);
+    forged.threshold = 1;
+    assert!(!forged.verify(&members));
+
+    // only the sender outputs anything.
+    for node in [0, 2, 3] {
+        let certificates: Vec<ExportedCertificate> = cluster.nodes[node].outputs().await;
+        assert!(certificates.is_empty());
+    }
+
+    cluster.shutdown().await;
+}
+
+// CB is RB without the vote phase, so it delivers the same way, with at most one round of echoes.
+#[cfg(feature = "lean")]
+#[tokio::test(flavor = "multi_thread")]
+async fn consistent_broadcast_runs_on_the_same_driver() {
+    let cluster = Cluster::start_with(4, ProtocolKind::Cb).await;
+    cluster.init_all(0).await;
+    cluster.broadcast(0, "consistent").await;
+    cluster
+        .assert_delivered(&cluster.all(), 0, 0, "consistent")
+        .await;
+
+    // the leader sends its initial message and its echo to the 3 others, everyone else only echoes.
+    for (i, node) in cluster.nodes.iter().enumerate() {
+        let status = node.call_eventually("status", json!({})).await;
+        let most = if i == 0 { 6 } else { 3 };
+        assert!(status["packets_sent"].as_u64().unwrap() <= most);
+    }
+
+    cluster.shutdown().await;
+}
+
+// ABA has no leader: every node proposes, and they all decide the same bit.
+#[tokio::test(flavor = "multi_thread")]
+async fn binary_agreement_runs_on_the_same_driver() {
+    let cluster = Cluster::start_with(4, ProtocolKind::Aba).await;
+    cluster.init_all(0).await;
+    for (node, bit) in ["1", "0", "1", "0"].into_iter().enumerate() {
+        cluster.broadcast(node, bit).await;
+    }
+
+    let deadline = Instant::now() + TIMEOUT;
+    let mut decisions = Vec::new();
+    for node in cluster.all() {
+        let decision = loop {
+            let decisions: Vec<Decision> = cluster.nodes[node].outputs().await;
+            if let Some(decision) = decisions.into_iter().next() {
+                break decision;
+            }
+            assert!(Instant::now() < deadline, "node {node} never decided");
+            tokio::time::sleep(POLL_INTERVAL).await;
+        };
+        decisions.push(decision.value);
+    }
+    assert!(decisions.iter().all(|value| *value == decisions[0]));
+
+    cluster.shutdown().await;
+}